@@ -0,0 +1,58 @@
+/* Copyright 2017 Mitchell Kember. Subject to the MIT License. */
+
+/*
+ * C interface to WOWCPE. Build the crate with `--features ffi` and link against
+ * the resulting `libwowcpe` shared library.
+ */
+
+#ifndef WOWCPE_H
+#define WOWCPE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+enum {
+	WOWCPE_OK = 0,
+	WOWCPE_INVALID_ARGUMENT = 1,
+	WOWCPE_CURL = 2,
+	WOWCPE_NO_DATA = 3,
+	WOWCPE_NO_ENTRY = 4,
+	WOWCPE_BAD_UTF8 = 5,
+	WOWCPE_BAD_SCRAPE = 6,
+	WOWCPE_BAD_TIME = 7,
+	WOWCPE_HTTP_STATUS = 8,
+	WOWCPE_BODY_TOO_LARGE = 9,
+	WOWCPE_CANCELLED = 10,
+};
+
+/*
+ * Information about a piece. Times are Unix timestamps in seconds. All strings
+ * except program are NULL if the playlist does not list them.
+ */
+typedef struct {
+	char *program;
+	int64_t start_time;
+	int64_t end_time;
+	char *composer;
+	char *title;
+	char *performers;
+	char *record_label;
+} WowcpeResponse;
+
+/* Looks up what is playing at `time`. Returns WOWCPE_OK on success. */
+int wowcpe_lookup(int64_t time, WowcpeResponse *out);
+
+/* Frees the strings in a response filled in by wowcpe_lookup. */
+void wowcpe_response_free(WowcpeResponse *response);
+
+/* Returns a static description of a status code. */
+const char *wowcpe_strerror(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,359 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Caching parsed playlists on disk as JSON, compressed with zstd if the
+//! `zstd` feature is enabled.
+
+use {
+    crate::{
+        get_program, Entry, Playlist, RawFields, Request, Response, Result,
+        StationTime, Warning,
+    },
+    chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc},
+    directories::ProjectDirs,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+    tracing::{debug, warn},
+};
+
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Sets the directory returned by `cache_dir` for the rest of the process.
+/// Passing `None` restores the platform default.
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CACHE_DIR.lock().unwrap() = dir;
+}
+
+/// Returns the directory for cache files.
+///
+/// This is the directory set by `set_cache_dir` if there is one, and otherwise
+/// the platform's cache directory: `~/.cache/wowcpe` on Linux (respecting
+/// `XDG_CACHE_HOME`), `~/Library/Caches/wowcpe` on macOS, and
+/// `%LOCALAPPDATA%\wowcpe\cache` on Windows. Returns `None` if there is no
+/// override and the home directory cannot be determined.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = CACHE_DIR.lock().unwrap().clone() {
+        return Some(dir);
+    }
+    ProjectDirs::from("", "", "wowcpe").map(|dirs| dirs.cache_dir().into())
+}
+
+/// Returns the path of the default cache file for `lookup_cached` inside
+/// `cache_dir`, creating the directory if it does not exist.
+pub fn default_cache_file() -> Option<PathBuf> {
+    cache_file_in(&cache_dir()?)
+}
+
+/// Returns the path of the cache file in `dir`, creating the directory if it
+/// does not exist.
+fn cache_file_in(dir: &Path) -> Option<PathBuf> {
+    if let Err(err) = fs::create_dir_all(dir) {
+        warn!(path = %dir.display(), %err, "failed to create cache directory");
+        return None;
+    }
+    Some(dir.join("playlist.json"))
+}
+
+/// The version of the cache format. Bump this whenever the format changes, so
+/// that old cache files are treated as misses rather than misread.
+const VERSION: u32 = 6;
+
+/// The first four bytes of a zstd frame. Cache files starting with these are
+/// compressed, and all others are plain JSON, so that files written with and
+/// without the `zstd` feature can be read either way.
+pub(crate) const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    url: String,
+    date: NaiveDate,
+    fetched_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    entries: Vec<CachedEntry>,
+    warnings: Vec<Warning>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+}
+
+/// An `Entry` without the index and program, which are recomputed when
+/// reading.
+#[derive(Serialize, Deserialize)]
+struct CachedEntry {
+    hour: Option<String>,
+    start_time: DateTime<FixedOffset>,
+    end_time: DateTime<FixedOffset>,
+    composer: Option<String>,
+    title: Option<String>,
+    performers: Option<String>,
+    record_label: Option<String>,
+    raw: RawFields,
+}
+
+/// Reads the playlist for `url` from the cache file at `path`. Returns `None`
+/// if the file is missing, unreadable, in an old format, or for another URL.
+/// Compressed files are decompressed transparently.
+pub(crate) fn read(path: &Path, url: &str) -> Option<Playlist> {
+    let json = match decode(fs::read(path).ok()?) {
+        Ok(json) => json,
+        Err(err) => {
+            debug!(%err, "unreadable cache file");
+            return None;
+        }
+    };
+    let file: CacheFile = match serde_json::from_slice(&json) {
+        Ok(file) => file,
+        Err(err) => {
+            debug!(%err, "invalid cache file");
+            return None;
+        }
+    };
+    if file.version != VERSION || file.url != url {
+        return None;
+    }
+    let entries = file
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| Entry {
+            index,
+            hour: entry.hour,
+            program: get_program(entry.start_time),
+            start_time: entry.start_time.into(),
+            end_time: entry.end_time.into(),
+            composer: entry.composer,
+            title: entry.title,
+            performers: entry.performers,
+            record_label: entry.record_label,
+            raw: entry.raw,
+        })
+        .collect();
+    let playlist = Playlist::new(file.date, entries, file.warnings);
+    let playlist = playlist.with_expiry(file.expires_at);
+    Some(match file.fetched_at {
+        Some(fetched_at) => playlist.with_source(file.url, fetched_at),
+        None => playlist,
+    })
+}
+
+/// Writes `playlist` for `url` to the cache file at `path`, logging any error.
+/// If `html` is given, it is stored alongside the entries for debugging. With
+/// the `zstd` feature, the file is compressed.
+pub(crate) fn write(
+    path: &Path,
+    url: &str,
+    playlist: &Playlist,
+    html: Option<&str>,
+) {
+    if let Err(err) = try_write(path, url, playlist, html) {
+        warn!(path = %path.display(), %err, "failed to write cache");
+    }
+}
+
+fn try_write(
+    path: &Path,
+    url: &str,
+    playlist: &Playlist,
+    html: Option<&str>,
+) -> io::Result<()> {
+    let file = CacheFile {
+        version: VERSION,
+        url: url.to_string(),
+        date: playlist.date(),
+        fetched_at: playlist.fetched_at(),
+        expires_at: playlist.expires_at(),
+        entries: playlist
+            .entries()
+            .iter()
+            .map(|entry| CachedEntry {
+                hour: entry.hour.clone(),
+                start_time: entry.start_time.fixed_offset(),
+                end_time: entry.end_time.fixed_offset(),
+                composer: entry.composer.clone(),
+                title: entry.title.clone(),
+                performers: entry.performers.clone(),
+                record_label: entry.record_label.clone(),
+                raw: entry.raw.clone(),
+            })
+            .collect(),
+        warnings: playlist.warnings().to_vec(),
+        html: html.map(str::to_string),
+    };
+    fs::write(path, encode(serde_json::to_vec(&file)?)?)
+}
+
+/// Compresses the JSON for a cache file if the `zstd` feature is enabled.
+fn encode(json: Vec<u8>) -> io::Result<Vec<u8>> {
+    #[cfg(feature = "zstd")]
+    return crate::zstd::compress(&json);
+    #[cfg(not(feature = "zstd"))]
+    Ok(json)
+}
+
+/// Returns the JSON in the contents of a cache file, decompressing it if it
+/// starts with `ZSTD_MAGIC`.
+fn decode(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !bytes.starts_with(&ZSTD_MAGIC) {
+        return Ok(bytes);
+    }
+    #[cfg(feature = "zstd")]
+    return crate::zstd::decompress(&bytes);
+    #[cfg(not(feature = "zstd"))]
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "compressed, but built without the zstd feature",
+    ))
+}
+
+/// Looks up `request` in `playlist`, or returns `None` if the answer could be
+/// out of date and the page should be downloaded again.
+///
+/// Pages for past days never change. Today's page is fresh for as long as its
+/// `Cache-Control` or `Expires` headers say. If it had no such headers, only
+/// the latest entry is considered out of date, since its end time is not known
+/// until the next piece starts.
+pub(crate) fn lookup_if_fresh<Tz: TimeZone>(
+    playlist: &Playlist,
+    request: &Request<Tz>,
+    now: DateTime<Utc>,
+) -> Option<Result<Response<Tz>>> {
+    let result = playlist.lookup(request);
+    if is_fresh(playlist, now) {
+        return Some(result);
+    }
+    if playlist.expires_at().is_some() {
+        return None;
+    }
+    let last = playlist.entries().last()?;
+    match &result {
+        Ok(response) if response.start_time != last.start_time => Some(result),
+        _ => None,
+    }
+}
+
+/// Returns true if the whole of a cached `playlist` is still up to date at
+/// `now`: it is for a past day, or its headers say it has not expired yet.
+pub(crate) fn is_fresh(playlist: &Playlist, now: DateTime<Utc>) -> bool {
+    playlist.date() < StationTime::from(now).date()
+        || playlist
+            .expires_at()
+            .is_some_and(|expires_at| now < expires_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::HTML, crate::Eastern};
+
+    const URL: &str = "https://example.com/?date=2020-09-01";
+
+    fn playlist() -> Playlist {
+        let fetched_at = Utc.ymd(2020, 9, 1).and_hms(14, 0, 0);
+        Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1))
+            .unwrap()
+            .with_source(URL.to_string(), fetched_at)
+    }
+
+    /// Held by tests that set or read the process-wide `CACHE_DIR`, since tests
+    /// run in parallel.
+    pub(crate) static CACHE_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_cache_dir_override() {
+        let _lock = CACHE_DIR_LOCK.lock().unwrap();
+        let dir = std::env::temp_dir().join("wowcpe-test-cache-dir");
+        set_cache_dir(Some(dir.clone()));
+        let overridden = cache_dir();
+        set_cache_dir(None);
+
+        assert_eq!(Some(dir.clone()), overridden);
+        assert_ne!(Some(dir), cache_dir());
+    }
+
+    #[test]
+    fn test_cache_file_in() {
+        let dir = std::env::temp_dir().join("wowcpe-test-cache-file-in");
+        let file = cache_file_in(&dir);
+        let exists = dir.is_dir();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(Some(dir.join("playlist.json")), file);
+        assert!(exists);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join("wowcpe-test-cache.json");
+        write(&path, URL, &playlist(), Some(HTML));
+        let cached = read(&path, URL);
+        let other_url = read(&path, "https://example.com/?date=2020-09-02");
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some(playlist()), cached);
+        assert_eq!(None, other_url);
+    }
+
+    #[test]
+    fn test_compression() {
+        let path = std::env::temp_dir().join("wowcpe-test-cache-zstd.json");
+        write(&path, URL, &playlist(), Some(HTML));
+        let bytes = fs::read(&path).unwrap();
+        let compressed = bytes.starts_with(&ZSTD_MAGIC);
+        // Plain JSON is still read when compression is enabled.
+        fs::write(&path, decode(bytes).unwrap()).unwrap();
+        let plain = read(&path, URL);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(cfg!(feature = "zstd"), compressed);
+        assert_eq!(Some(playlist()), plain);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let path = std::env::temp_dir().join("wowcpe-test-cache-invalid.json");
+        fs::write(&path, format!("<!-- {} -->\n{}", URL, HTML)).unwrap();
+        let old_html = read(&path, URL);
+        fs::write(&path, r#"{"version":0}"#).unwrap();
+        let old_version = read(&path, URL);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(None, old_html);
+        assert_eq!(None, old_version);
+        assert_eq!(None, read(&path, URL));
+    }
+
+    #[test]
+    fn test_lookup_if_fresh() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let playlist = Playlist::parse(crate::tests::HTML, date).unwrap();
+        let now = Eastern
+            .ymd(2020, 9, 1)
+            .and_hms(10, 0, 0)
+            .with_timezone(&Utc);
+        let first = Request {
+            time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+        };
+        let latest = Request {
+            time: Eastern.ymd(2020, 9, 1).and_hms(8, 0, 0),
+        };
+        let fresh = |playlist: &Playlist, request, now| {
+            lookup_if_fresh(playlist, request, now).is_some()
+        };
+
+        // Past days never change.
+        let tomorrow = now + chrono::Duration::days(1);
+        assert!(fresh(&playlist, &latest, tomorrow));
+
+        // Without caching headers, only the latest entry is stale.
+        assert!(fresh(&playlist, &first, now));
+        assert!(!fresh(&playlist, &latest, now));
+
+        // With them, the whole page is fresh until it expires.
+        let minute = chrono::Duration::minutes(1);
+        let playlist = playlist.with_expiry(Some(now + minute));
+        assert!(fresh(&playlist, &latest, now));
+        assert!(!fresh(&playlist, &first, now + minute));
+    }
+}
@@ -0,0 +1,678 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! A reusable client for looking up what is playing on WCPE.
+
+use {
+    crate::{
+        cache::{self, lookup_if_fresh},
+        decode_html, eastern_noon, has_playlist,
+        memory::MemoryCache,
+        playlist_url, rate_limit, snapshot, validate_request, CacheHeaders,
+        CancellationToken, Entry, Error, NowPlayingSource, Playlist, Request,
+        Response, Result, StationTime,
+    },
+    chrono::{DateTime, Local, NaiveDate, TimeZone, Utc},
+    curl::easy::Easy,
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, MutexGuard},
+    },
+    tracing::{debug, debug_span},
+};
+
+/// A client for looking up what is playing on WCPE.
+///
+/// The client keeps `curl` handles alive between lookups, so applications that
+/// poll regularly reuse the same connection instead of doing a new TLS
+/// handshake every time. The free functions like `lookup` create a fresh client
+/// for each call.
+///
+/// The client is `Send + Sync`, and cloning it is cheap: clones share the same
+/// connections, settings, and in-memory cache. A multithreaded service can
+/// create one client and hand a clone to each request handler. Concurrent
+/// lookups each use their own connection.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // Idle handles. A lookup takes one (or creates one if there are none) and
+    // puts it back when done, so concurrent lookups never share a handle.
+    handles: Mutex<Vec<Easy>>,
+    settings: Mutex<Settings>,
+    memory: Mutex<MemoryCache>,
+}
+
+/// Text that only appears in playlist pages with at least one entry. It
+/// follows the opening tag that `has_playlist` looks for.
+const PLAYLIST_MARKER: &[u8] = b"playlist-song__time";
+
+/// A downloaded page.
+#[derive(Debug)]
+pub(crate) struct Page {
+    /// The body, decoded according to its charset.
+    pub(crate) body: String,
+    /// When the page stops being fresh according to its caching headers, or
+    /// `None` if they don't say.
+    pub(crate) expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+struct Settings {
+    debug_snapshots: Option<PathBuf>,
+    max_redirects: u32,
+    max_body_size: usize,
+    cache_html: bool,
+    #[cfg(feature = "test-util")]
+    fixtures: Option<crate::Fixtures>,
+}
+
+impl Client {
+    /// Creates a new client.
+    pub fn new() -> Self {
+        Client {
+            inner: Arc::new(Inner {
+                handles: Mutex::new(Vec::new()),
+                settings: Mutex::new(Settings {
+                    debug_snapshots: None,
+                    max_redirects: 5,
+                    max_body_size: 10 * 1024 * 1024,
+                    cache_html: false,
+                    #[cfg(feature = "test-util")]
+                    fixtures: None,
+                }),
+                memory: Mutex::new(MemoryCache::new(8)),
+            }),
+        }
+    }
+
+    /// Sets the maximum number of redirects to follow, so that lookups keep
+    /// working if the station's website moves pages around. Passing 0 disables
+    /// following redirects. The default is 5.
+    ///
+    /// Like all settings, this applies to every clone of the client.
+    pub fn set_max_redirects(&self, max_redirects: u32) {
+        self.settings().max_redirects = max_redirects;
+    }
+
+    /// Sets the maximum size of a downloaded page in bytes. Downloads that
+    /// exceed it are aborted with `Error::BodyTooLarge`, which guards against
+    /// misbehaving servers and captive portals. The default is 10 MiB.
+    pub fn set_max_body_size(&self, max_body_size: usize) {
+        self.settings().max_body_size = max_body_size;
+    }
+
+    /// Sets a directory in which to save a snapshot of the HTML whenever
+    /// scraping it fails. Passing `None` (the default) disables snapshots.
+    ///
+    /// Each snapshot is a timestamped file containing the page's URL in a
+    /// comment followed by the HTML. Attach it when reporting a bug so that it
+    /// can be added to the test fixtures.
+    pub fn set_debug_snapshots(&self, dir: Option<PathBuf>) {
+        self.settings().debug_snapshots = dir;
+    }
+
+    /// Sets whether `lookup_cached` stores the raw HTML in the cache file
+    /// alongside the parsed entries, which is useful for debugging. The default
+    /// is false.
+    pub fn set_cache_html(&self, cache_html: bool) {
+        self.settings().cache_html = cache_html;
+    }
+
+    /// Sets how many days of playlists to keep in memory. The default is 8, and
+    /// 0 disables the in-memory cache.
+    ///
+    /// Lookups answered from memory do no I/O at all, which suits applications
+    /// that call `lookup` every few seconds. Lookups that land on the latest
+    /// entry of today's playlist always go to the network, since its end time
+    /// is not known until the next piece starts.
+    pub fn set_memory_cache_capacity(&self, capacity: usize) {
+        self.memory().set_capacity(capacity);
+    }
+
+    /// Sets fixtures to record downloads to or replay them from, so that tests
+    /// don't depend on network access. Passing `None` (the default) goes back
+    /// to downloading normally. Only available with the `test-util` feature.
+    ///
+    /// Replayed downloads skip the rate limit. The in-memory cache still
+    /// applies, so clear it with `set_memory_cache_capacity(0)` if a test
+    /// needs every lookup to reach the fixtures.
+    #[cfg(feature = "test-util")]
+    pub fn set_fixtures(&self, fixtures: Option<crate::Fixtures>) {
+        self.settings().fixtures = fixtures;
+    }
+
+    fn settings(&self) -> MutexGuard<'_, Settings> {
+        self.inner.settings.lock().unwrap()
+    }
+
+    fn memory(&self) -> MutexGuard<'_, MemoryCache> {
+        self.inner.memory.lock().unwrap()
+    }
+
+    /// Looks up what is playing on WCPE. See `wowcpe::lookup`.
+    pub fn lookup<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Response<Tz>> {
+        self.lookup_cancellable(request, &CancellationToken::new())
+    }
+
+    /// Like `lookup`, but returns `Error::Cancelled` as soon as possible once
+    /// `token` is cancelled, aborting the download if one is in progress.
+    pub fn lookup_cancellable<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+        token: &CancellationToken,
+    ) -> Result<Response<Tz>> {
+        validate_request(request, Local::now())?;
+        if let Some(response) = self.lookup_in_memory(request) {
+            return Ok(response);
+        }
+        let url = playlist_url(request.time.clone());
+        let date = request.station_time().date();
+        let stale = self.memory().get(date).cloned();
+        let page = self.download(&url, token)?;
+        let playlist = self.parse(&url, &page, date, stale)?;
+        self.remember(playlist, request)
+    }
+
+    /// Looks up what is playing on WCPE, caching the page in `cache_file`. See
+    /// `wowcpe::lookup_cached`.
+    pub fn lookup_cached<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+        cache_file: &Path,
+    ) -> Result<Response<Tz>> {
+        validate_request(request, Local::now())?;
+        if let Some(response) = self.lookup_in_memory(request) {
+            return Ok(response);
+        }
+        let url = playlist_url(request.time.clone());
+        let date = request.station_time().date();
+        let mut stale = self.memory().get(date).cloned();
+        if let Some(playlist) = cache::read(cache_file, &url) {
+            if let Some(result) =
+                lookup_if_fresh(&playlist, request, Utc::now())
+            {
+                debug!(path = %cache_file.display(), "cache hit");
+                self.memory().insert(playlist);
+                return result;
+            }
+            debug!(path = %cache_file.display(), "cache stale");
+            stale = stale.or(Some(playlist));
+        } else {
+            debug!(path = %cache_file.display(), "cache miss");
+        }
+
+        let page = self.download(&url, &CancellationToken::new())?;
+        let playlist = self.parse(&url, &page, date, stale)?;
+        let cache_html = self.settings().cache_html;
+        let cached_html = if cache_html { Some(&*page.body) } else { None };
+        cache::write(cache_file, &url, &playlist, cached_html);
+        self.remember(playlist, request)
+    }
+
+    /// Looks up the whole playlist for the day containing `request.time`,
+    /// using the in-memory copy if the whole day is fresh. See
+    /// `wowcpe::lookup_playlist`.
+    pub fn lookup_playlist<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Playlist> {
+        validate_request(request, Local::now())?;
+        let url = playlist_url(request.time.clone());
+        let date = request.station_time().date();
+        let stale = match self.memory().get(date).cloned() {
+            Some(playlist) if cache::is_fresh(&playlist, Utc::now()) => {
+                debug!(%date, "memory cache hit");
+                return Ok(playlist);
+            }
+            stale => stale,
+        };
+        let page = self.download(&url, &CancellationToken::new())?;
+        let playlist = self.parse(&url, &page, date, stale)?;
+        self.memory().insert(playlist.clone());
+        Ok(playlist)
+    }
+
+    /// Looks up the whole playlist for the day containing `request.time`,
+    /// caching the page in `cache_file`. The cached copy is used only if the
+    /// whole day is fresh, not just the entry at `request.time`.
+    pub fn lookup_playlist_cached<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+        cache_file: &Path,
+    ) -> Result<Playlist> {
+        validate_request(request, Local::now())?;
+        let url = playlist_url(request.time.clone());
+        let date = request.station_time().date();
+        let mut stale = self.memory().get(date).cloned();
+        if let Some(playlist) = cache::read(cache_file, &url) {
+            if cache::is_fresh(&playlist, Utc::now()) {
+                debug!(path = %cache_file.display(), "cache hit");
+                self.memory().insert(playlist.clone());
+                return Ok(playlist);
+            }
+            debug!(path = %cache_file.display(), "cache stale");
+            stale = stale.or(Some(playlist));
+        } else {
+            debug!(path = %cache_file.display(), "cache miss");
+        }
+
+        let page = self.download(&url, &CancellationToken::new())?;
+        let playlist = self.parse(&url, &page, date, stale)?;
+        let cache_html = self.settings().cache_html;
+        let cached_html = if cache_html { Some(&*page.body) } else { None };
+        cache::write(cache_file, &url, &playlist, cached_html);
+        self.memory().insert(playlist.clone());
+        Ok(playlist)
+    }
+
+    /// Looks up what is playing for each of `requests`, downloading each day's
+    /// page only once. See `wowcpe::lookup_many`.
+    pub fn lookup_many<Tz: TimeZone>(
+        &self,
+        requests: &[Request<Tz>],
+    ) -> Vec<Result<Response<Tz>>> {
+        let mut playlists = HashMap::new();
+        requests
+            .iter()
+            .map(|request| {
+                validate_request(request, Local::now())?;
+                let date = request.station_time().date();
+                let playlist = playlists
+                    .entry(date)
+                    .or_insert_with(|| self.lookup_playlist(request));
+                match playlist {
+                    Ok(playlist) => playlist.lookup(request),
+                    Err(err) => Err(copy_error(err)),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the `n` entries played before the one playing at
+    /// `request.time`, most recent first. See `wowcpe::lookup_previous`.
+    pub fn lookup_previous<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+        n: usize,
+    ) -> Result<Vec<Entry>> {
+        let time = request.station_time();
+        let today = self.lookup_playlist(request)?;
+        let mut previous: Vec<Entry> =
+            today.before(time).iter().rev().take(n).cloned().collect();
+        if previous.len() < n {
+            let date = time.date() - chrono::Duration::days(1);
+            // Before today's first entry, yesterday's last one is still
+            // playing, so it isn't one of the previous pieces.
+            let playing = today
+                .entries()
+                .first()
+                .is_none_or(|first| time < first.start_time);
+            match self.lookup_playlist(&Request::whole_day(date)) {
+                Ok(yesterday) => {
+                    let rest = n - previous.len();
+                    let entries = yesterday.entries().iter().rev();
+                    let entries = entries.skip(playing as usize).take(rest);
+                    previous.extend(entries.cloned());
+                }
+                // The archive doesn't go back any further.
+                Err(Error::NoData) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(previous)
+    }
+
+    /// Returns true if the station has a playlist for `date`. See
+    /// `wowcpe::is_available`.
+    pub fn is_available(&self, date: NaiveDate) -> Result<bool> {
+        let url = playlist_url(eastern_noon(date)?);
+        let token = CancellationToken::new();
+        match self.download_until(&url, &token, Some(PLAYLIST_MARKER)) {
+            Ok(page) => Ok(has_playlist(&page.body)),
+            Err(Error::HttpStatus(404, _)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Answers `request` from the in-memory cache. Returns `None` if the
+    /// playlist is not cached or the answer could be out of date (see
+    /// `lookup_if_fresh`).
+    fn lookup_in_memory<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Option<Response<Tz>> {
+        let date = request.station_time().date();
+        let mut memory = self.memory();
+        let playlist = memory.get(date)?;
+        let response = lookup_if_fresh(playlist, request, Utc::now())?.ok()?;
+        debug!(%date, "memory cache hit");
+        Some(response)
+    }
+
+    /// Looks up `request` in `playlist` and keeps the playlist in memory.
+    fn remember<Tz: TimeZone>(
+        &self,
+        playlist: Playlist,
+        request: &Request<Tz>,
+    ) -> Result<Response<Tz>> {
+        let response = playlist.lookup(request);
+        self.memory().insert(playlist);
+        response
+    }
+
+    /// Parses the playlist for `date` from `page`. If there is a `stale` copy
+    /// of it, only the entries added since then are parsed (see
+    /// `Playlist::refresh`).
+    fn parse(
+        &self,
+        url: &str,
+        page: &Page,
+        date: NaiveDate,
+        stale: Option<Playlist>,
+    ) -> Result<Playlist> {
+        let fetched_at = Utc::now();
+        let result = match stale {
+            Some(mut playlist) => playlist.refresh(&page.body).map(|added| {
+                debug!(added, "refreshed playlist");
+                playlist
+            }),
+            None => Playlist::parse(&page.body, date),
+        };
+        let result = result.map(|playlist| {
+            playlist
+                .with_source(url.to_string(), fetched_at)
+                .with_expiry(page.expires_at)
+        });
+        match (&result, &self.settings().debug_snapshots) {
+            (Err(Error::BadScrape), Some(dir))
+            | (Err(Error::BadTime), Some(dir)) => {
+                snapshot::save(dir, url, &page.body)
+            }
+            _ => (),
+        }
+        result
+    }
+
+    /// Downloads `url`, subject to the global rate limit. Returns an error if
+    /// the response status is not 2xx. Decodes the body according to its
+    /// charset, replacing invalid bytes rather than failing. Returns
+    /// `Error::Cancelled` if `token` is cancelled before or during the download.
+    pub(crate) fn download(
+        &self,
+        url: &str,
+        token: &CancellationToken,
+    ) -> Result<Page> {
+        self.download_until(url, token, None)
+    }
+
+    /// Like `download`, but stops reading the body as soon as it contains
+    /// `marker`, returning what was read so far.
+    fn download_until(
+        &self,
+        url: &str,
+        token: &CancellationToken,
+        marker: Option<&[u8]>,
+    ) -> Result<Page> {
+        let _span = debug_span!("download", url).entered();
+        debug!("downloading");
+        #[cfg(feature = "test-util")]
+        {
+            let fixtures = self.settings().fixtures.clone();
+            if let Some(fixtures) = fixtures {
+                // Record whole pages, so that lookups can replay them too.
+                return fixtures.download(url, || self.fetch(url, token, None));
+            }
+        }
+        self.fetch(url, token, marker)
+    }
+
+    /// Downloads `url` over the network, for `download_until`.
+    fn fetch(
+        &self,
+        url: &str,
+        token: &CancellationToken,
+        marker: Option<&[u8]>,
+    ) -> Result<Page> {
+        rate_limit::wait(token)?;
+        let settings = self.settings().clone();
+        let idle = self.inner.handles.lock().unwrap().pop();
+        let mut handle = idle.unwrap_or_else(Easy::new);
+        let result = perform(&mut handle, url, &settings, token, marker);
+        self.inner.handles.lock().unwrap().push(handle);
+        result
+    }
+}
+
+/// Downloads `url` using `handle`, for `Client::download_until`.
+fn perform(
+    handle: &mut Easy,
+    url: &str,
+    settings: &Settings,
+    token: &CancellationToken,
+    marker: Option<&[u8]>,
+) -> Result<Page> {
+    let mut body = Vec::new();
+    let mut found = false;
+    let mut status_line = None;
+    let mut headers = CacheHeaders::default();
+    let mut too_large = false;
+    handle.url(url)?;
+    handle.follow_location(settings.max_redirects > 0)?;
+    handle.max_redirections(settings.max_redirects)?;
+    handle.progress(true)?;
+    let performed = {
+        let mut transfer = handle.transfer();
+        transfer.header_function(|header| {
+            if let Some(line) = parse_status_line(header) {
+                // A new response, e.g. after a redirect.
+                status_line = Some(line);
+                headers = CacheHeaders::default();
+            } else {
+                headers.add(header);
+            }
+            true
+        })?;
+        transfer.write_function(|data| {
+            if body.len() + data.len() > settings.max_body_size {
+                // Writing fewer bytes than given aborts the transfer.
+                too_large = true;
+                return Ok(0);
+            }
+            let searched =
+                body.len().saturating_sub(marker.map_or(0, |m| m.len()));
+            body.extend_from_slice(data);
+            if let Some(marker) = marker {
+                if body[searched..].windows(marker.len()).any(|w| w == marker) {
+                    found = true;
+                    return Ok(0);
+                }
+            }
+            Ok(data.len())
+        })?;
+        // Returning false from the progress callback aborts the transfer.
+        // Curl calls it at least once per second, even when no data arrives.
+        transfer.progress_function(|_, _, _, _| !token.is_cancelled())?;
+        transfer.perform()
+    };
+    if let Err(err) =
+        performed.or_else(|err| if found { Ok(()) } else { Err(err) })
+    {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        if too_large {
+            return Err(Error::BodyTooLarge(settings.max_body_size));
+        }
+        return Err(err.into());
+    }
+    let code = handle.response_code()?;
+    let ms = handle.total_time()?.as_millis() as u64;
+    debug!(code, bytes = body.len(), ms, "downloaded");
+    if !(200..300).contains(&code) {
+        let line = status_line.unwrap_or_else(|| code.to_string());
+        return Err(Error::HttpStatus(code, line));
+    }
+
+    Ok(Page {
+        body: decode_html(&body, handle.content_type()?),
+        expires_at: headers.expires_at(Utc::now()),
+    })
+}
+
+/// Returns a copy of `err`, for reporting one failed download to each of the
+/// requests that needed it. A transport error is copied as its message.
+fn copy_error(err: &Error) -> Error {
+    match err {
+        Error::Curl(err) => Error::Curl(err.clone()),
+        Error::Cancelled => Error::Cancelled,
+        Error::NotCached(date) => Error::NotCached(*date),
+        Error::HttpStatus(code, line) => Error::HttpStatus(*code, line.clone()),
+        Error::Transport(err) => Error::Transport(err.to_string().into()),
+        Error::BodyTooLarge(limit) => Error::BodyTooLarge(*limit),
+        Error::NoData => Error::NoData,
+        Error::NoEntry => Error::NoEntry,
+        Error::BadUtf8 => Error::BadUtf8,
+        Error::BadScrape => Error::BadScrape,
+        Error::BadTime => Error::BadTime,
+    }
+}
+
+/// Returns the status line if `header` is one, e.g., "HTTP/1.1 404 Not Found".
+fn parse_status_line(header: &[u8]) -> Option<String> {
+    let line = std::str::from_utf8(header).ok()?.trim();
+    if line.starts_with("HTTP/") {
+        Some(line.to_string())
+    } else {
+        None
+    }
+}
+
+impl NowPlayingSource for Client {
+    fn current(&self, time: StationTime) -> Result<Response> {
+        self.lookup(&Request {
+            time: time.to_local(),
+        })
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, assert_matches::assert_matches};
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(
+            Some("HTTP/1.1 404 Not Found".to_string()),
+            parse_status_line(b"HTTP/1.1 404 Not Found\r\n")
+        );
+        assert_eq!(
+            Some("HTTP/2 200".to_string()),
+            parse_status_line(b"HTTP/2 200\r\n")
+        );
+        assert_eq!(None, parse_status_line(b"Content-Type: text/html\r\n"));
+        assert_eq!(None, parse_status_line(b"\r\n"));
+    }
+
+    #[test]
+    fn test_client_is_shareable() {
+        fn assert_shareable<T: Send + Sync + Clone>() {}
+        assert_shareable::<Client>();
+    }
+
+    #[test]
+    fn test_lookup_playlist_in_memory() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        // Downloading would give a playlist with entries.
+        let playlist = Playlist::new(date, Vec::new(), Vec::new());
+        let client = Client::new();
+        client.memory().insert(playlist.clone());
+        let result = client.lookup_playlist(&Request::whole_day(date));
+        assert_eq!(playlist, result.unwrap());
+    }
+
+    #[test]
+    fn test_lookup_previous_after_midnight() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let today = Playlist::parse(crate::tests::HTML, date).unwrap();
+        // Yesterday's playlist is the same as today's, a day earlier.
+        let entries = today.entries().iter().cloned().map(|mut entry| {
+            entry.start_time = entry.start_time - chrono::Duration::days(1);
+            entry.end_time = entry.end_time - chrono::Duration::days(1);
+            entry
+        });
+        let yesterday = date.pred_opt().unwrap();
+        let yesterday = Playlist::new(yesterday, entries.collect(), Vec::new());
+        let client = Client::new();
+        client.memory().insert(today);
+        client.memory().insert(yesterday.clone());
+
+        // Before today's first entry, yesterday's last one is playing.
+        let time = crate::Eastern.ymd(2020, 9, 1).and_hms(0, 0, 30);
+        let previous = client.lookup_previous(&Request { time }, 1).unwrap();
+        let entries = yesterday.entries();
+        assert_eq!(vec![entries[entries.len() - 2].clone()], previous);
+    }
+
+    #[test]
+    fn test_download_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = Client::new().download("https://example.com", &token);
+        assert_matches!(result, Err(Error::Cancelled));
+    }
+
+    #[test]
+    fn test_download_until() {
+        let mut html =
+            b"<article class=\"block--playlist\"><div class=\"playlist-song\">"
+                .to_vec();
+        html.extend_from_slice(PLAYLIST_MARKER);
+        html.extend_from_slice(&[b'x'; 1_000_000]);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let response = html.clone();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                response.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&response);
+        });
+        let token = CancellationToken::new();
+        let page = Client::new()
+            .download_until(&url, &token, Some(PLAYLIST_MARKER))
+            .unwrap();
+
+        assert!(page.body.len() < html.len());
+        assert!(has_playlist(&page.body));
+    }
+
+    #[test]
+    fn test_download_too_large() {
+        let path = std::env::temp_dir().join("wowcpe-test-too-large.html");
+        std::fs::write(&path, [b'x'; 100]).unwrap();
+        let client = Client::new();
+        client.set_max_body_size(10);
+        let url = format!("file://{}", path.display());
+        let result = client.download(&url, &CancellationToken::new());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_matches!(result, Err(Error::BodyTooLarge(10)));
+    }
+}
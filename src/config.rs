@@ -0,0 +1,523 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Defaults for the command-line tool, read from a config file.
+
+use {
+    crate::{Cache, LastFm, ListenBrainz, Mailer, Program, Scrobbler, Webhook},
+    directories::ProjectDirs,
+    serde::Deserialize,
+    std::{
+        env, fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The environment variable that overrides the path of the config file.
+pub const CONFIG_ENV: &str = "WOWCPE_CONFIG";
+
+/// The most minutes ahead a reminder can be, which is one week.
+const MAX_REMIND_MINUTES: u64 = 7 * 24 * 60;
+
+/// Defaults for the command-line tool, so that long-running modes like
+/// `--watch` and `notify` don't need long command lines. Flags override them.
+///
+/// ```toml
+/// format = "{composer} – {title}"
+/// timezone = "America/Vancouver"
+/// favorite_composers = ["Sibelius", "Bach"]
+/// favorite_works = ["Dvořák New World", "Goldberg Variations"]
+///
+/// [cache]
+/// max_entries = 30
+///
+/// [notify]
+/// favorites_only = true
+/// remind_minutes = 10
+///
+/// [[reminders]]
+/// program = "Thursday Night Opera House"
+/// minutes = 30
+///
+/// [play]
+/// player = "vlc --intf dummy"
+///
+/// [email]
+/// server = "smtps://smtp.example.com"
+/// username = "me@example.com"
+/// password = "…"
+/// from = "me@example.com"
+/// to = ["me@example.com"]
+///
+/// [lastfm]
+/// api_key = "…"
+/// api_secret = "…"
+/// session_key = "…"
+///
+/// [listenbrainz]
+/// token = "…"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How to print lookups: "text", "json", "csv", "waybar", "oneline",
+    /// "xbar", or a template for `--format`.
+    pub format: Option<String>,
+    /// When to color output: "auto", "always", or "never".
+    pub color: Option<String>,
+    /// The time zone for showing times, e.g., "America/Vancouver", instead of
+    /// the system's.
+    pub timezone: Option<String>,
+    /// Whether to show relative times like "ends in 8 minutes" in text output,
+    /// as with `--relative`.
+    pub relative: bool,
+    /// Composers to call out, matched case-insensitively against part of the
+    /// composer field, e.g., "Bach" matches "Johann Sebastian Bach".
+    pub favorite_composers: Vec<String>,
+    /// Works to call out, like "Sibelius violin concerto". Each word must
+    /// appear in the composer or title, ignoring case.
+    pub favorite_works: Vec<String>,
+    /// The cache policy.
+    pub cache: CacheConfig,
+    /// Settings for desktop notifications.
+    pub notify: NotifyConfig,
+    /// Settings for playing the live stream.
+    pub play: PlayConfig,
+    /// Settings for `--oneline`.
+    pub oneline: OnelineConfig,
+    /// The broker for `mqtt`.
+    pub mqtt: MqttConfig,
+    /// The URLs for `webhook`, each in a `[[webhooks]]` section.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Programs to remind about before they start, each in a `[[reminders]]`
+    /// section.
+    pub reminders: Vec<ReminderConfig>,
+    /// The Last.fm account for `scrobble`.
+    pub lastfm: LastFmConfig,
+    /// The ListenBrainz account for `scrobble`.
+    pub listenbrainz: ListenBrainzConfig,
+    /// The SMTP server and recipients for `digest`.
+    pub email: EmailConfig,
+}
+
+/// The `[cache]` section of a `Config`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Whether to cache playlists at all. The default is true.
+    pub enabled: bool,
+    /// The cache directory, like `--cache-dir`.
+    pub dir: Option<PathBuf>,
+    /// The maximum number of days to keep, or 0 for no limit. The default is
+    /// the same as `Cache::open_default`.
+    pub max_entries: Option<usize>,
+    /// The maximum size of the cache in bytes, or 0 for no limit.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            dir: None,
+            max_entries: None,
+            max_bytes: None,
+        }
+    }
+}
+
+/// The `[notify]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Only notify for pieces by `favorite_composers` or in `favorite_works`.
+    pub favorites_only: bool,
+    /// How many minutes before a favorite piece starts to notify about it, if
+    /// the playlist lists it ahead of time. The default is 5, 0 turns
+    /// reminders off, and the most is a week.
+    pub remind_minutes: Option<u64>,
+}
+
+/// The `[oneline]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OnelineConfig {
+    /// The most characters to print, like `--max-width`.
+    pub max_width: Option<usize>,
+    /// What to end shortened lines with, like `--ellipsis`.
+    pub ellipsis: Option<String>,
+}
+
+impl OnelineConfig {
+    /// The smallest `max_width`, leaving room for a character and an ellipsis.
+    pub const MIN_WIDTH: usize = 2;
+}
+
+/// The `[mqtt]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// The broker's address, like `--broker`.
+    pub broker: Option<String>,
+    /// The topic to publish to, like `--topic`.
+    pub topic: Option<String>,
+    /// The client identifier. The default is "wowcpe".
+    pub client_id: Option<String>,
+    /// The user name, if the broker requires one.
+    pub username: Option<String>,
+    /// The password for `username`.
+    pub password: Option<String>,
+    /// Connect over TLS, like `--tls`. The password is sent in the clear
+    /// without it.
+    pub tls: bool,
+}
+
+/// A `[[webhooks]]` section of a `Config`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// The URL to post to.
+    pub url: String,
+    /// The secret for signing requests, if the receiver checks them.
+    pub secret: Option<String>,
+}
+
+impl WebhookConfig {
+    /// Returns the webhook.
+    pub fn webhook(&self) -> Webhook {
+        let webhook = Webhook::new(&self.url);
+        match &self.secret {
+            Some(secret) => webhook.with_secret(secret),
+            None => webhook,
+        }
+    }
+}
+
+/// A `[[reminders]]` section of a `Config`. Reminders are delivered by
+/// `notify`, `mqtt`, and `webhook`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReminderConfig {
+    /// The program's name, e.g., "Thursday Night Opera House".
+    pub program: String,
+    /// How many minutes before the program starts to remind. The default is
+    /// 30, and the most is a week.
+    pub minutes: Option<u64>,
+}
+
+impl ReminderConfig {
+    /// Returns the program, or `None` if the name isn't one of the station's
+    /// programs.
+    pub fn program(&self) -> Option<Program> {
+        match Program::from_name(&self.program) {
+            Program::Special(_) => None,
+            program => Some(program),
+        }
+    }
+}
+
+/// The `[play]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayConfig {
+    /// The command to play a stream URL with, like `--player`. The URL is
+    /// added as the last argument.
+    pub player: Option<String>,
+    /// The id of the stream to play, like `--stream`.
+    pub stream: Option<String>,
+}
+
+/// The `[lastfm]` section of a `Config`. See `LastFm` for how to get a session
+/// key.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LastFmConfig {
+    /// The API account's key.
+    pub api_key: Option<String>,
+    /// The API account's shared secret.
+    pub api_secret: Option<String>,
+    /// The user's session key.
+    pub session_key: Option<String>,
+}
+
+impl LastFmConfig {
+    /// Returns the scrobbler, or `None` unless all the fields are set.
+    pub fn scrobbler(&self) -> Option<LastFm> {
+        match (&self.api_key, &self.api_secret, &self.session_key) {
+            (Some(key), Some(secret), Some(session)) => {
+                Some(LastFm::new(key, secret, session))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The `[listenbrainz]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenBrainzConfig {
+    /// The user token from https://listenbrainz.org/settings/.
+    pub token: Option<String>,
+}
+
+/// The `[email]` section of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    /// The SMTP server's URL, e.g., "smtps://smtp.example.com". See `Mailer`.
+    pub server: Option<String>,
+    /// The user name, if the server requires one.
+    pub username: Option<String>,
+    /// The password for `username`.
+    pub password: Option<String>,
+    /// The address to send from.
+    pub from: Option<String>,
+    /// The addresses to send to, like `--to`.
+    pub to: Vec<String>,
+    /// Send without TLS if an "smtp://" server doesn't offer STARTTLS, which
+    /// exposes the password. See `Mailer::with_plaintext`.
+    pub allow_plaintext: bool,
+}
+
+impl EmailConfig {
+    /// Returns the mailer, or `None` unless the server and sender are set.
+    pub fn mailer(&self) -> Option<Mailer> {
+        let mailer = Mailer::new(self.server.as_ref()?, self.from.as_ref()?)
+            .with_plaintext(self.allow_plaintext);
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => {
+                Some(mailer.with_credentials(username, password))
+            }
+            _ => Some(mailer),
+        }
+    }
+}
+
+/// Returns the path of the config file: the value of `WOWCPE_CONFIG` if it is
+/// set, and otherwise `config.toml` in the platform's config directory, e.g.,
+/// `~/.config/wowcpe/config.toml` on Linux.
+pub fn config_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(path.into());
+    }
+    ProjectDirs::from("", "", "wowcpe")
+        .map(|dirs| dirs.config_dir().join("config.toml"))
+}
+
+impl Config {
+    /// Reads the config from `config_file`. Returns the default config if
+    /// there is no such file.
+    pub fn load() -> io::Result<Self> {
+        match config_file() {
+            Some(path) => Config::read(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Reads the config from `path`. Returns the default config if the file
+    /// does not exist.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn parse(text: &str) -> io::Result<Self> {
+        let config: Config = toml::from_str(text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks values that parse but make no sense.
+    fn validate(&self) -> io::Result<()> {
+        let invalid =
+            |msg: String| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        if let Some(width) = self.oneline.max_width {
+            if width < OnelineConfig::MIN_WIDTH {
+                return invalid(format!(
+                    "oneline.max_width must be at least {}",
+                    OnelineConfig::MIN_WIDTH
+                ));
+            }
+        }
+        if self.notify.remind_minutes > Some(MAX_REMIND_MINUTES) {
+            return invalid(format!(
+                "notify.remind_minutes must be at most {}",
+                MAX_REMIND_MINUTES
+            ));
+        }
+        for reminder in &self.reminders {
+            if reminder.minutes > Some(MAX_REMIND_MINUTES) {
+                return invalid(format!(
+                    "{}: reminders.minutes must be at most {}",
+                    reminder.program, MAX_REMIND_MINUTES
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the multi-day cache with the configured limits, or `None` if
+    /// there is no cache directory. This ignores `cache.enabled`.
+    pub fn cache(&self) -> Option<Cache> {
+        let mut cache = Cache::open_default()?;
+        if let Some(max) = self.cache.max_entries {
+            cache = cache.with_max_entries(Some(max).filter(|&max| max > 0));
+        }
+        if let Some(max) = self.cache.max_bytes {
+            cache = cache.with_max_bytes(Some(max).filter(|&max| max > 0));
+        }
+        Some(cache)
+    }
+
+    /// Returns the scrobblers for each service with an account configured.
+    pub fn scrobblers(&self) -> Vec<Box<dyn Scrobbler>> {
+        let mut scrobblers: Vec<Box<dyn Scrobbler>> = Vec::new();
+        if let Some(lastfm) = self.lastfm.scrobbler() {
+            scrobblers.push(Box::new(lastfm));
+        }
+        if let Some(token) = &self.listenbrainz.token {
+            scrobblers.push(Box::new(ListenBrainz::new(token)));
+        }
+        scrobblers
+    }
+
+    /// Returns true if `composer` is one of `favorite_composers`.
+    pub fn is_favorite(&self, composer: &str) -> bool {
+        let composer = composer.to_lowercase();
+        self.favorite_composers
+            .iter()
+            .any(|favorite| composer.contains(&favorite.to_lowercase()))
+    }
+
+    /// Returns true if the piece is by one of `favorite_composers` or matches
+    /// one of `favorite_works`.
+    pub fn is_favorite_piece(&self, composer: &str, title: &str) -> bool {
+        let piece = format!("{} {}", composer, title).to_lowercase();
+        self.is_favorite(composer)
+            || self.favorite_works.iter().any(|work| {
+                let work = work.to_lowercase();
+                let mut words = work.split_whitespace().peekable();
+                words.peek().is_some() && words.all(|word| piece.contains(word))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+            format = "json"
+            favorite_composers = ["Bach"]
+            favorite_works = ["sibelius VIOLIN concerto"]
+
+            [cache]
+            enabled = false
+            max_entries = 0
+
+            [notify]
+            favorites_only = true
+            remind_minutes = 10
+
+            [listenbrainz]
+            token = "abc"
+
+            [email]
+            server = "smtp://localhost"
+            from = "me@example.com"
+            to = ["a@example.com", "b@example.com"]
+
+            [[reminders]]
+            program = "Thursday Night Opera House"
+
+            [[reminders]]
+            program = "Opera Night"
+            minutes = 10
+
+            [[webhooks]]
+            url = "http://localhost/a"
+
+            [[webhooks]]
+            url = "http://localhost/b"
+            secret = "key"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Some("json"), config.format.as_deref());
+        assert!(!config.cache.enabled);
+        assert_eq!(Some(0), config.cache.max_entries);
+        assert!(config.notify.favorites_only);
+        assert_eq!(Some(10), config.notify.remind_minutes);
+        assert!(config.lastfm.scrobbler().is_none());
+        assert_eq!(1, config.scrobblers().len());
+        assert!(config.email.mailer().is_some());
+        assert_eq!(2, config.email.to.len());
+        assert_eq!(
+            Some(Program::ThursdayNightOperaHouse),
+            config.reminders[0].program()
+        );
+        assert_eq!(None, config.reminders[1].program());
+        assert_eq!(Some(10), config.reminders[1].minutes);
+        assert_eq!(2, config.webhooks.len());
+        assert_eq!(Some("key"), config.webhooks[1].secret.as_deref());
+        assert!(config.is_favorite("Johann Sebastian BACH"));
+        assert!(!config.is_favorite("Franz Liszt"));
+        assert!(config.is_favorite_piece(
+            "Jean Sibelius",
+            "Violin Concerto in D minor, Op. 47"
+        ));
+        assert!(config.is_favorite_piece("J. S. Bach", "Cello Suite No. 1"));
+        assert!(!config.is_favorite_piece("Jean Sibelius", "Finlandia"));
+    }
+
+    #[test]
+    fn test_parse_default() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(Config::default(), config);
+        assert!(config.email.mailer().is_none());
+        assert!(config.cache.enabled);
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        let err = Config::parse("colour = \"never\"").unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_parse_narrow_oneline() {
+        let err = Config::parse("[oneline]\nmax_width = 1").unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_parse_remind_too_far() {
+        let err = Config::parse("[notify]\nremind_minutes = 99999999999999")
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_parse_reminder_too_far() {
+        let err = Config::parse(
+            "[[reminders]]\nprogram = \"Peaceful Reflections\"\n\
+             minutes = 10081",
+        )
+        .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_read_missing() {
+        let path = env::temp_dir().join("wowcpe-test-no-config.toml");
+        assert_eq!(Config::default(), Config::read(&path).unwrap());
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! A long-running server that answers lookups over a Unix domain socket.
+
+use {
+    crate::{Client, Error, Playlist, Request, Response, Result, StationTime},
+    chrono::{FixedOffset, Local, NaiveDate},
+    serde_json::json,
+    std::{
+        fs,
+        io::{self, BufRead, BufReader, Read, Write},
+        os::unix::{
+            fs::FileTypeExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    },
+    tracing::{debug, warn},
+};
+
+/// The longest request line the daemon reads, in bytes. Requests are at most a
+/// timestamp, so anything longer is rejected.
+const MAX_REQUEST_LEN: u64 = 256;
+
+/// How long the daemon waits on a client to send its request or read the reply.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server that keeps today's playlist parsed in memory and answers lookups
+/// over a Unix domain socket.
+///
+/// Status bar widgets that refresh every few seconds can share one daemon
+/// instead of each downloading the playlist. The daemon refreshes the playlist
+/// on a schedule, parsing only the entries added since the last refresh, so
+/// lookups for today never wait on the network.
+///
+/// The protocol is one request per connection. The client writes a line
+/// containing an RFC 3339 timestamp or a YYYY-MM-DD date (see `Request`'s
+/// `FromStr` implementation), or an empty line for the current time. The daemon
+/// replies with a line of JSON and closes the connection. The JSON has the
+/// fields of `Response` on success, and a single `error` field on failure.
+///
+/// ```sh
+/// echo | nc -U ~/.cache/wowcpe/daemon.sock
+/// ```
+#[derive(Clone)]
+pub struct Daemon {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: Client,
+    interval: Mutex<Duration>,
+    today: Mutex<Option<Playlist>>,
+}
+
+impl Daemon {
+    /// Creates a daemon that looks up playlists with `client`.
+    pub fn new(client: Client) -> Self {
+        Daemon {
+            inner: Arc::new(Inner {
+                client,
+                interval: Mutex::new(Duration::from_secs(60)),
+                today: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Sets how often to refresh today's playlist. The default is one minute.
+    pub fn set_refresh_interval(&self, interval: Duration) {
+        *self.inner.interval.lock().unwrap() = interval;
+    }
+
+    /// Downloads today's playlist, replacing the one in memory.
+    pub fn refresh(&self) -> Result<()> {
+        let date = StationTime::now().date();
+        let playlist = self
+            .inner
+            .client
+            .lookup_playlist(&Request::whole_day(date))?;
+        debug!(%date, entries = playlist.entries().len(), "refreshed daemon");
+        *self.inner.today.lock().unwrap() = Some(playlist);
+        Ok(())
+    }
+
+    /// Looks up what is playing on WCPE. Answers from memory if `request` is
+    /// for the day of the last refresh, and otherwise uses the client.
+    pub fn lookup(&self, request: &Request) -> Result<Response> {
+        let date = request.station_time().date();
+        if let Some(playlist) = self.today(date) {
+            match playlist.lookup(request) {
+                // The entry could still be added by the next refresh.
+                Err(Error::NoEntry) => {}
+                result => return result,
+            }
+        }
+        self.inner.client.lookup(request)
+    }
+
+    fn today(&self, date: NaiveDate) -> Option<Playlist> {
+        let today = self.inner.today.lock().unwrap();
+        today.as_ref().filter(|p| p.date() == date).cloned()
+    }
+
+    /// Listens on the Unix domain socket at `path` and serves lookups until an
+    /// I/O error occurs. Replaces a stale socket left at `path` by a daemon
+    /// that exited, but fails if another daemon is listening there or if it is
+    /// not a socket. Refreshes today's playlist on a background thread.
+    pub fn serve(&self, path: &Path) -> io::Result<()> {
+        remove_stale_socket(path)?;
+        let listener = UnixListener::bind(path)?;
+        let daemon = self.clone();
+        thread::spawn(move || loop {
+            if let Err(err) = daemon.refresh() {
+                warn!(%err, "failed to refresh daemon");
+            }
+            let interval = *daemon.inner.interval.lock().unwrap();
+            thread::sleep(interval);
+        });
+        self.accept(listener)
+    }
+
+    /// Serves lookups from connections to `listener`.
+    fn accept(&self, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = self.clone();
+            thread::spawn(move || {
+                if let Err(err) = daemon.respond(stream) {
+                    warn!(%err, "failed to respond to daemon client");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn respond(&self, stream: UnixStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_REQUEST_LEN)).read_line(&mut line)?;
+        let reply = if line.len() as u64 == MAX_REQUEST_LEN {
+            json!({ "error": "Request line is too long" })
+        } else {
+            self.answer(line.trim())
+        };
+        let mut reply = reply.to_string();
+        reply.push('\n');
+        (&stream).write_all(reply.as_bytes())
+    }
+
+    /// Answers a request line from a client with JSON.
+    pub(crate) fn answer(&self, line: &str) -> serde_json::Value {
+        let request = if line.is_empty() {
+            Request { time: Local::now() }
+        } else {
+            match line.parse::<Request<FixedOffset>>() {
+                Ok(request) => Request {
+                    time: request.time.with_timezone(&Local),
+                },
+                Err(err) => return json!({ "error": err.to_string() }),
+            }
+        };
+        match self.lookup(&request) {
+            Ok(response) => json!(response),
+            Err(err) => json!({ "error": err.to_string() }),
+        }
+    }
+
+    #[cfg(test)]
+    fn set_today(&self, playlist: Playlist) {
+        *self.inner.today.lock().unwrap() = Some(playlist);
+    }
+}
+
+/// Removes the socket at `path` if a previous daemon left it behind. Fails
+/// rather than removing anything else: a file that is not a socket, or a socket
+/// that another daemon is still listening on.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another daemon is listening on {}", path.display()),
+        ));
+    }
+    fs::remove_file(path)
+}
+
+/// Sends a request line to the daemon listening at `path` and returns its
+/// reply, without the trailing newline. See `Daemon` for the protocol.
+pub fn query_daemon(path: &Path, line: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", line)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    reply.truncate(reply.trim_end().len());
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::HTML, crate::Eastern, chrono::TimeZone};
+
+    fn daemon() -> Daemon {
+        let daemon = Daemon::new(Client::new());
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        daemon.set_today(Playlist::parse(HTML, date).unwrap());
+        daemon
+    }
+
+    #[test]
+    fn test_answer() {
+        let reply = daemon().answer("2020-09-01T01:00:00-04:00");
+        assert_eq!("Sleepers, Awake!", reply["program"]);
+        assert_eq!("Franz Liszt", reply["composer"]);
+        let start = Eastern.ymd(2020, 9, 1).and_hms(0, 1, 0);
+        assert_eq!(json!(start.with_timezone(&Local)), reply["start_time"]);
+    }
+
+    #[test]
+    fn test_answer_invalid() {
+        let reply = daemon().answer("yesterday");
+        assert_eq!(
+            json!({ "error": "Expected an RFC 3339 timestamp or a YYYY-MM-DD date" }),
+            reply
+        );
+    }
+
+    #[test]
+    fn test_remove_stale_socket() {
+        let path = std::env::temp_dir().join("wowcpe-test-daemon-stale.sock");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let live = remove_stale_socket(&path);
+        drop(listener);
+        let stale = remove_stale_socket(&path);
+
+        assert_eq!(io::ErrorKind::AddrInUse, live.unwrap_err().kind());
+        assert!(stale.is_ok());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_serve_regular_file() {
+        let path = std::env::temp_dir().join("wowcpe-test-daemon-file.sock");
+        fs::write(&path, "keep me").unwrap();
+        let result = daemon().serve(&path);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(io::ErrorKind::AlreadyExists, result.unwrap_err().kind());
+        assert_eq!("keep me", contents);
+    }
+
+    #[test]
+    fn test_serve_long_line() {
+        let path = std::env::temp_dir().join("wowcpe-test-daemon-long.sock");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = daemon();
+        thread::spawn(move || server.accept(listener));
+
+        // Written in one go, since the daemon hangs up after the limit.
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(format!("{}\n", "x".repeat(300)).as_bytes())
+            .unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        assert_eq!("{\"error\":\"Request line is too long\"}\n", reply);
+    }
+
+    #[test]
+    fn test_serve() {
+        let path = std::env::temp_dir().join("wowcpe-test-daemon.sock");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let daemon = daemon();
+        let server = daemon.clone();
+        thread::spawn(move || server.accept(listener));
+
+        let line = "2020-09-01T01:00:00-04:00";
+        let reply = query_daemon(&path, line).unwrap();
+        assert_eq!(daemon.answer(line).to_string(), reply);
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Diagnosing why lookups fail, from the network to the parser to the cache.
+
+use {
+    crate::{
+        parse_playlist_with_strategy, playlist_url, Cache, CancellationToken,
+        Client, Playlist, StationTime, Strategy,
+    },
+    chrono::NaiveDate,
+    curl::{easy::Easy, Version},
+    std::{
+        fs, io,
+        net::{TcpStream, ToSocketAddrs},
+        time::{Duration, Instant},
+    },
+};
+
+/// The station's website, which has the playlists.
+const HOST: &str = "theclassicalstation.org";
+
+/// How long to wait for the website before giving up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a check turned out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CheckStatus {
+    /// Everything is fine.
+    Ok,
+    /// Lookups work, but something is off, e.g., the parser had to fall back
+    /// to a less reliable layout.
+    Warning,
+    /// Lookups fail because of this.
+    Failed,
+}
+
+/// The result of one check made by `diagnose`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Check {
+    /// What was checked, e.g., "TLS".
+    pub name: &'static str,
+    /// How it turned out.
+    pub status: CheckStatus,
+    /// What was found, e.g., "Connected with OpenSSL/3.0.13, and the
+    /// certificate is valid".
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: String) -> Self {
+        Check {
+            name,
+            status,
+            detail,
+        }
+    }
+}
+
+/// Checks everything lookups depend on, for when they stop working: the
+/// connection to the station's website, its TLS certificate, downloading
+/// today's playlist with `client`, parsing it, and `cache` if there is one.
+/// This needs network access and takes a few seconds.
+pub fn diagnose(client: &Client, cache: Option<&Cache>) -> Vec<Check> {
+    let mut checks = vec![check_connect(), check_tls()];
+    let date = StationTime::now().date();
+    let (download, html) = check_download(client, date);
+    checks.push(download);
+    if let Some(html) = html {
+        checks.push(check_parse(&html, date));
+    }
+    checks.push(match cache {
+        Some(cache) => check_cache(cache),
+        None => {
+            Check::new("Cache", CheckStatus::Warning, "Disabled".to_string())
+        }
+    });
+    checks
+}
+
+fn connect() -> io::Result<TcpStream> {
+    let addr = (HOST, 443)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("{} has no address", HOST)))?;
+    let tcp = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+    Ok(tcp)
+}
+
+fn check_connect() -> Check {
+    let start = Instant::now();
+    match connect().and_then(|tcp| tcp.peer_addr()) {
+        Ok(addr) => Check::new(
+            "Connection",
+            CheckStatus::Ok,
+            format!(
+                "Reached {} at {} in {} ms",
+                HOST,
+                addr,
+                start.elapsed().as_millis()
+            ),
+        ),
+        Err(err) => Check::new(
+            "Connection",
+            CheckStatus::Failed,
+            format!("Cannot reach {}: {}", HOST, err),
+        ),
+    }
+}
+
+/// Completes a TLS handshake with the website the way downloads do, verifying
+/// its certificate against the system's, and returns the TLS library used.
+fn handshake() -> io::Result<String> {
+    let mut easy = Easy::new();
+    easy.url(&format!("https://{}/", HOST))?;
+    easy.connect_only(true)?;
+    easy.connect_timeout(TIMEOUT)?;
+    easy.perform()?;
+    let version = Version::get();
+    Ok(format!(
+        "Connected with {}, and the certificate is valid",
+        version.ssl_version().unwrap_or("an unknown TLS library")
+    ))
+}
+
+fn check_tls() -> Check {
+    match handshake() {
+        Ok(detail) => Check::new("TLS", CheckStatus::Ok, detail),
+        Err(err) => Check::new(
+            "TLS",
+            CheckStatus::Failed,
+            format!("Cannot connect securely: {}", err),
+        ),
+    }
+}
+
+/// Downloads the playlist for `date`, returning the page if it worked.
+fn check_download(client: &Client, date: NaiveDate) -> (Check, Option<String>) {
+    let url = playlist_url(StationTime::now());
+    let start = Instant::now();
+    match client.download(&url, &CancellationToken::new()) {
+        Ok(page) => {
+            let detail = format!(
+                "Got {} bytes for {} in {} ms",
+                page.body.len(),
+                date,
+                start.elapsed().as_millis()
+            );
+            (
+                Check::new("Download", CheckStatus::Ok, detail),
+                Some(page.body),
+            )
+        }
+        Err(err) => {
+            let detail = format!("{}: {}", url, err);
+            (Check::new("Download", CheckStatus::Failed, detail), None)
+        }
+    }
+}
+
+/// Checks that the parser still understands `html`, the page for `date`.
+fn check_parse(html: &str, date: NaiveDate) -> Check {
+    let (entries, strategy) = match parse_playlist_with_strategy(html, date) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            let detail =
+                format!("{}; the website's layout may have changed", err);
+            return Check::new("Parser", CheckStatus::Failed, detail);
+        }
+    };
+    let skipped = Playlist::parse(html, date)
+        .map(|playlist| playlist.warnings().len())
+        .unwrap_or_default();
+    let mut detail = format!("Found {} pieces", entries.len());
+    if skipped > 0 {
+        detail.push_str(&format!(", skipping {}", skipped));
+    }
+    match strategy {
+        Strategy::PlaylistSong => {
+            detail.push_str(" in the current layout");
+            let status = match skipped {
+                0 => CheckStatus::Ok,
+                _ => CheckStatus::Warning,
+            };
+            Check::new("Parser", status, detail)
+        }
+        Strategy::Table | Strategy::Generic => {
+            detail.push_str(&format!(
+                " with the {:?} fallback, since the current layout didn't \
+                 match",
+                strategy
+            ));
+            Check::new("Parser", CheckStatus::Warning, detail)
+        }
+    }
+}
+
+/// Checks that the cache directory is writable and its files are readable.
+fn check_cache(cache: &Cache) -> Check {
+    let dir = cache.dir();
+    let probe = dir.join(".doctor");
+    let writable = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&probe, ""))
+        .and_then(|()| fs::remove_file(&probe));
+    if let Err(err) = writable {
+        let detail = format!("Cannot write to {}: {}", dir.display(), err);
+        return Check::new("Cache", CheckStatus::Failed, detail);
+    }
+    let dates = cache.dates();
+    let unreadable: Vec<String> = dates
+        .iter()
+        .filter(|date| cache.get(**date).is_none())
+        .map(|date| date.to_string())
+        .collect();
+    let detail = format!(
+        "{} days, {} KiB, in {}",
+        dates.len(),
+        cache.bytes() / 1024,
+        dir.display()
+    );
+    if unreadable.is_empty() {
+        return Check::new("Cache", CheckStatus::Ok, detail);
+    }
+    let detail = format!(
+        "{}; cannot read {} (run `wowcpe cache clear` to remove them)",
+        detail,
+        unreadable.join(", ")
+    );
+    Check::new("Cache", CheckStatus::Warning, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::HTML};
+
+    #[test]
+    fn test_check_parse() {
+        let check = check_parse(HTML, NaiveDate::from_ymd(2020, 9, 1));
+        assert_eq!(CheckStatus::Ok, check.status);
+        assert_eq!("Found 2 pieces in the current layout", check.detail);
+    }
+
+    #[test]
+    fn test_check_parse_failed() {
+        let html = "<html><body><p>Maintenance</p></body></html>";
+        let check = check_parse(html, NaiveDate::from_ymd(2020, 9, 1));
+        assert_eq!(CheckStatus::Failed, check.status);
+    }
+
+    #[test]
+    fn test_check_cache() {
+        let dir = std::env::temp_dir().join("wowcpe-test-doctor-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+        assert_eq!(CheckStatus::Ok, check_cache(&cache).status);
+
+        fs::write(dir.join("2020-09-01.json"), "{").unwrap();
+        let check = check_cache(&cache);
+        assert_eq!(CheckStatus::Warning, check.status);
+        assert!(check.detail.contains("cannot read 2020-09-01"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Detecting how far back the station's playlist archive goes.
+
+use {
+    crate::{Client, Error, Result, StationTime},
+    chrono::{Duration, NaiveDate},
+    std::sync::Mutex,
+};
+
+static PROBED: Mutex<Option<NaiveDate>> = Mutex::new(None);
+
+/// Returns the earliest date for which WCPE has a playlist, by probing the
+/// website with a binary search over dates.
+///
+/// This downloads around a dozen pages the first time it is called. The result
+/// is cached for the rest of the process, and `lookup` uses it instead of the
+/// built-in cutoff date from then on.
+pub fn earliest_available_date() -> Result<NaiveDate> {
+    if let Some(date) = *PROBED.lock().unwrap() {
+        return Ok(date);
+    }
+    // The website is assumed to have no data this far back.
+    let start = NaiveDate::from_ymd(2000, 1, 1);
+    let today = StationTime::now().date();
+    let client = Client::new();
+    let date = bisect(start, today, |date| client.is_available(date))?;
+    *PROBED.lock().unwrap() = Some(date);
+    Ok(date)
+}
+
+/// Returns the probed earliest date if available, and otherwise the date the
+/// website's archive started on when this was last checked.
+pub(crate) fn earliest_date() -> NaiveDate {
+    PROBED
+        .lock()
+        .unwrap()
+        .unwrap_or_else(|| NaiveDate::from_ymd(2019, 12, 19))
+}
+
+/// Finds the earliest date in `[lo, hi]` for which `has_data` returns true,
+/// assuming it returns false for all dates before that and true for all dates
+/// after it.
+fn bisect(
+    lo: NaiveDate,
+    hi: NaiveDate,
+    mut has_data: impl FnMut(NaiveDate) -> Result<bool>,
+) -> Result<NaiveDate> {
+    if !has_data(hi)? {
+        return Err(Error::NoData);
+    }
+    if has_data(lo)? {
+        return Ok(lo);
+    }
+    let (mut lo, mut hi) = (lo, hi);
+    while (hi - lo).num_days() > 1 {
+        let mid = lo + Duration::days((hi - lo).num_days() / 2);
+        if has_data(mid)? {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok(hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, assert_matches::assert_matches};
+
+    #[test]
+    fn test_bisect() {
+        let earliest = NaiveDate::from_ymd(2019, 12, 19);
+        let lo = NaiveDate::from_ymd(2000, 1, 1);
+        let hi = NaiveDate::from_ymd(2020, 9, 4);
+        let mut probes = 0;
+        let result = bisect(lo, hi, |date| {
+            probes += 1;
+            Ok(date >= earliest)
+        });
+        assert_eq!(earliest, result.unwrap());
+        assert!(probes < 20);
+
+        assert_eq!(lo, bisect(lo, hi, |_| Ok(true)).unwrap());
+        assert_matches!(bisect(lo, hi, |_| Ok(false)), Err(_));
+    }
+
+    #[test]
+    fn test_earliest_date_default() {
+        assert_eq!(NaiveDate::from_ymd(2019, 12, 19), earliest_date());
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Sending email over SMTP.
+
+// Without a TLS library, sending always fails, leaving most of this unused.
+#![cfg_attr(
+    not(any(feature = "native-tls", feature = "rustls")),
+    allow(dead_code, unused_imports)
+)]
+
+use {
+    lettre::{
+        message::{Mailbox, MultiPart},
+        transport::smtp::authentication::Credentials,
+        Message, SmtpTransport, Transport,
+    },
+    std::{io, time::Duration},
+    tracing::debug,
+};
+
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An SMTP server for sending email, e.g., a daily digest of the playlist.
+///
+/// The URL is like "smtps://smtp.example.com" for TLS from the start (port 465
+/// by default), or "smtp://smtp.example.com" to upgrade with STARTTLS (port 587
+/// by default). Sending fails if the server doesn't offer STARTTLS, unless
+/// plaintext is allowed with `with_plaintext`. TLS needs the `native-tls` or
+/// `rustls` feature.
+///
+/// Messages have a text and an HTML version.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// let mailer = wowcpe::Mailer::new("smtps://smtp.example.com", "me@example.com")
+///     .with_credentials("me@example.com", "password");
+/// mailer.send(&["you@example.com"], "Hello", "Hi!", "<p>Hi!</p>")
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Mailer {
+    url: String,
+    from: String,
+    credentials: Option<(String, String)>,
+    plaintext: bool,
+}
+
+impl Mailer {
+    /// Creates a mailer that sends through the server at `url` from the
+    /// address `from`.
+    pub fn new(url: &str, from: &str) -> Self {
+        Mailer {
+            url: url.to_string(),
+            from: from.to_string(),
+            credentials: None,
+            plaintext: false,
+        }
+    }
+
+    /// Logs in to the server with `username` and `password`.
+    pub fn with_credentials(self, username: &str, password: &str) -> Self {
+        Mailer {
+            credentials: Some((username.to_string(), password.to_string())),
+            ..self
+        }
+    }
+
+    /// Sets whether to send without TLS if an "smtp://" server doesn't offer
+    /// STARTTLS. The password and the message are then sent in the clear.
+    pub fn with_plaintext(self, allow: bool) -> Self {
+        Mailer {
+            plaintext: allow,
+            ..self
+        }
+    }
+
+    /// Sends a message to each address in `to`, with `text` and `html` as
+    /// alternative versions of the body.
+    pub fn send<S: AsRef<str>>(
+        &self,
+        to: &[S],
+        subject: &str,
+        text: &str,
+        html: &str,
+    ) -> io::Result<()> {
+        let mut message = Message::builder().from(mailbox(&self.from)?);
+        for address in to {
+            message = message.to(mailbox(address.as_ref())?);
+        }
+        let message = message
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(
+                text.to_string(),
+                html.to_string(),
+            ))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.transport()?.send(&message).map_err(io::Error::other)?;
+        debug!(url = %self.url, recipients = to.len(), "sent email");
+        Ok(())
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    fn transport(&self) -> io::Result<SmtpTransport> {
+        let mut transport = SmtpTransport::from_url(&self.transport_url()?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+            .timeout(Some(TIMEOUT));
+        if let Some((username, password)) = &self.credentials {
+            transport = transport.credentials(Credentials::new(
+                username.clone(),
+                password.clone(),
+            ));
+        }
+        Ok(transport.build())
+    }
+
+    #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+    fn transport(&self) -> io::Result<SmtpTransport> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Sending email needs the native-tls or rustls feature",
+        ))
+    }
+
+    /// Returns the URL with the query that tells lettre whether TLS is
+    /// required for "smtp://", which otherwise means plaintext to it.
+    fn transport_url(&self) -> io::Result<String> {
+        let plain = match self.url.strip_prefix("smtp://") {
+            Some(plain) => plain,
+            None if self.url.starts_with("smtps://") => {
+                return Ok(self.url.clone())
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{}: Expected an smtps:// or smtp:// URL",
+                        self.url
+                    ),
+                ))
+            }
+        };
+        if plain.contains('?') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: Unexpected query in the URL", self.url),
+            ));
+        }
+        let tls = if self.plaintext {
+            "opportunistic"
+        } else {
+            "required"
+        };
+        Ok(format!("{}?tls={}", self.url, tls))
+    }
+}
+
+/// Parses an address like "me@example.com" or "Me <me@example.com>".
+fn mailbox(address: &str) -> io::Result<Mailbox> {
+    address.parse().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", address, err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        },
+    };
+
+    #[test]
+    fn test_transport_url() {
+        let mailer = Mailer::new("smtp://localhost:2525/", "me@example.com");
+        assert_eq!(
+            "smtp://localhost:2525/?tls=required",
+            mailer.transport_url().unwrap()
+        );
+        assert_eq!(
+            "smtps://[::1]",
+            Mailer::new("smtps://[::1]", "me@example.com")
+                .transport_url()
+                .unwrap()
+        );
+        assert!(Mailer::new("https://example.com", "me@example.com")
+            .transport_url()
+            .is_err());
+    }
+
+    /// Runs an SMTP server without STARTTLS that accepts one connection, and
+    /// returns the URL and a handle that returns the commands and the message.
+    fn server() -> (String, thread::JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("smtp://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let reply = |line: &str| write!(&stream, "{}\r\n", line).unwrap();
+            reply("220 localhost");
+            let mut commands = Vec::new();
+            let mut data = String::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let command = line.trim_end().to_string();
+                match command.split(' ').next().unwrap() {
+                    "EHLO" => reply("250 localhost"),
+                    "DATA" => {
+                        reply("354 Go ahead");
+                        loop {
+                            let mut line = String::new();
+                            reader.read_line(&mut line).unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            data.push_str(&line);
+                        }
+                        reply("250 OK");
+                    }
+                    "QUIT" => reply("221 Bye"),
+                    _ => reply("250 OK"),
+                }
+                commands.push(command);
+            }
+            (commands, data)
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_send_plaintext() {
+        let (url, handle) = server();
+        Mailer::new(&url, "me@example.com")
+            .with_plaintext(true)
+            .send(&["you@example.com"], "Dvořák", "Hi!\n.", "<p>Hi!</p>")
+            .unwrap();
+        let (commands, data) = handle.join().unwrap();
+        assert_eq!(
+            vec![
+                "MAIL FROM:<me@example.com>",
+                "RCPT TO:<you@example.com>",
+                "DATA",
+                "QUIT"
+            ],
+            commands[1..]
+        );
+        assert!(data.contains("\r\nSubject: =?utf-8?b?RHZvxZnDoWs=?=\r\n"));
+        assert!(data.contains("\r\n<p>Hi!</p>\r\n"));
+        assert!(data.is_ascii());
+    }
+
+    #[test]
+    fn test_send_requires_tls() {
+        let (url, handle) = server();
+        let result = Mailer::new(&url, "me@example.com")
+            .with_credentials("me", "secret")
+            .send(&["you@example.com"], "Hello", "Hi!", "<p>Hi!</p>");
+        assert!(result.is_err());
+        let commands = handle.join().unwrap().0;
+        assert!(commands.iter().all(|command| !command.starts_with("AUTH")));
+        assert!(commands.iter().all(|command| !command.starts_with("MAIL")));
+    }
+}
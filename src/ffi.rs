@@ -0,0 +1,220 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! C interface, enabled by the `ffi` feature. See `include/wowcpe.h`.
+
+use {
+    crate::{Error, Request, Response},
+    chrono::{offset::LocalResult, TimeZone, Utc},
+    std::{
+        ffi::CString,
+        os::raw::{c_char, c_int},
+        ptr,
+    },
+};
+
+/// Information about a piece playing on WCPE, for C callers.
+///
+/// Times are Unix timestamps in seconds. Strings are owned by the response and
+/// must be released with `wowcpe_response_free`. All strings except `program`
+/// are null if the playlist does not list them.
+#[repr(C)]
+pub struct WowcpeResponse {
+    pub program: *mut c_char,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub composer: *mut c_char,
+    pub title: *mut c_char,
+    pub performers: *mut c_char,
+    pub record_label: *mut c_char,
+}
+
+/// Status code for success.
+pub const WOWCPE_OK: c_int = 0;
+/// Status code for an invalid argument, e.g., a null pointer.
+pub const WOWCPE_INVALID_ARGUMENT: c_int = 1;
+/// Status code for `Error::Curl`.
+pub const WOWCPE_CURL: c_int = 2;
+/// Status code for `Error::NoData`.
+pub const WOWCPE_NO_DATA: c_int = 3;
+/// Status code for `Error::NoEntry`.
+pub const WOWCPE_NO_ENTRY: c_int = 4;
+/// Status code for `Error::BadUtf8`.
+pub const WOWCPE_BAD_UTF8: c_int = 5;
+/// Status code for `Error::BadScrape`.
+pub const WOWCPE_BAD_SCRAPE: c_int = 6;
+/// Status code for `Error::BadTime`.
+pub const WOWCPE_BAD_TIME: c_int = 7;
+/// Status code for `Error::HttpStatus`.
+pub const WOWCPE_HTTP_STATUS: c_int = 8;
+/// Status code for `Error::BodyTooLarge`.
+pub const WOWCPE_BODY_TOO_LARGE: c_int = 9;
+/// Status code for `Error::Cancelled`.
+pub const WOWCPE_CANCELLED: c_int = 10;
+
+/// Looks up what is playing on WCPE at the Unix timestamp `time`, storing the
+/// result in `out`. Returns `WOWCPE_OK` on success, and otherwise one of the
+/// other status codes, in which case `out` is left untouched.
+///
+/// # Safety
+///
+/// `out` must be null or point to memory valid for writing a `WowcpeResponse`.
+#[no_mangle]
+pub unsafe extern "C" fn wowcpe_lookup(
+    time: i64,
+    out: *mut WowcpeResponse,
+) -> c_int {
+    if out.is_null() {
+        return WOWCPE_INVALID_ARGUMENT;
+    }
+    let time = match Utc.timestamp_opt(time, 0) {
+        LocalResult::Single(time) => time,
+        _ => return WOWCPE_INVALID_ARGUMENT,
+    };
+    match crate::lookup(&Request { time }) {
+        Ok(response) => {
+            ptr::write(out, to_c(&response));
+            WOWCPE_OK
+        }
+        Err(err) => error_code(&err),
+    }
+}
+
+/// Frees the strings in a response filled in by `wowcpe_lookup`, and sets them
+/// to null. Does nothing if `response` is null.
+///
+/// # Safety
+///
+/// `response` must be null or point to a response filled in by
+/// `wowcpe_lookup` that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn wowcpe_response_free(response: *mut WowcpeResponse) {
+    if let Some(r) = response.as_mut() {
+        for field in &mut [
+            &mut r.program,
+            &mut r.composer,
+            &mut r.title,
+            &mut r.performers,
+            &mut r.record_label,
+        ] {
+            if !field.is_null() {
+                drop(CString::from_raw(**field));
+                **field = ptr::null_mut();
+            }
+        }
+    }
+}
+
+/// Returns a static, NUL-terminated description of a status code.
+#[no_mangle]
+pub extern "C" fn wowcpe_strerror(code: c_int) -> *const c_char {
+    let message: &'static [u8] = match code {
+        WOWCPE_OK => b"Success\0",
+        WOWCPE_INVALID_ARGUMENT => b"Invalid argument\0",
+        WOWCPE_CURL => b"Failed to download the playlist\0",
+        WOWCPE_NO_DATA => b"There is no data for the given time\0",
+        WOWCPE_NO_ENTRY => b"Cannot find entry for the given time\0",
+        WOWCPE_BAD_UTF8 => b"Failed to parse HTML as UTF-8\0",
+        WOWCPE_BAD_SCRAPE => b"Failed to scrape the HTML\0",
+        WOWCPE_BAD_TIME => b"Failed to parse a time in the HTML\0",
+        WOWCPE_HTTP_STATUS => b"The server responded with an error status\0",
+        WOWCPE_BODY_TOO_LARGE => b"The page exceeded the size limit\0",
+        WOWCPE_CANCELLED => b"The lookup was cancelled\0",
+        _ => b"Unknown error\0",
+    };
+    message.as_ptr() as *const c_char
+}
+
+fn error_code(err: &Error) -> c_int {
+    match err {
+        Error::Curl(_) | Error::Transport(_) => WOWCPE_CURL,
+        Error::Cancelled => WOWCPE_CANCELLED,
+        Error::HttpStatus(..) => WOWCPE_HTTP_STATUS,
+        Error::BodyTooLarge(_) => WOWCPE_BODY_TOO_LARGE,
+        Error::NoData | Error::NotCached(_) => WOWCPE_NO_DATA,
+        Error::NoEntry => WOWCPE_NO_ENTRY,
+        Error::BadUtf8 => WOWCPE_BAD_UTF8,
+        Error::BadScrape => WOWCPE_BAD_SCRAPE,
+        Error::BadTime => WOWCPE_BAD_TIME,
+    }
+}
+
+fn to_c(r: &Response<Utc>) -> WowcpeResponse {
+    WowcpeResponse {
+        program: c_string(r.program.name()),
+        start_time: r.start_time.timestamp(),
+        end_time: r.end_time.timestamp(),
+        composer: c_string_opt(&r.composer),
+        title: c_string_opt(&r.title),
+        performers: c_string_opt(&r.performers),
+        record_label: c_string_opt(&r.record_label),
+    }
+}
+
+fn c_string_opt(s: &Option<String>) -> *mut c_char {
+    s.as_deref().map_or(ptr::null_mut(), c_string)
+}
+
+fn c_string(s: &str) -> *mut c_char {
+    let s = s.replace('\0', "");
+    CString::new(s).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::Program, std::ffi::CStr};
+
+    #[test]
+    fn test_lookup_null() {
+        let code = unsafe { wowcpe_lookup(0, ptr::null_mut()) };
+        assert_eq!(WOWCPE_INVALID_ARGUMENT, code);
+    }
+
+    #[test]
+    fn test_lookup_no_data() {
+        let mut out = std::mem::MaybeUninit::<WowcpeResponse>::uninit();
+        let code = unsafe { wowcpe_lookup(0, out.as_mut_ptr()) };
+        assert_eq!(WOWCPE_NO_DATA, code);
+    }
+
+    #[test]
+    fn test_strerror() {
+        let message =
+            unsafe { CStr::from_ptr(wowcpe_strerror(WOWCPE_NO_DATA)) };
+        assert_eq!(
+            "There is no data for the given time",
+            message.to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_code_cancelled() {
+        assert_eq!(WOWCPE_CANCELLED, error_code(&Error::Cancelled));
+    }
+
+    #[test]
+    fn test_response_free() {
+        let response = Response {
+            program: Program::ConcertHall,
+            start_time: Utc.timestamp_opt(10, 0).unwrap(),
+            end_time: Utc.timestamp_opt(20, 0).unwrap(),
+            program_start: Utc.timestamp_opt(0, 0).unwrap(),
+            program_end: Utc.timestamp_opt(3600, 0).unwrap(),
+            composer: Some("Bach".to_string()),
+            title: Some("Nul\0l".to_string()),
+            performers: None,
+            record_label: None,
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+        let mut c = to_c(&response);
+        assert_eq!(10, c.start_time);
+        let title = unsafe { CStr::from_ptr(c.title) };
+        assert_eq!("Null", title.to_str().unwrap());
+        assert!(c.performers.is_null());
+
+        unsafe { wowcpe_response_free(&mut c) };
+        assert!(c.title.is_null());
+        unsafe { wowcpe_response_free(&mut c) };
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Recording and replaying downloads, for tests that shouldn't need network
+//! access.
+
+use {
+    crate::{client::Page, Error, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+    tracing::debug,
+};
+
+/// Whether a `Fixtures` directory is recorded to or replayed from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FixtureMode {
+    /// Download every page and save it as a fixture, overwriting any existing
+    /// one.
+    Record,
+    /// Answer every download from a fixture, never touching the network.
+    Replay,
+    /// Replay the fixture if there is one, and otherwise download the page and
+    /// record it.
+    Auto,
+}
+
+/// A directory of recorded downloads.
+///
+/// Pass fixtures to `Client::set_fixtures` so that the client records the
+/// pages it downloads, or replays them later without network access. Each page
+/// is stored in its own JSON file named after the URL, holding the decoded
+/// body and the HTTP status, so error responses replay faithfully too.
+///
+/// A typical test replays fixtures that were recorded once and checked in:
+///
+/// ```no_run
+/// use wowcpe::{Client, FixtureMode, Fixtures};
+///
+/// let client = Client::new();
+/// client.set_fixtures(Some(Fixtures::new("tests/fixtures", FixtureMode::Replay)));
+/// ```
+///
+/// Setting the environment variable `WOWCPE_RECORD` when constructing with
+/// `Fixtures::from_env` switches to `FixtureMode::Record`, which makes it easy
+/// to refresh the fixtures.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fixtures {
+    dir: PathBuf,
+    mode: FixtureMode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    url: String,
+    status: u32,
+    status_line: String,
+    body: String,
+}
+
+impl Fixtures {
+    /// Creates fixtures stored in `dir`.
+    pub fn new(dir: impl Into<PathBuf>, mode: FixtureMode) -> Self {
+        Fixtures {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    /// Creates fixtures stored in `dir`, in `FixtureMode::Record` if the
+    /// `WOWCPE_RECORD` environment variable is set and `FixtureMode::Replay`
+    /// otherwise.
+    pub fn from_env(dir: impl Into<PathBuf>) -> Self {
+        let mode = match std::env::var_os("WOWCPE_RECORD") {
+            Some(_) => FixtureMode::Record,
+            None => FixtureMode::Replay,
+        };
+        Fixtures::new(dir, mode)
+    }
+
+    /// Returns the directory the fixtures are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the mode.
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    /// Returns the path of the fixture for `url`.
+    pub fn path(&self, url: &str) -> PathBuf {
+        let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let name: String = url
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Answers a download of `url` from a fixture, or by calling `download`
+    /// and recording the result, depending on the mode. Replayed pages have no
+    /// caching headers, so their freshness is judged as if the server sent
+    /// none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a fixture is needed for replay but is missing or invalid, or
+    /// if recording one fails, since these indicate a broken test setup rather
+    /// than a failed lookup.
+    pub(crate) fn download(
+        &self,
+        url: &str,
+        download: impl FnOnce() -> Result<Page>,
+    ) -> Result<Page> {
+        let path = self.path(url);
+        let replay = match self.mode {
+            FixtureMode::Record => false,
+            FixtureMode::Replay => true,
+            FixtureMode::Auto => path.exists(),
+        };
+        if replay {
+            debug!(path = %path.display(), "replaying fixture");
+            let json = fs::read_to_string(&path).unwrap_or_else(|err| {
+                panic!("failed to read fixture {}: {}", path.display(), err)
+            });
+            let fixture: Fixture =
+                serde_json::from_str(&json).unwrap_or_else(|err| {
+                    panic!("invalid fixture {}: {}", path.display(), err)
+                });
+            return if (200..300).contains(&fixture.status) {
+                Ok(Page {
+                    body: fixture.body,
+                    expires_at: None,
+                })
+            } else {
+                Err(Error::HttpStatus(fixture.status, fixture.status_line))
+            };
+        }
+        let result = download();
+        let fixture = match &result {
+            Ok(page) => Fixture {
+                url: url.to_string(),
+                status: 200,
+                status_line: "HTTP/1.1 200 OK".to_string(),
+                body: page.body.clone(),
+            },
+            Err(Error::HttpStatus(status, status_line)) => Fixture {
+                url: url.to_string(),
+                status: *status,
+                status_line: status_line.clone(),
+                body: String::new(),
+            },
+            // Network failures and cancellation aren't worth replaying.
+            Err(_) => return result,
+        };
+        debug!(path = %path.display(), "recording fixture");
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| {
+                let json = serde_json::to_string_pretty(&fixture)?;
+                fs::write(&path, json)
+            })
+            .unwrap_or_else(|err| {
+                panic!("failed to record fixture {}: {}", path.display(), err)
+            });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, assert_matches::assert_matches};
+
+    const URL: &str = "https://example.com/listen/playlist/?date=2020-09-01";
+
+    fn page(body: &str) -> Result<Page> {
+        Ok(Page {
+            body: body.to_string(),
+            expires_at: None,
+        })
+    }
+
+    fn body(result: Result<Page>) -> String {
+        result.unwrap().body
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_path() {
+        let fixtures = Fixtures::new("fixtures", FixtureMode::Replay);
+        assert_eq!(
+            Path::new(
+                "fixtures/example.com_listen_playlist__date_2020-09-01.json"
+            )
+            .to_path_buf(),
+            fixtures.path(URL)
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = temp_dir("wowcpe-test-fixtures");
+        let record = Fixtures::new(&dir, FixtureMode::Record);
+        let replay = Fixtures::new(&dir, FixtureMode::Replay);
+        let not_found = "https://example.com/missing";
+
+        assert_eq!(
+            "<html></html>",
+            body(record.download(URL, || page("<html></html>")))
+        );
+        assert_matches!(
+            record.download(not_found, || Err(Error::HttpStatus(
+                404,
+                "HTTP/1.1 404 Not Found".into()
+            ))),
+            Err(Error::HttpStatus(404, _))
+        );
+        assert_eq!(
+            "<html></html>",
+            body(replay.download(URL, || panic!("downloaded")))
+        );
+        assert_matches!(
+            replay.download(not_found, || panic!("downloaded")),
+            Err(Error::HttpStatus(404, line)) if line == "HTTP/1.1 404 Not Found"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_auto() {
+        let dir = temp_dir("wowcpe-test-fixtures-auto");
+        let auto = Fixtures::new(&dir, FixtureMode::Auto);
+
+        assert_eq!("a", body(auto.download(URL, || page("a"))));
+        assert_eq!("a", body(auto.download(URL, || page("b"))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "failed to read fixture")]
+    fn test_replay_missing() {
+        let dir = temp_dir("wowcpe-test-fixtures-missing");
+        let replay = Fixtures::new(&dir, FixtureMode::Replay);
+        let _ = replay.download(URL, || page(""));
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Loading schedule corrections from files. The parsers live in `wowcpe-core`,
+//! which does no I/O.
+
+use {
+    crate::{MetOperaSeason, SpecialtySchedule},
+    std::{fs, io, path::Path},
+};
+
+/// Loads a Metropolitan Opera season from a JSON file. See `MetOperaSeason`
+/// for the format, and pass the result to `set_met_opera_season`.
+pub fn load_met_opera_season(path: &Path) -> io::Result<MetOperaSeason> {
+    MetOperaSeason::from_json(&fs::read_to_string(path)?)
+}
+
+/// Loads a specialty schedule from a TOML file. See `SpecialtySchedule` for
+/// the format, and pass the result to `set_specialty_schedule`.
+pub fn load_specialty_schedule(path: &Path) -> io::Result<SpecialtySchedule> {
+    SpecialtySchedule::from_toml(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{Eastern, Program},
+        chrono::TimeZone,
+    };
+
+    #[test]
+    fn test_load_missing() {
+        let path = Path::new("/nonexistent/wowcpe/season.json");
+        let err = load_met_opera_season(path).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        let err = load_specialty_schedule(path).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+    }
+
+    #[test]
+    fn test_load_specialty_schedule() {
+        let path = std::env::temp_dir().join("wowcpe-test-specialty.toml");
+        let toml = r#"
+            [[holiday]]
+            program = "Bach Marathon"
+            date = "03-21"
+        "#;
+        fs::write(&path, toml).unwrap();
+        let schedule = load_specialty_schedule(&path);
+        fs::remove_file(&path).unwrap();
+
+        let time = Eastern.ymd(2021, 3, 21).and_hms(12, 0, 0);
+        assert_eq!(
+            Some(Program::Special("Bach Marathon".to_owned())),
+            schedule.unwrap().program_at(&time)
+        );
+    }
+}
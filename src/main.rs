@@ -1,23 +1,86 @@
 // Copyright 2017 Mitchell Kember. Subject to the MIT License.
 
 use {
-    chrono::{DateTime, Local, Timelike},
-    clap::{App, Arg},
-    std::path::PathBuf,
-    wowcpe::{Request, Response},
+    chrono::{DateTime, Local, NaiveDate, Timelike},
+    clap::{App, AppSettings, Arg, ArgMatches, SubCommand},
+    std::{
+        cell::RefCell,
+        collections::{BTreeSet, HashMap},
+        env, fmt, fs,
+        io::{self, IsTerminal, Write},
+        net::TcpListener,
+        path::PathBuf,
+        process::{Command, Stdio},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, OnceLock,
+        },
+        thread,
+        time::Duration,
+    },
+    tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Level, Metadata, Subscriber,
+    },
+    wowcpe::{
+        repeats, Cache, CheckStatus, Config, Entry, Error, LastFm, Mqtt,
+        OnelineConfig, Play, Playlist, Program, Repeat, Request, Response,
+        Result, StationTime, Stats, Stream, Synced, Template, Webhook, STREAMS,
+    },
 };
 
 fn main() {
+    let stream_ids: Vec<&str> = STREAMS.iter().map(|s| s.id).collect();
     let matches = App::new("WOWCPE")
-        .version("0.2.2")
+        .version("0.3.0")
         .about("Show what is playing on WCPE - theclassicalstation.org")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .global(true)
+                .help(
+                    "Show URLs fetched, cache hits, HTTP status, timing, and \
+                     parsing; repeat for more",
+                ),
+        )
         .arg(
             Arg::with_name("time")
                 .short("t")
                 .long("time")
-                .value_name("HH:MM")
+                .value_name("TIME")
+                .takes_value(true)
+                .help(
+                    "Look up a specific time, e.g. 8pm or \"yesterday 19:30\"",
+                ),
+        )
+        .arg(
+            Arg::with_name("date")
+                .short("d")
+                .long("date")
+                .value_name("DATE")
+                .takes_value(true)
+                .help("Look up a time on DATE (YYYY-MM-DD) instead of today"),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .takes_value(false)
+                .conflicts_with_all(&["date", "time", "watch"])
+                .help(
+                    "Ask for the date and time to look up, choosing the date \
+                     with the arrow keys",
+                ),
+        )
+        .arg(
+            Arg::with_name("cache_dir")
+                .long("cache-dir")
+                .value_name("DIR")
                 .takes_value(true)
-                .help("Look up a specific time today"),
+                .help("Store the cache in DIR"),
         )
         .arg(
             Arg::with_name("no_cache")
@@ -26,88 +89,3021 @@ fn main() {
                 .takes_value(false)
                 .help("Disable caching"),
         )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .global(true)
+                .conflicts_with("no_cache")
+                .help("Only use cached playlists, even if they are stale"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .short("w")
+                .long("watch")
+                .takes_value(false)
+                .conflicts_with_all(&["time", "date"])
+                .help("Keep running and print each new piece as it starts"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .takes_value(false)
+                .conflicts_with("watch")
+                .help(
+                    "Print nothing, and exit with 0 if found, 2 if there is no \
+                     data, 3 for network errors, or 4 for parse errors",
+                ),
+        )
+        .arg(
+            Arg::with_name("next")
+                .long("next")
+                .value_name("N")
+                .takes_value(true)
+                .conflicts_with("watch")
+                .help("Print the next N pieces, if the playlist lists them yet"),
+        )
+        .arg(
+            Arg::with_name("day")
+                .long("day")
+                .takes_value(false)
+                .conflicts_with_all(&["watch", "next"])
+                .help("Print a table of every piece played that day"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .conflicts_with_all(&["json", "next", "day"])
+                .validator(|arg| {
+                    arg.parse::<Template>().map(drop).map_err(|e| e.to_string())
+                })
+                .help(
+                    "Print the response using TEMPLATE, e.g. \"{composer} - \
+                     {title} ({remaining}m left)\". Placeholders: {program} \
+                     {composer} {title} {performers} {record_label} {start} \
+                     {end} {program_start} {program_end} {elapsed} \
+                     {remaining} {relative} {source_url} {fetched_at}",
+                ),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("WHEN")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help(
+                    "Color the output: auto (if a terminal and NO_COLOR is \
+                     not set), always, or never",
+                ),
+        )
+        .arg(
+            Arg::with_name("csv")
+                .long("csv")
+                .takes_value(false)
+                .conflicts_with_all(&["json", "format", "watch"])
+                .help("Print CSV with a header row, with times in RFC 3339"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .takes_value(false)
+                .help("Print the response as JSON, with times in RFC 3339"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv", "waybar", "oneline", "xbar"])
+                .conflicts_with_all(&["json", "csv", "format", "oneline"])
+                .help(
+                    "Print the response for MODE: text, json, csv, waybar \
+                     (JSON for Waybar and i3status-rust custom blocks), \
+                     oneline (like --oneline), or xbar (an xbar or SwiftBar \
+                     plugin)",
+                ),
+        )
+        .arg(
+            Arg::with_name("fields")
+                .long("fields")
+                .value_name("FIELDS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(&FIELDS)
+                .hide_possible_values(true)
+                .conflicts_with_all(&["json", "csv", "format", "oneline", "output"])
+                .help(
+                    "Print only FIELDS, separated by tabs, e.g. \
+                     composer,title,end_time. Use the JSON field names; \
+                     times are in RFC 3339",
+                ),
+        )
+        .arg(
+            Arg::with_name("print0")
+                .short("0")
+                .long("print0")
+                .takes_value(false)
+                .requires("fields")
+                .help(
+                    "With --fields, end each field with a NUL byte instead of \
+                     separating them with tabs, e.g. for xargs -0",
+                ),
+        )
+        .arg(
+            Arg::with_name("relative")
+                .long("relative")
+                .takes_value(false)
+                .help(
+                    "Also show how long ago the piece started and when it \
+                     ends, e.g. \"started 12 minutes ago · ends in 8 minutes\"",
+                ),
+        )
+        .arg(
+            Arg::with_name("last_played")
+                .long("last-played")
+                .takes_value(false)
+                .help(
+                    "Also show when the piece last played, from the cache and \
+                     archive, e.g. \"2024-11-03 on Classical Café\"",
+                ),
+        )
+        .arg(
+            Arg::with_name("oneline")
+                .long("oneline")
+                .takes_value(false)
+                .conflicts_with_all(&["json", "csv", "format"])
+                .help(
+                    "Print a compact line for status lines, e.g. \"Concert \
+                     Hall · Brahms: Symphony No. 2 (23m left)\"",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_width")
+                .long("max-width")
+                .value_name("N")
+                .takes_value(true)
+                .validator(|arg| match arg.parse::<usize>() {
+                    Ok(width) if width >= OnelineConfig::MIN_WIDTH => Ok(()),
+                    _ => Err(format!(
+                        "expected a number from {}",
+                        OnelineConfig::MIN_WIDTH
+                    )),
+                })
+                .help("Shorten --oneline output to at most N characters"),
+        )
+        .arg(
+            Arg::with_name("ellipsis")
+                .long("ellipsis")
+                .value_name("TEXT")
+                .takes_value(true)
+                .help("End shortened --oneline output with TEXT [default: …]"),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Answer lookups over a Unix socket")
+                .arg(
+                    Arg::with_name("socket")
+                        .long("socket")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("Listen on PATH [default: daemon.sock in the cache dir]"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .value_name("SECS")
+                        .takes_value(true)
+                        .help("Refresh the playlist every SECS seconds [default: 60]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Answer lookups over HTTP with JSON")
+                .arg(
+                    Arg::with_name("addr")
+                        .long("addr")
+                        .value_name("ADDR")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:8080")
+                        .help("Listen on ADDR, e.g. 0.0.0.0:8080 for the local network"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Search the playlists in the cache")
+                .arg(
+                    Arg::with_name("composer")
+                        .long("composer")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .help("Only show pieces by composers matching NAME"),
+                )
+                .arg(
+                    Arg::with_name("title")
+                        .long("title")
+                        .value_name("TEXT")
+                        .takes_value(true)
+                        .help("Only show pieces with titles containing TEXT"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Only show pieces played on or after DATE"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Only show pieces played on or before DATE"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Summarize the playlists in the cache")
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Only count pieces played on or after DATE"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Only count pieces played on or before DATE"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("Show the N most played of each"),
+                )
+                .arg(
+                    Arg::with_name("repeats")
+                        .long("repeats")
+                        .help("List works played again within a window instead"),
+                )
+                .arg(
+                    Arg::with_name("window")
+                        .long("window")
+                        .value_name("AGE")
+                        .takes_value(true)
+                        .requires("repeats")
+                        .help("Count plays within AGE of each other as repeats, e.g. 3d [default: 7d]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("Search the playlists for a range of dates, downloading them if needed")
+                .arg(
+                    Arg::with_name("query")
+                        .value_name("QUERY")
+                        .required(true)
+                        .help("Text to find in the composer, title, or performers"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Search from DATE [default: a week before --to]"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Search until DATE, inclusive [default: today]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export the playlists for a range of dates to a file")
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["csv", "json", "ics", "jsonl"])
+                        .default_value("csv")
+                        .help("Export to FORMAT"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Write to FILE instead of standard output"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Export from DATE [default: a week before --until]"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .value_name("DATE")
+                        .takes_value(true)
+                        .help("Export until DATE, inclusive [default: today]"),
+                )
+                .arg(
+                    Arg::with_name("cached")
+                        .long("cached")
+                        .help("Only export days in the cache, without downloading"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("digest")
+                .about("Email the whole playlist for DATE [default: yesterday]")
+                .arg(Arg::with_name("date").value_name("DATE"))
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("ADDR")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Send to ADDR instead of the addresses in [email] in the config"),
+                )
+                .arg(
+                    Arg::with_name("print")
+                        .long("print")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["text", "html"])
+                        .conflicts_with("to")
+                        .help("Print the digest as FORMAT instead of sending it"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pin")
+                .about("Keep the cached playlist for DATE from being evicted")
+                .arg(Arg::with_name("date").value_name("DATE").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("unpin")
+                .about("Allow the cached playlist for DATE to be evicted")
+                .arg(Arg::with_name("date").value_name("DATE").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage the cached playlists")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("path")
+                        .about("Print the directory of cached playlists"),
+                )
+                .subcommand(
+                    SubCommand::with_name("stats")
+                        .about("Show how many days are cached and their size"),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Remove every cached playlist, keeping pins"),
+                )
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("Remove cached playlists for old dates, except pinned ones")
+                        .arg(
+                            Arg::with_name("older_than")
+                                .long("older-than")
+                                .value_name("AGE")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Remove dates more than AGE ago, e.g. 30d or 4w"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("archive")
+                .about("Keep every day's playlist, for history and stats")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("sync")
+                        .about("Download the playlists that aren't archived yet, resuming where the last sync left off")
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .value_name("DATE")
+                                .takes_value(true)
+                                .help("Start at DATE instead of the earliest date with a playlist"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("DATE")
+                                .takes_value(true)
+                                .help("End at DATE instead of today"),
+                        )
+                        .arg(
+                            Arg::with_name("per_minute")
+                                .long("per-minute")
+                                .value_name("N")
+                                .takes_value(true)
+                                .default_value("20")
+                                .help("Download at most N pages per minute, to go easy on the website"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor").about(
+                "Check the connection, TLS, download, parser, and cache, \
+                 for when lookups stop working",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("notify").about(
+                "Keep running and show a desktop notification as each new \
+                 piece starts, and before favorites start",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("streams")
+                .about("List the live streams and their URLs"),
+        )
+        .subcommand(
+            SubCommand::with_name("mqtt")
+                .about("Keep running and publish each new piece to an MQTT broker")
+                .arg(
+                    Arg::with_name("broker")
+                        .long("broker")
+                        .value_name("ADDR")
+                        .takes_value(true)
+                        .help("Connect to the broker at ADDR [default: localhost:1883]"),
+                )
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .value_name("TOPIC")
+                        .takes_value(true)
+                        .help("Publish to TOPIC [default: wowcpe/now]"),
+                )
+                .arg(
+                    Arg::with_name("tls")
+                        .long("tls")
+                        .help("Connect to the broker over TLS"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("webhook")
+                .about("Keep running and post each new piece as JSON to webhooks")
+                .arg(
+                    Arg::with_name("url")
+                        .long("url")
+                        .value_name("URL")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Post to URL, in addition to [[webhooks]] in the config"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("scrobble")
+                .about("Keep running and scrobble each piece to Last.fm or ListenBrainz")
+                .arg(
+                    Arg::with_name("login")
+                        .long("login")
+                        .help("Authorize with Last.fm and print a session key for the config"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Play the live stream")
+                .arg(
+                    Arg::with_name("player")
+                        .long("player")
+                        .value_name("CMD")
+                        .takes_value(true)
+                        .help("Play with CMD, e.g. \"vlc --intf dummy\" [default: mpv or vlc]"),
+                )
+                .arg(
+                    Arg::with_name("stream")
+                        .long("stream")
+                        .value_name("ID")
+                        .takes_value(true)
+                        .possible_values(&stream_ids)
+                        .help("Play the stream with ID; see `wowcpe streams` [default: mp3]"),
+                )
+                .arg(
+                    Arg::with_name("print_url")
+                        .long("print-url")
+                        .conflicts_with_all(&["player", "watch"])
+                        .help("Print the stream URL instead of playing it"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .short("w")
+                        .long("watch")
+                        .help("Print each new piece as it starts while playing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("calendar")
+                .about("Print the weekly program schedule as an iCalendar file"),
+        )
         .get_matches();
 
-    let time = if let Some(arg) = matches.value_of("time") {
-        parse_time(arg).unwrap_or_else(|| invalid_arg(arg))
+    let level = match matches.occurrences_of("verbose") {
+        0 => None,
+        1 => Some(Level::DEBUG),
+        _ => Some(Level::TRACE),
+    };
+    if let Some(level) = level {
+        let _ = tracing::subscriber::set_global_default(Logger::new(level));
+    }
+    let config = config();
+    if let Some(tz) = &config.timezone {
+        if tz.parse::<chrono_tz::Tz>().is_err() {
+            fail(&format!("{}: Invalid time zone in config", tz));
+        }
+        // Chrono reads TZ to determine the local time zone.
+        env::set_var("TZ", tz);
+    }
+    match matches.value_of_os("cache_dir") {
+        Some(dir) => wowcpe::set_cache_dir(Some(PathBuf::from(dir))),
+        None => {
+            if let Some(dir) = &config.cache.dir {
+                wowcpe::set_cache_dir(Some(dir.clone()));
+            }
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("daemon") {
+        #[cfg(unix)]
+        daemon(matches);
+        #[cfg(not(unix))]
+        fail("The daemon requires Unix domain sockets");
+    }
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        serve(matches);
+    }
+    if let Some(sub) = matches.subcommand_matches("history") {
+        history(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("stats") {
+        stats(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("search") {
+        search(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("export") {
+        export(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("digest") {
+        digest(&matches, sub);
+    }
+    for (name, pin) in [("pin", true), ("unpin", false)] {
+        if let Some(matches) = matches.subcommand_matches(name) {
+            pin_date(matches, pin);
+        }
+    }
+    if let Some(sub) = matches.subcommand_matches("cache") {
+        manage_cache(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("archive") {
+        if let Some(sync) = sub.subcommand_matches("sync") {
+            sync_archive(&matches, sync);
+        }
+    }
+    if matches.subcommand_matches("doctor").is_some() {
+        doctor(&matches);
+    }
+    if matches.subcommand_matches("notify").is_some() {
+        notify(&matches);
+    }
+    if let Some(sub) = matches.subcommand_matches("play") {
+        play(&matches, sub);
+    }
+    if matches.subcommand_matches("streams").is_some() {
+        streams(&matches);
+    }
+    if let Some(sub) = matches.subcommand_matches("mqtt") {
+        mqtt(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("webhook") {
+        webhook(&matches, sub);
+    }
+    if let Some(sub) = matches.subcommand_matches("scrobble") {
+        if sub.is_present("login") {
+            lastfm_login();
+        }
+        scrobble(&matches);
+    }
+    if matches.subcommand_matches("calendar").is_some() {
+        let today = StationTime::now().date();
+        print!("{}", wowcpe::schedule_ics(today));
+        return;
+    }
+
+    let time = if matches.is_present("interactive") {
+        prompt_time()
     } else {
-        current_time()
+        time_arg(&matches)
     };
 
-    let request = &Request { time };
-    let cache = cache_file_path();
-    let result = match (cache, matches.is_present("no_cache")) {
-        (Some(path), false) => wowcpe::lookup_cached(request, &path),
-        _ => wowcpe::lookup(request),
+    if matches.is_present("watch") {
+        watch(&matches);
+    }
+    if let Some(arg) = matches.value_of("next") {
+        let n: usize = arg.parse().unwrap_or_else(|_| invalid_arg(arg));
+        let playlist = lookup_playlist(&matches, &Request { time })
+            .unwrap_or_else(|err| fail_lookup(&matches, &err));
+        let upcoming = playlist.after(time);
+        if matches.is_present("quiet") {
+            let found = !upcoming.is_empty() && n > 0;
+            std::process::exit(if found { 0 } else { EXIT_NO_DATA });
+        }
+        if upcoming.is_empty() && !machine_readable(&matches) {
+            println!("No upcoming pieces are listed yet");
+        }
+        print_entries(&matches, &upcoming[..n.min(upcoming.len())], false);
+        return;
+    }
+    if matches.is_present("day") {
+        let playlist = lookup_playlist(&matches, &Request { time })
+            .unwrap_or_else(|err| fail_lookup(&matches, &err));
+        if matches.is_present("quiet") {
+            let found = !playlist.entries().is_empty();
+            std::process::exit(if found { 0 } else { EXIT_NO_DATA });
+        }
+        if !machine_readable(&matches) {
+            for warning in playlist.warnings() {
+                eprintln!("warning: {}", warning);
+            }
+        }
+        print_entries(&matches, playlist.entries(), false);
+        return;
+    }
+    match lookup(&matches, &Request { time }) {
+        Ok(_) if matches.is_present("quiet") => {}
+        Ok(response) => print(&matches, &response, &time),
+        Err(err) => fail_lookup(&matches, &err),
+    }
+}
+
+/// Returns the time to look up given by `--date` and `--time`, or now.
+fn time_arg(matches: &ArgMatches<'_>) -> DateTime<Local> {
+    // With --date, times are relative to that date at the current time of day.
+    let base = match matches.value_of("date") {
+        Some(arg) => NaiveDate::parse_from_str(arg, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| Request::on_date(date, current_time().time()))
+            .map(|request| request.time)
+            .unwrap_or_else(|| invalid_arg(arg)),
+        None => current_time(),
     };
-    match result {
-        Ok(response) => print_response(&response),
-        Err(err) => fail(&err.to_string()),
+    match matches.value_of("time") {
+        Some(arg) => wowcpe::parse_natural_time(arg, base)
+            .unwrap_or_else(|| invalid_arg(arg)),
+        None => base,
     }
 }
 
-fn cache_file_path() -> Option<PathBuf> {
-    xdg::BaseDirectories::with_prefix("wowcpe")
-        .ok()?
-        .place_cache_file("playlist.html")
-        .ok()
+/// Asks for the date and then the time to look up on standard error, for
+/// `--interactive`. Pressing Enter accepts the defaults: today, and the
+/// current time of day.
+fn prompt_time() -> DateTime<Local> {
+    let now = current_time();
+    let date = prompt_date(now);
+    let base = Request::on_date(date, now.time())
+        .map(|request| request.time)
+        .unwrap_or(now);
+    let default = base.format("%l:%M %p").to_string();
+    loop {
+        let input = prompt_line(&format!("Time [{}]: ", default.trim()));
+        if input.is_empty() {
+            return base;
+        }
+        match wowcpe::parse_natural_time(&input, base) {
+            Some(time) => return time,
+            None => eprintln!("Invalid time; try 8pm, 19:30, or noon"),
+        }
+    }
 }
 
-fn current_time() -> DateTime<Local> {
-    Local::now().with_nanosecond(0).unwrap()
+/// Asks for a date, with the arrow keys if standard input is a terminal.
+fn prompt_date(now: DateTime<Local>) -> NaiveDate {
+    let raw = match RawMode::enable() {
+        Some(raw) => raw,
+        None => loop {
+            let today = now.naive_local().date();
+            let input = prompt_line(&format!("Date [{}]: ", today));
+            if input.is_empty() {
+                return today;
+            }
+            match parse_date_input(&input, &now) {
+                Some(date) => return date,
+                None => eprintln!("Invalid date; try 2020-09-01 or yesterday"),
+            }
+        },
+    };
+    let mut picker = DatePicker::new(now);
+    let mut stdin = io::stdin().lock();
+    loop {
+        // Return to the start of the line and clear it.
+        eprint!("\r\x1b[K{}", picker.line());
+        let key = read_key(&mut stdin).unwrap_or(Key::Interrupt);
+        if let Key::Interrupt = key {
+            drop(raw);
+            eprintln!();
+            std::process::exit(130);
+        }
+        if let Some(date) = picker.handle(key) {
+            eprintln!("\r\x1b[KDate: {}", date.format("%a, %b %-d, %Y"));
+            return date;
+        }
+    }
 }
 
-fn parse_time(input: &str) -> Option<DateTime<Local>> {
-    let input: &str = &input.trim().to_lowercase();
-    let (input, hour_offset) = if input.len() >= 2 {
-        match input.split_at(input.len() - 2) {
-            (x, "am") => (x, 0),
-            (x, "pm") => (x, 12),
-            _ => (input, 0),
+/// Prints `prompt` and returns the line typed, trimmed. Exits at end of file.
+fn prompt_line(prompt: &str) -> String {
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => {
+            eprintln!();
+            std::process::exit(1)
         }
-    } else {
-        (input, 0)
-    };
-    let (hour, minute): (u32, u32) = if let Some(index) = input.find(':') {
-        let (hh, colon_mm) = input.split_at(index);
-        let mm = &colon_mm[1..];
-        if let (Ok(hour), Ok(minute)) = (hh.parse(), mm.parse()) {
-            (hour, minute)
-        } else {
+        Ok(_) => line.trim().to_string(),
+    }
+}
+
+/// Parses a date typed at a prompt, like "2020-09-01", "yesterday", or
+/// "last thursday".
+fn parse_date_input(input: &str, now: &DateTime<Local>) -> Option<NaiveDate> {
+    match NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        Ok(date) => Some(date),
+        Err(_) => wowcpe::parse_natural_time(input, *now)
+            .map(|time| time.naive_local().date()),
+    }
+}
+
+/// Chooses a date with the arrow keys, or by typing one.
+struct DatePicker {
+    now: DateTime<Local>,
+    date: NaiveDate,
+    /// The last date with a playlist, so there's no point going past it.
+    latest: NaiveDate,
+    typed: String,
+    invalid: bool,
+}
+
+impl DatePicker {
+    fn new(now: DateTime<Local>) -> Self {
+        let today = now.naive_local().date();
+        DatePicker {
+            now,
+            date: today,
+            latest: today.max(StationTime::now().date()),
+            typed: String::new(),
+            invalid: false,
+        }
+    }
+
+    /// Returns what to show on the prompt line.
+    fn line(&self) -> String {
+        if self.typed.is_empty() {
+            return format!(
+                "Date: ← {} →  (arrow keys to change, Enter to choose, or \
+                 type a date)",
+                self.date.format("%a, %b %-d, %Y")
+            );
+        }
+        let invalid = if self.invalid { "  (invalid date)" } else { "" };
+        format!("Date: {}{}", self.typed, invalid)
+    }
+
+    /// Updates the picker for `key`, returning the date once it is chosen.
+    fn handle(&mut self, key: Key) -> Option<NaiveDate> {
+        self.invalid = false;
+        match key {
+            Key::Left | Key::Down => {
+                self.typed.clear();
+                self.date = self.date.pred_opt().unwrap_or(self.date);
+            }
+            Key::Right | Key::Up => {
+                self.typed.clear();
+                if self.date < self.latest {
+                    self.date = self.date.succ_opt().unwrap_or(self.date);
+                }
+            }
+            Key::Char(c) => self.typed.push(c),
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Enter if self.typed.is_empty() => return Some(self.date),
+            Key::Enter => match parse_date_input(&self.typed, &self.now) {
+                Some(date) => return Some(date),
+                None => self.invalid = true,
+            },
+            Key::Interrupt | Key::Other => {}
+        }
+        None
+    }
+}
+
+/// A key pressed at a prompt in `RawMode`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Key {
+    Left,
+    Right,
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Char(char),
+    /// Ctrl-C or Ctrl-D, or the end of the input.
+    Interrupt,
+    Other,
+}
+
+/// Reads one key from `input`, decoding the escape sequences for arrow keys.
+fn read_key(input: &mut impl io::Read) -> io::Result<Key> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    Ok(match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x03 | 0x04 => Key::Interrupt,
+        0x1b => {
+            let mut sequence = [0; 2];
+            input.read_exact(&mut sequence)?;
+            match sequence {
+                [b'[', b'A'] => Key::Up,
+                [b'[', b'B'] => Key::Down,
+                [b'[', b'C'] => Key::Right,
+                [b'[', b'D'] => Key::Left,
+                _ => Key::Other,
+            }
+        }
+        c if c.is_ascii_graphic() || c == b' ' => Key::Char(c as char),
+        _ => Key::Other,
+    })
+}
+
+/// Reads keys from the terminal one at a time without echoing them, using
+/// `stty`, until dropped.
+struct RawMode {
+    /// The previous settings, as printed by `stty -g`.
+    saved: String,
+}
+
+impl RawMode {
+    /// Returns `None` if standard input is not a terminal or `stty` fails.
+    fn enable() -> Option<Self> {
+        if !io::stdin().is_terminal() {
             return None;
         }
-    } else if let Ok(hour) = input.parse() {
-        (hour, 0)
-    } else {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        let saved = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        // Without isig, Ctrl-C is read as a key, so the settings are restored.
+        let status = Command::new("stty")
+            .args(["-icanon", "-echo", "-isig", "min", "1"])
+            .status()
+            .ok()?;
+        status.success().then_some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).status();
+    }
+}
+
+/// The exit code when there is no playlist or entry for the time.
+const EXIT_NO_DATA: i32 = 2;
+/// The exit code when the playlist can't be downloaded.
+const EXIT_NETWORK: i32 = 3;
+/// The exit code when the playlist page can't be parsed.
+const EXIT_PARSE: i32 = 4;
+
+/// Exits with the code for `err`, printing it unless `--quiet` is given.
+fn fail_lookup(matches: &ArgMatches<'_>, err: &Error) -> ! {
+    if !matches.is_present("quiet") {
+        eprintln!("{}", err);
+    }
+    std::process::exit(exit_code(err))
+}
+
+/// Returns the exit code for a failed lookup, so that scripts can tell a gap
+/// in the playlist from a failure to get it.
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::NoData
+        | Error::NoEntry
+        | Error::NotCached(_)
+        | Error::HttpStatus(404, _) => EXIT_NO_DATA,
+        Error::BadUtf8 | Error::BadScrape | Error::BadTime => EXIT_PARSE,
+        _ => EXIT_NETWORK,
+    }
+}
+
+/// Returns the config, reading it the first time. Exits if it is invalid.
+fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let path = wowcpe::config_file().unwrap_or_default();
+        let config = Config::read(&path).unwrap_or_else(|err| {
+            fail(&format!("{}: {}", path.display(), err))
+        });
+        if let Some(format) = &config.format {
+            if let Err(err) = format.parse::<Template>() {
+                fail(&format!("{}: Invalid format: {}", path.display(), err));
+            }
+        }
+        config
+    })
+}
+
+/// Returns the cache to use for lookups, or `None` if caching is disabled.
+fn cache(matches: &ArgMatches<'_>) -> Option<Cache> {
+    if matches.is_present("no_cache") || !config().cache.enabled {
         return None;
+    }
+    config().cache()
+}
+
+/// Returns true if lookups must only use the cache. Exits if the cache is
+/// disabled, since then there would be nothing to look up.
+fn offline(matches: &ArgMatches<'_>) -> bool {
+    if !matches.is_present("offline") {
+        return false;
+    }
+    if cache(matches).is_none() {
+        fail("Cannot use --offline when the cache is disabled");
+    }
+    true
+}
+
+fn lookup(matches: &ArgMatches<'_>, request: &Request) -> Result<Response> {
+    if offline(matches) {
+        return lookup_playlist(matches, request)?.lookup(request);
+    }
+    match cache(matches) {
+        Some(cache) => cache.lookup(request),
+        None => wowcpe::lookup(request),
+    }
+}
+
+fn lookup_playlist(
+    matches: &ArgMatches<'_>,
+    request: &Request,
+) -> Result<Playlist> {
+    playlist_from(cache(matches).as_ref(), offline(matches), request)
+}
+
+/// Looks up the playlist for `request` in `cache`, downloading it if needed
+/// unless `offline` is true. With no cache, it always downloads.
+fn playlist_from(
+    cache: Option<&Cache>,
+    offline: bool,
+    request: &Request,
+) -> Result<Playlist> {
+    match cache {
+        Some(cache) if offline => {
+            let date = request.station_time().date();
+            cache.get(date).ok_or(Error::NotCached(date))
+        }
+        Some(cache) => cache.lookup_playlist(request),
+        None => wowcpe::lookup_playlist(request),
+    }
+}
+
+/// How to print lookups.
+enum Output {
+    Text,
+    Json,
+    Csv,
+    Waybar,
+    Oneline,
+    Xbar,
+    Template(Template),
+    Fields(Vec<String>),
+}
+
+/// Returns the output chosen by flags, or else by the config.
+fn output(matches: &ArgMatches<'_>) -> Output {
+    if let Some(fields) = matches.values_of("fields") {
+        return Output::Fields(fields.map(String::from).collect());
+    }
+    if matches.is_present("json") {
+        return Output::Json;
+    }
+    if matches.is_present("csv") {
+        return Output::Csv;
+    }
+    if matches.is_present("oneline") {
+        return Output::Oneline;
+    }
+    let format = matches
+        .value_of("output")
+        .or(matches.value_of("format"))
+        .or(config().format.as_deref());
+    match format {
+        None | Some("text") => Output::Text,
+        Some("json") => Output::Json,
+        Some("csv") => Output::Csv,
+        Some("waybar") => Output::Waybar,
+        Some("oneline") => Output::Oneline,
+        Some("xbar") => Output::Xbar,
+        // Templates in flags and the config were already validated.
+        Some(template) => Output::Template(template.parse().unwrap()),
+    }
+}
+
+/// Prints `response` as it stood at `now`.
+fn print(matches: &ArgMatches<'_>, response: &Response, now: &DateTime<Local>) {
+    match output(matches) {
+        Output::Text => {}
+        Output::Json if matches.is_present("last_played") => {
+            #[derive(serde::Serialize)]
+            struct WithLastPlayed<'a> {
+                #[serde(flatten)]
+                response: &'a Response,
+                last_played: Option<Play>,
+            }
+            let json = WithLastPlayed {
+                response,
+                last_played: last_played(response),
+            };
+            println!("{}", serde_json::to_string(&json).unwrap());
+            return;
+        }
+        Output::Json => {
+            println!("{}", serde_json::to_string(response).unwrap());
+            return;
+        }
+        Output::Csv => {
+            print_csv_row(&RESPONSE_COLUMNS);
+            print!("{}\r\n", csv_row(&response_values(response)));
+            return;
+        }
+        Output::Fields(fields) => {
+            let values = response_values(response);
+            print_fields(matches, &fields, &RESPONSE_COLUMNS, &values);
+            return;
+        }
+        Output::Waybar => {
+            println!("{}", waybar_json(response, now));
+            return;
+        }
+        Output::Xbar => {
+            print!("{}", xbar_plugin(response));
+            return;
+        }
+        Output::Oneline => {
+            let oneline = &config().oneline;
+            let max_width = match matches.value_of("max_width") {
+                Some(arg) => {
+                    Some(arg.parse().unwrap_or_else(|_| invalid_arg(arg)))
+                }
+                None => oneline.max_width,
+            };
+            let ellipsis = matches
+                .value_of("ellipsis")
+                .or(oneline.ellipsis.as_deref())
+                .unwrap_or("…");
+            println!("{}", oneline_text(response, now, max_width, ellipsis));
+            return;
+        }
+        Output::Template(template) => {
+            println!("{}", template.render(response, now));
+            return;
+        }
+    }
+    for warning in &response.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let style = Style::new(matches);
+    let relative = matches.is_present("relative") || config().relative;
+    let with_last_played = matches.is_present("last_played");
+    if !style.enabled && !relative && !with_last_played {
+        println!("{}", response.display());
+        return;
+    }
+    let field = |value: &Option<String>| {
+        value.as_deref().unwrap_or("<missing>").to_string()
+    };
+    let time = |time: &DateTime<Local>| {
+        time.format("%l:%M %p").to_string().trim().to_string()
+    };
+    let program = &response.program;
+    let mut lines = vec![
+        (
+            "Program",
+            style.paint(program_color(program), program.name()),
+        ),
+        (
+            "Time",
+            format!(
+                "{} - {}",
+                time(&response.start_time),
+                time(&response.end_time)
+            ),
+        ),
+        ("Composer", field(&response.composer)),
+        ("Title", style.paint(BOLD, &field(&response.title))),
+        ("Performers", field(&response.performers)),
+        ("Record Label", field(&response.record_label)),
+    ];
+    if relative {
+        let text = wowcpe::relative_time(
+            &response.start_time,
+            &response.end_time,
+            now,
+        );
+        lines.insert(2, ("", text));
+    }
+    if with_last_played {
+        let text = match last_played(response) {
+            Some(play) => play.to_string(),
+            None => "Not in the cache or archive".to_string(),
+        };
+        lines.push(("Last Played", text));
+    }
+    for (label, value) in lines {
+        println!("{}{}", style.paint(DIM, &format!("{:<14}", label)), value);
+    }
+}
+
+/// Prints a table of entries with their local start time, composer, and
+/// title, and also their date and program if `with_dates` is true. Templates
+/// only apply to single responses, so they print the table.
+fn print_entries(
+    matches: &ArgMatches<'_>,
+    entries: &[Entry],
+    with_dates: bool,
+) {
+    match output(matches) {
+        Output::Text
+        | Output::Waybar
+        | Output::Oneline
+        | Output::Xbar
+        | Output::Template(_) => {}
+        Output::Json => {
+            println!("{}", serde_json::to_string(entries).unwrap());
+            return;
+        }
+        Output::Csv => {
+            print_csv_row(&ENTRY_COLUMNS);
+            for entry in entries {
+                print!("{}\r\n", entry_csv_row(entry));
+            }
+            return;
+        }
+        Output::Fields(fields) => {
+            for entry in entries {
+                let values = entry_values(entry);
+                print_fields(matches, &fields, &ENTRY_COLUMNS, &values);
+            }
+            return;
+        }
+    }
+    if entries.is_empty() {
+        return;
+    }
+    let field = |value: &Option<String>| {
+        value.as_deref().unwrap_or("<missing>").to_string()
+    };
+    let mut headers = vec!["Time", "Composer", "Title"];
+    if with_dates {
+        headers.splice(0..0, ["Date", "Program"]);
+    }
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            let start = entry.start_time.with_timezone(&Local);
+            let mut row = vec![
+                start.format("%l:%M %p").to_string(),
+                field(&entry.composer),
+                field(&entry.title),
+            ];
+            if with_dates {
+                let date = start.format("%Y-%m-%d").to_string();
+                row.splice(0..0, [date, entry.program.name().to_string()]);
+            }
+            row
+        })
+        .collect();
+    print_table(matches, &headers, &rows);
+}
+
+/// Prints `rows` in aligned columns under a dimmed header, with the last
+/// column (the title) in bold.
+fn print_table(
+    matches: &ArgMatches<'_>,
+    headers: &[&str],
+    rows: &[Vec<String>],
+) {
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .chain([headers[column].len()])
+                .max()
+                .unwrap()
+        })
+        .collect();
+    // Pads every column except the last.
+    let line = |cells: Vec<String>| {
+        let mut line = String::new();
+        let last = cells.len() - 1;
+        for (i, cell) in cells.into_iter().enumerate() {
+            if i == last {
+                line.push_str(&cell);
+            } else {
+                line.push_str(&format!("{:<1$}  ", cell, widths[i]));
+            }
+        }
+        line
     };
+    let style = Style::new(matches);
+    let header = line(headers.iter().map(|h| h.to_string()).collect());
+    println!("{}", style.paint(DIM, &header));
+    for row in rows {
+        let mut cells = row.clone();
+        if let Some(last) = cells.last_mut() {
+            *last = style.paint(BOLD, last);
+        }
+        println!("{}", line(cells));
+    }
+}
 
-    Local::now()
-        .with_hour(hour + hour_offset)
-        .and_then(|t| t.with_minute(minute))
-        .and_then(|t| t.with_nanosecond(0))
+/// Styling for human-readable output with ANSI escape codes.
+struct Style {
+    enabled: bool,
 }
 
-fn print_response(r: &Response) {
-    let fmt = "%l:%M %p";
-    let start = r.start_time.time().format(fmt).to_string();
-    let end = r.end_time.time().format(fmt).to_string();
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const MAGENTA: &str = "35";
+const CYAN: &str = "36";
+
+impl Style {
+    /// Uses color if `--color` or the config says to, or by default if stdout
+    /// is a terminal and the `NO_COLOR` environment variable is not set (see
+    /// no-color.org).
+    fn new(matches: &ArgMatches<'_>) -> Self {
+        let when = match matches.occurrences_of("color") {
+            0 => config().color.as_deref(),
+            _ => matches.value_of("color"),
+        };
+        let enabled = match when {
+            Some("always") => true,
+            Some("never") => false,
+            _ => {
+                let no_color = env::var_os("NO_COLOR");
+                no_color.is_none_or(|value| value.is_empty())
+                    && io::stdout().is_terminal()
+            }
+        };
+        Style { enabled }
+    }
 
-    println!("Program       {}", r.program);
-    println!("Time          {} - {}", start.trim(), end.trim());
-    println!("Composer      {}", r.composer);
-    println!("Title         {}", r.title);
-    println!("Performers    {}", r.performers);
-    println!("Record Label  {}", r.record_label);
+    /// Wraps `text` in the SGR escape `code`, if color is enabled.
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
 }
 
-fn fail(message: &str) -> ! {
-    eprintln!("{}", message);
-    std::process::exit(1);
+/// Returns the SGR code for coloring `program`: cyan for the regular lineup,
+/// yellow for specialty programs, and magenta for special broadcasts.
+fn program_color(program: &Program) -> &'static str {
+    match program {
+        Program::Special(_) => MAGENTA,
+        _ if program.is_regular() => CYAN,
+        _ => YELLOW,
+    }
 }
 
-fn invalid_arg(arg: &str) -> ! {
-    eprintln!("{}: Invalid argument", arg);
-    eprintln!("For more information try --help");
-    std::process::exit(1)
+/// Returns true if the output is for programs rather than people.
+fn machine_readable(matches: &ArgMatches<'_>) -> bool {
+    matches!(
+        output(matches),
+        Output::Json
+            | Output::Csv
+            | Output::Waybar
+            | Output::Oneline
+            | Output::Xbar
+            | Output::Fields(_)
+    )
+}
+
+/// Returns the JSON that Waybar and i3status-rust custom blocks expect: the
+/// piece as the text, every field in the tooltip, the program as the class,
+/// and how much of the piece has played as the percentage.
+fn waybar_json(
+    response: &Response,
+    now: &DateTime<Local>,
+) -> serde_json::Value {
+    let program = response.program.name();
+    let text = match (&response.composer, &response.title) {
+        (Some(composer), Some(title)) => format!("{}: {}", composer, title),
+        (Some(name), None) | (None, Some(name)) => name.clone(),
+        (None, None) => program.to_string(),
+    };
+    let time = |time: &DateTime<Local>| {
+        time.format("%l:%M %p").to_string().trim().to_string()
+    };
+    let mut tooltip = vec![
+        format!("Program: {}", program),
+        format!(
+            "Time: {} - {}",
+            time(&response.start_time),
+            time(&response.end_time)
+        ),
+    ];
+    let fields = [
+        ("Composer", &response.composer),
+        ("Title", &response.title),
+        ("Performers", &response.performers),
+        ("Record Label", &response.record_label),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            tooltip.push(format!("{}: {}", label, value));
+        }
+    }
+    let length = (response.end_time - response.start_time).num_seconds();
+    let elapsed = (*now - response.start_time).num_seconds();
+    let percentage = (elapsed * 100).checked_div(length).unwrap_or(0);
+    serde_json::json!({
+        "text": pango_escape(&text),
+        "tooltip": pango_escape(&tooltip.join("\n")),
+        "alt": program,
+        "class": css_class(program),
+        "percentage": percentage.clamp(0, 100),
+    })
+}
+
+/// Returns a compact line like "Concert Hall · Brahms: Symphony No. 2 (23m
+/// left)", with the composer's last name, shortened to `max_width` characters
+/// by cutting the piece short with `ellipsis`.
+fn oneline_text(
+    response: &Response,
+    now: &DateTime<Local>,
+    max_width: Option<usize>,
+    ellipsis: &str,
+) -> String {
+    let piece = short_piece(&response.composer, &response.title);
+    let prefix = response.program.name().to_string();
+    let prefix = if piece.is_empty() {
+        prefix
+    } else {
+        prefix + " · "
+    };
+    let remaining = (response.end_time - *now).num_seconds().max(0);
+    let suffix = format!(" ({}m left)", (remaining + 59) / 60);
+    let line = format!("{}{}{}", prefix, piece, suffix);
+    let max_width = match max_width {
+        Some(max_width) if line.chars().count() > max_width => max_width,
+        _ => return line,
+    };
+    let fixed = prefix.chars().count() + suffix.chars().count();
+    match max_width.checked_sub(fixed) {
+        // Keep the program and time left if there is room for some of the
+        // piece, and otherwise cut the whole line.
+        Some(room) if room > ellipsis.chars().count() => {
+            format!("{}{}{}", prefix, truncate(&piece, room, ellipsis), suffix)
+        }
+        _ => truncate(&line, max_width, ellipsis),
+    }
+}
+
+/// Returns the piece like "Brahms: Symphony No. 2", with the composer's last
+/// name, or an empty string if there is no composer or title.
+fn short_piece(composer: &Option<String>, title: &Option<String>) -> String {
+    let surname = |composer: &String| {
+        composer
+            .split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_string()
+    };
+    match (composer, title) {
+        (Some(composer), Some(title)) => {
+            format!("{}: {}", surname(composer), title)
+        }
+        (Some(composer), None) => surname(composer),
+        (None, Some(title)) => title.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+/// Shortens `text` to at most `max_width` characters, ending in `ellipsis` if
+/// it was too long. Leaves out the ellipsis if there is no room for it and at
+/// least one character of `text`.
+fn truncate(text: &str, max_width: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= max_width {
+        return text.to_string();
+    }
+    let keep = match max_width.checked_sub(ellipsis.chars().count()) {
+        Some(keep) if keep > 0 => keep,
+        _ => return text.chars().take(max_width).collect(),
+    };
+    let mut short: String = text.chars().take(keep).collect();
+    short.truncate(short.trim_end().len());
+    short + ellipsis
+}
+
+/// Returns the output of an xbar or SwiftBar plugin: the piece in the menu bar,
+/// and a dropdown with every field and links to the playlist and stream.
+fn xbar_plugin(response: &Response) -> String {
+    let title = match short_piece(&response.composer, &response.title) {
+        piece if piece.is_empty() => response.program.name().to_string(),
+        piece => truncate(&piece, 40, "…"),
+    };
+    let time = |time: &DateTime<Local>| {
+        time.format("%l:%M %p").to_string().trim().to_string()
+    };
+    let mut lines = vec![
+        xbar_text(&title),
+        "---".to_string(),
+        format!("Program: {}", xbar_text(response.program.name())),
+        format!(
+            "Time: {} - {}",
+            time(&response.start_time),
+            time(&response.end_time)
+        ),
+    ];
+    let fields = [
+        ("Composer", &response.composer),
+        ("Title", &response.title),
+        ("Performers", &response.performers),
+        ("Record Label", &response.record_label),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            lines.push(format!("{}: {}", label, xbar_text(value)));
+        }
+    }
+    let playlist = match &response.source_url {
+        Some(url) => url.clone(),
+        None => wowcpe::playlist_url(response.start_time),
+    };
+    lines.extend([
+        "---".to_string(),
+        format!("Open Playlist | href={}", playlist),
+        format!("Listen Live | href={}", STREAMS[0].url),
+        "Refresh | refresh=true".to_string(),
+    ]);
+    lines.iter().map(|line| line.clone() + "\n").collect()
+}
+
+/// Makes `text` safe for a line of xbar output, where a pipe starts the
+/// parameters and leading dashes make a submenu.
+fn xbar_text(text: &str) -> String {
+    text.replace('|', "│").trim_start_matches('-').to_string()
+}
+
+/// Escapes `text` for Pango markup, which Waybar uses for the text and tooltip
+/// and notification servers use for the body.
+fn pango_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Returns a CSS class name for `program`, e.g., "concert-hall".
+fn css_class(program: &str) -> String {
+    let words: Vec<String> = program
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("-")
+}
+
+/// The CSV columns for a response, named like the JSON fields.
+const RESPONSE_COLUMNS: [&str; 11] = [
+    "program",
+    "start_time",
+    "end_time",
+    "program_start",
+    "program_end",
+    "composer",
+    "title",
+    "performers",
+    "record_label",
+    "source_url",
+    "fetched_at",
+];
+
+/// The CSV columns for a playlist entry, named like the JSON fields.
+const ENTRY_COLUMNS: [&str; 8] = [
+    "index",
+    "program",
+    "start_time",
+    "end_time",
+    "composer",
+    "title",
+    "performers",
+    "record_label",
+];
+
+/// The fields for `--fields`: the columns of responses and entries.
+const FIELDS: [&str; 12] = [
+    "index",
+    "program",
+    "start_time",
+    "end_time",
+    "program_start",
+    "program_end",
+    "composer",
+    "title",
+    "performers",
+    "record_label",
+    "source_url",
+    "fetched_at",
+];
+
+/// Returns the values of `response` for `RESPONSE_COLUMNS`.
+fn response_values(response: &Response) -> Vec<String> {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    vec![
+        response.program.name().to_string(),
+        response.start_time.to_rfc3339(),
+        response.end_time.to_rfc3339(),
+        response.program_start.to_rfc3339(),
+        response.program_end.to_rfc3339(),
+        text(&response.composer),
+        text(&response.title),
+        text(&response.performers),
+        text(&response.record_label),
+        text(&response.source_url),
+        response
+            .fetched_at
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+    ]
+}
+
+/// Returns the values of `entry` for `ENTRY_COLUMNS`.
+fn entry_values(entry: &Entry) -> Vec<String> {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    vec![
+        entry.index.to_string(),
+        entry.program.name().to_string(),
+        entry.start_time.to_rfc3339(),
+        entry.end_time.to_rfc3339(),
+        text(&entry.composer),
+        text(&entry.title),
+        text(&entry.performers),
+        text(&entry.record_label),
+    ]
+}
+
+fn entry_csv_row(entry: &Entry) -> String {
+    csv_row(&entry_values(entry))
+}
+
+/// Prints the `values` of the named `fields`, given the `columns` the values
+/// are for, on one line separated by tabs, or with `--print0`, each ending
+/// with a NUL byte.
+fn print_fields(
+    matches: &ArgMatches<'_>,
+    fields: &[String],
+    columns: &[&str],
+    values: &[String],
+) {
+    if matches.is_present("print0") {
+        print!("{}", nul_fields(fields, columns, values));
+    } else {
+        println!("{}", select_fields(fields, columns, values));
+    }
+}
+
+/// Returns the value of `field`, or nothing if it isn't in `columns`.
+fn field_value<'a>(
+    field: &str,
+    columns: &[&str],
+    values: &'a [String],
+) -> &'a str {
+    match columns.iter().position(|column| *column == field) {
+        Some(i) => &values[i],
+        None => "",
+    }
+}
+
+/// Joins the values of `fields` with tabs. Tabs and line breaks in values
+/// become spaces, so each record is one line.
+fn select_fields(
+    fields: &[String],
+    columns: &[&str],
+    values: &[String],
+) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            field_value(field, columns, values).replace(['\t', '\r', '\n'], " ")
+        })
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Returns the values of `fields` exactly as they are, each ending with a NUL
+/// byte, which can't appear in them.
+fn nul_fields(
+    fields: &[String],
+    columns: &[&str],
+    values: &[String],
+) -> String {
+    fields
+        .iter()
+        .map(|field| format!("{}\0", field_value(field, columns, values)))
+        .collect()
+}
+
+fn print_csv_row(fields: &[&str]) {
+    print!("{}\r\n", csv_row(fields));
+}
+
+/// Joins `fields` into a CSV record (RFC 4180), quoting fields that contain
+/// commas, quotes, or line breaks.
+fn csv_row(fields: &[impl AsRef<str>]) -> String {
+    let quote = |field: &str| {
+        if field.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+    let fields: Vec<String> =
+        fields.iter().map(|field| quote(field.as_ref())).collect();
+    fields.join(",")
+}
+
+/// How long to wait after a piece ends before looking up the next one, to give
+/// the station time to update the playlist.
+const WATCH_DELAY: Duration = Duration::from_secs(5);
+
+/// The longest to wait between lookups. The latest piece's end time isn't
+/// known until the next one starts, so this is how often to check for it.
+const WATCH_POLL: Duration = Duration::from_secs(60);
+
+/// How often `--watch` redraws the progress bar.
+const PROGRESS_TICK: Duration = Duration::from_secs(1);
+
+/// The width of the progress bar in characters, not counting the times.
+const PROGRESS_WIDTH: usize = 40;
+
+fn watch(matches: &ArgMatches<'_>) -> ! {
+    let clear = !machine_readable(matches) && io::stdout().is_terminal();
+    // The start and end of the current piece, for the progress bar. Holding
+    // the lock while printing keeps the bar from interrupting the text.
+    let piece = Arc::new(Mutex::new(None));
+    if clear && matches!(output(matches), Output::Text) {
+        let piece = Arc::clone(&piece);
+        thread::spawn(move || draw_progress(&piece));
+    }
+    on_each_piece(matches, |response, now| {
+        let mut piece = piece.lock().unwrap();
+        if clear {
+            // Clear the screen and move the cursor to the top.
+            print!("\x1b[2J\x1b[H");
+        }
+        print(matches, response, now);
+        *piece = Some((response.start_time, response.end_time));
+    })
+}
+
+/// Draws a progress bar for `piece` below the text every `PROGRESS_TICK`.
+fn draw_progress(
+    piece: &Mutex<Option<(DateTime<Local>, DateTime<Local>)>>,
+) -> ! {
+    loop {
+        if let Some((start, end)) = *piece.lock().unwrap() {
+            let bar = progress_bar(&start, &end, &Local::now(), PROGRESS_WIDTH);
+            // Return to the start of the line and clear it.
+            print!("\r\x1b[K{}", bar);
+            let _ = io::stdout().flush();
+        }
+        thread::sleep(PROGRESS_TICK);
+    }
+}
+
+/// Returns a bar `width` characters wide showing how far `now` is from `start`
+/// to `end`, followed by the elapsed and total time, e.g., "█████░░░░░ 4:10 /
+/// 8:20".
+fn progress_bar(
+    start: &DateTime<Local>,
+    end: &DateTime<Local>,
+    now: &DateTime<Local>,
+    width: usize,
+) -> String {
+    let total = (*end - *start).num_seconds().max(0);
+    let elapsed = (*now - *start).num_seconds().clamp(0, total);
+    let filled = match total {
+        0 => width,
+        _ => (elapsed as usize * width) / total as usize,
+    };
+    format!(
+        "{}{} {} / {}",
+        "█".repeat(filled),
+        "░".repeat(width - filled),
+        clock(elapsed),
+        clock(total)
+    )
+}
+
+/// Formats `seconds` like a media player, e.g., "4:05" or "1:02:05".
+fn clock(seconds: i64) -> String {
+    let (hours, minutes, seconds) =
+        (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match hours {
+        0 => format!("{}:{:02}", minutes, seconds),
+        _ => format!("{}:{:02}:{:02}", hours, minutes, seconds),
+    }
+}
+
+fn notify(matches: &ArgMatches<'_>) -> ! {
+    let config = config();
+    let remind = config.notify.remind_minutes.unwrap_or(5);
+    let has_favorites = !config.favorite_composers.is_empty()
+        || !config.favorite_works.is_empty();
+    if has_favorites && remind > 0 {
+        let cache = cache(matches);
+        let offline = offline(matches);
+        let advance = chrono::Duration::minutes(remind as i64);
+        thread::spawn(move || remind_favorites(cache, offline, advance));
+    }
+    remind_programs(|_, _, message| {
+        if let Err(err) = show_notification("Coming up on WCPE", message) {
+            eprintln!("Failed to show notification: {}", err);
+        }
+    });
+    on_each_piece(matches, |response, _| {
+        let composer = response.composer.as_deref().unwrap_or_default();
+        let title = response.title.as_deref().unwrap_or_default();
+        if config.notify.favorites_only
+            && !config.is_favorite_piece(composer, title)
+        {
+            return;
+        }
+        let summary = match &response.composer {
+            Some(composer) => composer.clone(),
+            None => response.program.name().to_string(),
+        };
+        let body = response.title.clone().unwrap_or_default();
+        if let Err(err) = show_notification(&summary, &body) {
+            eprintln!("Failed to show notification: {}", err);
+        }
+    })
+}
+
+/// Shows a notification `advance` before each favorite piece starts, for the
+/// pieces the playlist lists ahead of time. It checks every `WATCH_POLL`,
+/// since upcoming pieces can be added to the playlist at any time.
+fn remind_favorites(
+    cache: Option<Cache>,
+    offline: bool,
+    advance: chrono::Duration,
+) -> ! {
+    let config = config();
+    let mut reminded = Vec::new();
+    loop {
+        let now = current_time();
+        let request = Request { time: now };
+        let playlist = playlist_from(cache.as_ref(), offline, &request);
+        for entry in playlist.iter().flat_map(|p| p.after(now)) {
+            if entry.start_time > now + advance {
+                break;
+            }
+            let composer = entry.composer.as_deref().unwrap_or_default();
+            let title = entry.title.as_deref().unwrap_or_default();
+            if reminded.contains(&entry.start_time)
+                || !config.is_favorite_piece(composer, title)
+            {
+                continue;
+            }
+            reminded.push(entry.start_time);
+            if let Err(err) =
+                show_notification("Coming up on WCPE", &reminder(entry))
+            {
+                eprintln!("Failed to show notification: {}", err);
+            }
+        }
+        thread::sleep(WATCH_POLL);
+    }
+}
+
+/// Starts a thread for each `[[reminders]]` section in the config, which
+/// calls `deliver` with the program, its start time, and a message like
+/// "Thursday Night Opera House starts in 30 minutes" before each airing.
+fn remind_programs(
+    deliver: impl Fn(&Program, &DateTime<Local>, &str) + Clone + Send + 'static,
+) {
+    for reminder in &config().reminders {
+        let program = reminder.program().unwrap_or_else(|| {
+            fail(&format!(
+                "{}: Unknown program in [[reminders]]",
+                reminder.program
+            ))
+        });
+        let minutes = reminder.minutes.unwrap_or(30);
+        let advance = chrono::Duration::minutes(minutes as i64);
+        let deliver = deliver.clone();
+        thread::spawn(move || loop {
+            // This skips an airing that starts sooner than `advance`, and
+            // after waking at the start, the one on the air.
+            let after = Local::now() + advance;
+            let start = match wowcpe::next_occurrence(&program, &after) {
+                Some((start, _)) => start,
+                None => return,
+            };
+            sleep_until(start - advance);
+            deliver(&program, &start, &program_reminder(&program, minutes));
+            sleep_until(start);
+        });
+    }
+}
+
+/// Sleeps until `time`, in steps of at most `WATCH_POLL` so that the clock
+/// changing, e.g., after waking from sleep, doesn't delay it.
+fn sleep_until(time: DateTime<Local>) {
+    while let Ok(left) = (time - Local::now()).to_std() {
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(WATCH_POLL));
+    }
+}
+
+/// Returns the message for a reminder that `program` starts in `minutes`.
+fn program_reminder(program: &Program, minutes: u64) -> String {
+    let name = program.name();
+    match minutes {
+        0 => format!("{} is starting", name),
+        1 => format!("{} starts in 1 minute", name),
+        _ => format!("{} starts in {} minutes", name, minutes),
+    }
+}
+
+/// Returns a reminder as JSON, for `mqtt` and `webhook`.
+fn reminder_json(
+    program: &Program,
+    start: &DateTime<Local>,
+    message: &str,
+) -> String {
+    serde_json::json!({
+        "program": program.name(),
+        "start_time": start,
+        "message": message,
+    })
+    .to_string()
+}
+
+/// Returns the text of a reminder for `entry`, like "Sibelius: Violin
+/// Concerto starts at 8:04 PM".
+fn reminder(entry: &Entry) -> String {
+    let piece = match short_piece(&entry.composer, &entry.title) {
+        piece if piece.is_empty() => entry.program.name().to_string(),
+        piece => piece,
+    };
+    let start = entry.start_time.to_local().format("%l:%M %p").to_string();
+    format!("{} starts at {}", piece, start.trim())
+}
+
+/// The players to try when none is configured, with flags to play audio only.
+const DEFAULT_PLAYERS: [&str; 2] = ["mpv --no-video", "vlc --intf dummy"];
+
+/// Plays the live stream with the configured player, or prints its URL.
+fn play(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let stream =
+        match sub.value_of("stream").or(config().play.stream.as_deref()) {
+            Some(id) => Stream::find(id)
+                .unwrap_or_else(|| fail(&format!("{}: Unknown stream", id))),
+            None => &STREAMS[0],
+        };
+    if sub.is_present("print_url") {
+        println!("{}", stream.url);
+        std::process::exit(0);
+    }
+    // The player's own output would garble the list of pieces.
+    let quiet = sub.is_present("watch");
+    let player = sub.value_of("player").or(config().play.player.as_deref());
+    let mut child = match player {
+        Some(player) => spawn_player(player, stream.url, quiet)
+            .unwrap_or_else(|err| fail(&format!("{}: {}", player, err))),
+        None => DEFAULT_PLAYERS
+            .iter()
+            .find_map(|player| match spawn_player(player, stream.url, quiet) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                result => Some(result),
+            })
+            .unwrap_or_else(|| {
+                fail("Cannot find mpv or vlc. Choose a player with --player")
+            })
+            .unwrap_or_else(|err| fail(&format!("Failed to play: {}", err))),
+    };
+    if quiet {
+        thread::spawn(move || exit_with(child.wait()));
+        watch(matches);
+    }
+    exit_with(child.wait())
+}
+
+/// Prints the live streams, e.g., to pick a lower bitrate.
+fn streams(matches: &ArgMatches<'_>) -> ! {
+    match output(matches) {
+        Output::Json => println!("{}", serde_json::to_string(STREAMS).unwrap()),
+        Output::Csv => {
+            print_csv_row(&["id", "format", "bitrate", "url"]);
+            for stream in STREAMS {
+                let bitrate = stream.bitrate.to_string();
+                print_csv_row(&[
+                    stream.id,
+                    stream.format,
+                    &bitrate,
+                    stream.url,
+                ]);
+            }
+        }
+        Output::Text
+        | Output::Waybar
+        | Output::Oneline
+        | Output::Xbar
+        | Output::Template(_)
+        | Output::Fields(_) => {
+            let rows: Vec<Vec<String>> = STREAMS
+                .iter()
+                .map(|stream| {
+                    vec![
+                        stream.id.to_string(),
+                        stream.format.to_string(),
+                        format!("{} kbps", stream.bitrate),
+                        stream.url.to_string(),
+                    ]
+                })
+                .collect();
+            print_table(matches, &["ID", "Format", "Bitrate", "URL"], &rows);
+        }
+    }
+    std::process::exit(0)
+}
+
+/// Starts the `player` command, adding `url` as the last argument.
+fn spawn_player(
+    player: &str,
+    url: &str,
+    quiet: bool,
+) -> io::Result<std::process::Child> {
+    let mut words = player.split_whitespace();
+    let program = words.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Empty player command")
+    })?;
+    let mut command = Command::new(program);
+    command.args(words).arg(url);
+    if quiet {
+        command.stdout(Stdio::null()).stderr(Stdio::null());
+    }
+    command.spawn()
+}
+
+/// Exits with the status of the player.
+fn exit_with(status: io::Result<std::process::ExitStatus>) -> ! {
+    match status {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(err) => fail(&format!("Failed to play: {}", err)),
+    }
+}
+
+/// Publishes each piece as JSON to the MQTT broker, retained so that new
+/// subscribers get the current piece right away.
+fn mqtt(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let settings = &config().mqtt;
+    let broker = sub
+        .value_of("broker")
+        .or(settings.broker.as_deref())
+        .unwrap_or("localhost:1883")
+        .to_string();
+    let topic = sub
+        .value_of("topic")
+        .or(settings.topic.as_deref())
+        .unwrap_or("wowcpe/now")
+        .to_string();
+    let client_id = settings.client_id.as_deref().unwrap_or("wowcpe");
+    let credentials = settings
+        .username
+        .as_deref()
+        .map(|user| (user, settings.password.as_deref().unwrap_or_default()));
+    let tls = sub.is_present("tls") || settings.tls;
+    if credentials.is_some() && !tls {
+        eprintln!(
+            "warning: sending the MQTT password unencrypted; use --tls or set \
+             tls = true in [mqtt] if the broker supports it"
+        );
+    }
+    let mqtt = Mqtt::connect(&broker, client_id, credentials, tls)
+        .unwrap_or_else(|err| {
+            fail(&format!("Failed to connect to {}: {}", broker, err))
+        });
+    let mqtt = Arc::new(mqtt);
+    let publish = move |topic: &str, payload: &[u8], retain: bool| {
+        if let Err(err) = mqtt.publish(topic, payload, retain) {
+            eprintln!("Failed to publish to {}: {}", topic, err);
+        }
+    };
+    // Reminders go to a subtopic, and aren't retained since they expire.
+    let reminder_topic = format!("{}/reminder", topic);
+    let remind = publish.clone();
+    remind_programs(move |program, start, message| {
+        let payload = reminder_json(program, start, message);
+        remind(&reminder_topic, payload.as_bytes(), false);
+    });
+    on_each_piece(matches, |response, _| {
+        publish(&topic, &serde_json::to_vec(response).unwrap(), true);
+    })
+}
+
+/// Posts each piece as JSON to the webhooks in the config and `--url`.
+fn webhook(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let mut webhooks: Vec<Webhook> =
+        config().webhooks.iter().map(|w| w.webhook()).collect();
+    webhooks
+        .extend(sub.values_of("url").into_iter().flatten().map(Webhook::new));
+    if webhooks.is_empty() {
+        fail("No webhooks. Use --url or add [[webhooks]] to the config");
+    }
+    let reminded = webhooks.clone();
+    remind_programs(move |program, start, message| {
+        let body = reminder_json(program, start, message);
+        for webhook in &reminded {
+            if let Err(err) = webhook.send_event("reminder", &body) {
+                eprintln!("Failed to post to {}: {}", webhook.url(), err);
+            }
+        }
+    });
+    on_each_piece(matches, |response, _| {
+        let body = serde_json::to_string(response).unwrap();
+        // Post to each on its own thread, so that retries don't hold up the
+        // others or the next lookup.
+        for webhook in &webhooks {
+            let (webhook, body) = (webhook.clone(), body.clone());
+            thread::spawn(move || {
+                if let Err(err) = webhook.send(&body) {
+                    eprintln!("Failed to post to {}: {}", webhook.url(), err);
+                }
+            });
+        }
+    })
+}
+
+/// Submits each piece to the scrobblers in the config as it plays.
+fn scrobble(matches: &ArgMatches<'_>) -> ! {
+    let scrobblers = config().scrobblers();
+    if scrobblers.is_empty() {
+        fail(
+            "No scrobbler is configured. Add a [lastfm] or [listenbrainz] \
+             section to the config",
+        );
+    }
+    let began = current_time();
+    let mut playing: Option<Response> = None;
+    on_each_piece(matches, |response, _| {
+        if let Some(last) = playing.replace(response.clone()) {
+            // Only scrobble pieces heard for at least half their length, which
+            // only excludes the one playing when this started.
+            let length = response.start_time - last.start_time;
+            let heard = response.start_time - last.start_time.max(began);
+            if heard * 2 >= length {
+                for scrobbler in &scrobblers {
+                    if let Err(err) = scrobbler.scrobble(&last) {
+                        eprintln!("Failed to scrobble: {}", err);
+                    }
+                }
+            }
+        }
+        for scrobbler in &scrobblers {
+            if let Err(err) = scrobbler.now_playing(response) {
+                eprintln!("Failed to update now playing: {}", err);
+            }
+        }
+    })
+}
+
+/// Walks the user through authorizing Last.fm, and prints the session key.
+fn lastfm_login() -> ! {
+    let lastfm = &config().lastfm;
+    let (key, secret) = match (&lastfm.api_key, &lastfm.api_secret) {
+        (Some(key), Some(secret)) => (key, secret),
+        _ => fail("Add api_key and api_secret to [lastfm] in the config first"),
+    };
+    let token = LastFm::auth_token(key, secret)
+        .unwrap_or_else(|err| fail(&format!("Failed to get a token: {}", err)));
+    println!("Allow access at this page, and then press Enter:");
+    println!("{}", LastFm::auth_url(key, &token));
+    let mut line = String::new();
+    if let Err(err) = io::stdin().read_line(&mut line) {
+        fail(&err.to_string());
+    }
+    match LastFm::session_key(key, secret, &token) {
+        Ok(session) => {
+            println!("Add this to [lastfm] in the config:");
+            println!("session_key = \"{}\"", session);
+            std::process::exit(0)
+        }
+        Err(err) => fail(&format!("Failed to get a session key: {}", err)),
+    }
+}
+
+/// Looks up what is playing until the process is killed, calling `f` each time
+/// a new piece starts (and for the piece playing at first).
+fn on_each_piece(
+    matches: &ArgMatches<'_>,
+    mut f: impl FnMut(&Response, &DateTime<Local>),
+) -> ! {
+    let mut last = None;
+    loop {
+        let now = current_time();
+        let wait = match lookup(matches, &Request { time: now }) {
+            Ok(response) => {
+                // Compare start times, since refreshing the playlist changes
+                // other fields like fetched_at.
+                if last != Some(response.start_time) {
+                    f(&response, &now);
+                }
+                let remaining = (response.end_time - now).to_std();
+                let wait = remaining.unwrap_or_default() + WATCH_DELAY;
+                last = Some(response.start_time);
+                wait.min(WATCH_POLL)
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                WATCH_POLL
+            }
+        };
+        thread::sleep(wait);
+    }
+}
+
+/// Shows a desktop notification. On Linux and the BSDs, the notification
+/// server may interpret the body as markup, so it is escaped.
+fn show_notification(summary: &str, body: &str) -> io::Result<()> {
+    let body = if cfg!(all(unix, not(target_os = "macos"))) {
+        pango_escape(body)
+    } else {
+        body.to_string()
+    };
+    notify_rust::Notification::new()
+        .appname("WOWCPE")
+        .summary(summary)
+        .body(&body)
+        .show()
+        .map(drop)
+        .map_err(io::Error::other)
+}
+
+/// Prints tracing events to standard error for `--verbose`, after the spans
+/// they occurred in, e.g., "DEBUG download{url=…}: downloaded code=200".
+struct Logger {
+    level: Level,
+    next_id: AtomicU64,
+    /// The description of each open span, and how many handles it has.
+    spans: Mutex<HashMap<u64, (String, usize)>>,
+}
+
+thread_local! {
+    /// The spans each thread is in, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+impl Logger {
+    fn new(level: Level) -> Self {
+        Logger {
+            level,
+            next_id: AtomicU64::new(1),
+            spans: Mutex::default(),
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let name = span.metadata().name();
+        let text = format!("{}{{{}}}", name, fields.fields.trim_start());
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans.lock().unwrap().insert(id, (text, 1));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let spans = self.spans.lock().unwrap();
+        let context: String = ENTERED.with(|entered| {
+            entered
+                .borrow()
+                .iter()
+                .filter_map(|id| spans.get(id))
+                .map(|(text, _)| format!("{}: ", text))
+                .collect()
+        });
+        eprintln!(
+            "{:>5} {}{}{}",
+            event.metadata().level(),
+            context,
+            fields.message,
+            fields.fields
+        );
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, _: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        if let Some((_, handles)) =
+            self.spans.lock().unwrap().get_mut(&id.into_u64())
+        {
+            *handles += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        match spans.get_mut(&id.into_u64()) {
+            Some((_, handles)) if *handles > 1 => {
+                *handles -= 1;
+                false
+            }
+            Some(_) => {
+                spans.remove(&id.into_u64());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Collects the fields of an event or span as text.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    /// The other fields, like " code=200 bytes=1234".
+    fields: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn daemon(matches: &ArgMatches<'_>) -> ! {
+    let socket = match matches.value_of_os("socket") {
+        Some(path) => PathBuf::from(path),
+        None => match wowcpe::cache_dir() {
+            Some(dir) => dir.join("daemon.sock"),
+            None => fail("Cannot determine the cache directory"),
+        },
+    };
+    let daemon = wowcpe::Daemon::new(wowcpe::Client::new());
+    if let Some(arg) = matches.value_of("interval") {
+        let secs = arg.parse().unwrap_or_else(|_| invalid_arg(arg));
+        daemon.set_refresh_interval(Duration::from_secs(secs));
+    }
+    if let Some(dir) = socket.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    match daemon.serve(&socket) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => fail(&format!("{}: {}", socket.display(), err)),
+    }
+}
+
+fn serve(matches: &ArgMatches<'_>) -> ! {
+    let addr = matches.value_of("addr").unwrap();
+    let cache = config()
+        .cache()
+        .unwrap_or_else(|| fail("Cannot determine the cache directory"));
+    let listener = TcpListener::bind(addr)
+        .unwrap_or_else(|err| fail(&format!("{}: {}", addr, err)));
+    match wowcpe::Server::new(cache).serve(listener) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => fail(&format!("{}: {}", addr, err)),
+    }
+}
+
+/// Finds when the piece in `response` last played before it started this
+/// time, from the cache and archive.
+fn last_played(response: &Response) -> Option<Play> {
+    let (composer, title) = match (&response.composer, &response.title) {
+        (Some(composer), Some(title)) => (composer, title),
+        _ => return None,
+    };
+    let until = StationTime::from(response.start_time).date();
+    let entries = cached_entries(None, Some(until));
+    wowcpe::last_played(composer, title, &response.start_time, &entries)
+}
+
+/// Returns the entries cached or archived for `since` to `until` (inclusive),
+/// oldest first, without downloading anything. Days in both come from the
+/// cache, which may be fresher.
+fn cached_entries(
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Vec<Entry> {
+    let cache = config()
+        .cache()
+        .unwrap_or_else(|| fail("Cannot determine the cache directory"));
+    let archive = Cache::open_archive();
+    let dates: BTreeSet<NaiveDate> = cache
+        .dates()
+        .into_iter()
+        .chain(archive.iter().flat_map(Cache::dates))
+        .collect();
+    dates
+        .into_iter()
+        .filter(|date| since.is_none_or(|since| *date >= since))
+        .filter(|date| until.is_none_or(|until| *date <= until))
+        .filter_map(|date| {
+            cache.get(date).or_else(|| archive.as_ref()?.get(date))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Parses the YYYY-MM-DD date in argument `name`, if it was given.
+fn date_arg(matches: &ArgMatches<'_>, name: &str) -> Option<NaiveDate> {
+    let arg = matches.value_of(name)?;
+    Some(arg.parse().unwrap_or_else(|_| invalid_arg(arg)))
+}
+
+/// Prints the cached pieces matching the filters in `sub`, oldest first.
+fn history(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let matching = |field: &Option<String>, query: Option<&str>| {
+        query.is_none_or(|query| {
+            let field = field.as_deref().unwrap_or_default().to_lowercase();
+            field.contains(&query.to_lowercase())
+        })
+    };
+    let entries: Vec<Entry> =
+        cached_entries(date_arg(sub, "since"), date_arg(sub, "until"))
+            .into_iter()
+            .filter(|entry| {
+                matching(&entry.composer, sub.value_of("composer"))
+                    && matching(&entry.title, sub.value_of("title"))
+            })
+            .collect();
+    if entries.is_empty() && !machine_readable(matches) {
+        println!("No matching pieces in the cache");
+    }
+    print_entries(matches, &entries, true);
+    std::process::exit(0)
+}
+
+/// Prints the most played composers, labels, works, and programs in the cache.
+fn stats(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let arg = sub.value_of("top").unwrap();
+    let top = arg.parse().unwrap_or_else(|_| invalid_arg(arg));
+    let entries =
+        cached_entries(date_arg(sub, "since"), date_arg(sub, "until"));
+    if sub.is_present("repeats") {
+        let arg = sub.value_of("window").unwrap_or("7d");
+        let window = parse_age(arg).unwrap_or_else(|| invalid_arg(arg));
+        let mut found = repeats(&entries, window);
+        found.truncate(top);
+        print_repeats(matches, &found);
+        std::process::exit(0);
+    }
+    let mut stats = Stats::new(&entries);
+    stats.truncate(top);
+    if let Output::Json = output(matches) {
+        println!("{}", serde_json::to_string(&stats).unwrap());
+        std::process::exit(0);
+    }
+    println!("{} plays", stats.plays);
+    let sections = [
+        ("Composer", &stats.composers),
+        ("Record Label", &stats.labels),
+        ("Repeated Work", &stats.works),
+        ("Program", &stats.programs),
+    ];
+    for (heading, counts) in sections {
+        if counts.is_empty() {
+            continue;
+        }
+        let rows: Vec<Vec<String>> = counts
+            .iter()
+            .map(|c| vec![c.count.to_string(), c.name.clone()])
+            .collect();
+        println!();
+        print_table(matches, &["Plays", heading], &rows);
+    }
+    std::process::exit(0)
+}
+
+/// Prints works played more than once, with the dates they played.
+fn print_repeats(matches: &ArgMatches<'_>, found: &[Repeat]) {
+    if let Output::Json = output(matches) {
+        println!("{}", serde_json::to_string(found).unwrap());
+        return;
+    }
+    if found.is_empty() {
+        println!("No repeats");
+        return;
+    }
+    let rows: Vec<Vec<String>> = found
+        .iter()
+        .map(|repeat| {
+            let dates: Vec<String> = repeat
+                .plays
+                .iter()
+                .map(|play| {
+                    let start = play.start_time.with_timezone(&Local);
+                    start.format("%Y-%m-%d").to_string()
+                })
+                .collect();
+            vec![
+                repeat.plays.len().to_string(),
+                dates.join(", "),
+                repeat.name.clone(),
+            ]
+        })
+        .collect();
+    print_table(matches, &["Plays", "Dates", "Repeated Work"], &rows);
+}
+
+/// Prints the pieces from `--from` to `--to` whose composer, title, or
+/// performers contain the query, oldest first.
+fn search(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let to = date_arg(sub, "to").unwrap_or_else(|| StationTime::now().date());
+    let from = date_arg(sub, "from").unwrap_or(to - chrono::Duration::days(6));
+    let query = sub.value_of("query").unwrap().to_lowercase();
+    let matching = |field: &Option<String>| {
+        let field = field.as_deref().unwrap_or_default().to_lowercase();
+        field.contains(&query)
+    };
+    let mut entries = Vec::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        match lookup_playlist(matches, &Request::whole_day(date)) {
+            Ok(playlist) => entries.extend(playlist.into_iter().filter(|e| {
+                matching(&e.composer)
+                    || matching(&e.title)
+                    || matching(&e.performers)
+            })),
+            Err(err) => eprintln!("{}: {}", date, err),
+        }
+    }
+    if entries.is_empty() && !machine_readable(matches) {
+        println!("No matching pieces");
+    }
+    print_entries(matches, &entries, true);
+    std::process::exit(0)
+}
+
+/// Writes every piece from `--since` to `--until` to `--output` in the format
+/// given by `--to`, downloading days that aren't cached unless `--cached` or
+/// `--offline` is given.
+fn export(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let until =
+        date_arg(sub, "until").unwrap_or_else(|| StationTime::now().date());
+    let since =
+        date_arg(sub, "since").unwrap_or(until - chrono::Duration::days(6));
+    let entries = if sub.is_present("cached") || offline(matches) {
+        cached_entries(Some(since), Some(until))
+    } else {
+        let mut entries = Vec::new();
+        for date in since.iter_days().take_while(|date| *date <= until) {
+            match lookup_playlist(matches, &Request::whole_day(date)) {
+                Ok(playlist) => entries.extend(playlist),
+                Err(err) => eprintln!("{}: {}", date, err),
+            }
+        }
+        entries
+    };
+    let text = match sub.value_of("to").unwrap() {
+        "json" => serde_json::to_string(&entries).unwrap() + "\n",
+        "jsonl" => entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect(),
+        "ics" => wowcpe::playlist_ics(&entries),
+        _ => std::iter::once(csv_row(&ENTRY_COLUMNS))
+            .chain(entries.iter().map(entry_csv_row))
+            .map(|row| row + "\r\n")
+            .collect(),
+    };
+    let result = match sub.value_of_os("output") {
+        Some(path) => fs::write(path, text),
+        None => io::stdout().write_all(text.as_bytes()),
+    };
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(err) => fail(&format!("Failed to export: {}", err)),
+    }
+}
+
+/// Emails the playlist for the date in `sub`, yesterday by default, to the
+/// addresses given by `--to` or the config, or prints it with `--print`.
+fn digest(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let date = date_arg(sub, "date").unwrap_or_else(|| {
+        StationTime::now().date() - chrono::Duration::days(1)
+    });
+    let playlist = lookup_playlist(matches, &Request::whole_day(date))
+        .unwrap_or_else(|err| fail(&format!("{}: {}", date, err)));
+    let text = wowcpe::digest_text(&playlist);
+    let html = wowcpe::digest_html(&playlist);
+    if let Some(format) = sub.value_of("print") {
+        print!("{}", if format == "html" { &html } else { &text });
+        std::process::exit(0);
+    }
+    let config = config();
+    let mailer = config.email.mailer().unwrap_or_else(|| {
+        fail("No SMTP server. Add server and from to [email] in the config")
+    });
+    let to: Vec<&str> = match sub.values_of("to") {
+        Some(to) => to.collect(),
+        None => config.email.to.iter().map(String::as_str).collect(),
+    };
+    if to.is_empty() {
+        fail("No recipients. Use --to or add to to [email] in the config");
+    }
+    let subject = wowcpe::digest_title(&playlist);
+    match mailer.send(&to, &subject, &text, &html) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => fail(&format!("Failed to send the digest: {}", err)),
+    }
+}
+
+fn pin_date(matches: &ArgMatches<'_>, pin: bool) -> ! {
+    let arg = matches.value_of("date").unwrap();
+    let date = arg.parse().unwrap_or_else(|_| invalid_arg(arg));
+    let cache = config()
+        .cache()
+        .unwrap_or_else(|| fail("Cannot determine the cache directory"));
+    let result = if pin {
+        cache.pin(date)
+    } else {
+        cache.unpin(date)
+    };
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(err) => fail(&format!("{}: {}", cache.dir().display(), err)),
+    }
+}
+
+/// Runs one of the `cache` subcommands.
+fn manage_cache(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    let cache = config()
+        .cache()
+        .unwrap_or_else(|| fail("Cannot determine the cache directory"));
+    let dir = cache.dir().display();
+    let removed = match sub.subcommand() {
+        ("path", _) => {
+            println!("{}", dir);
+            std::process::exit(0)
+        }
+        ("stats", _) => cache_stats(matches, &cache),
+        ("clear", _) => cache.clear(),
+        ("prune", Some(prune)) => {
+            let arg = prune.value_of("older_than").unwrap();
+            let age = parse_age(arg).unwrap_or_else(|| invalid_arg(arg));
+            let today = StationTime::now().date();
+            let cutoff = today
+                .checked_sub_signed(age)
+                .unwrap_or_else(|| invalid_arg(arg));
+            cache.prune(cutoff)
+        }
+        _ => unreachable!(),
+    };
+    match removed {
+        Ok(1) => println!("Removed 1 day"),
+        Ok(n) => println!("Removed {} days", n),
+        Err(err) => fail(&format!("{}: {}", dir, err)),
+    }
+    std::process::exit(0)
+}
+
+fn cache_stats(matches: &ArgMatches<'_>, cache: &Cache) -> ! {
+    let dates = cache.dates();
+    let pinned = cache.pinned();
+    let bytes = cache.bytes();
+    if let Output::Json = output(matches) {
+        let json = serde_json::json!({
+            "dir": cache.dir(),
+            "days": dates.len(),
+            "pinned": pinned.len(),
+            "bytes": bytes,
+            "oldest": dates.first(),
+            "newest": dates.last(),
+        });
+        println!("{}", json);
+        std::process::exit(0);
+    }
+    let days = match (dates.first(), dates.last()) {
+        (Some(oldest), Some(newest)) => format!(
+            "{} ({} pinned), from {} to {}",
+            dates.len(),
+            pinned.len(),
+            oldest,
+            newest
+        ),
+        _ => "0".to_string(),
+    };
+    let style = Style::new(matches);
+    let lines = [
+        ("Directory", cache.dir().display().to_string()),
+        ("Days", days),
+        ("Size", format_bytes(bytes)),
+    ];
+    for (label, value) in lines {
+        println!("{}{}", style.paint(DIM, &format!("{:<11}", label)), value);
+    }
+    std::process::exit(0)
+}
+
+/// Downloads the playlists from `--from` to `--to` into the archive, showing
+/// progress on standard error.
+fn sync_archive(matches: &ArgMatches<'_>, sub: &ArgMatches<'_>) -> ! {
+    if matches.is_present("offline") {
+        fail("Cannot sync the archive with --offline");
+    }
+    let archive = Cache::open_archive()
+        .unwrap_or_else(|| fail("Cannot determine the cache directory"));
+    let arg = sub.value_of("per_minute").unwrap();
+    let per_minute = arg.parse().unwrap_or_else(|_| invalid_arg(arg));
+    if per_minute == 0 {
+        invalid_arg(arg);
+    }
+    wowcpe::set_rate_limit(Some(wowcpe::RateLimit {
+        per_minute,
+        burst: 1,
+    }));
+    let to = date_arg(sub, "to").unwrap_or_else(|| StationTime::now().date());
+    let from = match date_arg(sub, "from") {
+        Some(date) => date,
+        None => {
+            eprintln!("Finding the earliest date with a playlist...");
+            wowcpe::earliest_available_date()
+                .unwrap_or_else(|err| fail_lookup(matches, &err))
+        }
+    };
+    let dates: Vec<NaiveDate> =
+        from.iter_days().take_while(|date| *date <= to).collect();
+    let terminal = io::stderr().is_terminal();
+    let (mut downloaded, mut failed) = (0, 0);
+    archive.sync(&dates, |date, result| {
+        match result {
+            Ok(Synced::Downloaded) => downloaded += 1,
+            Ok(Synced::AlreadyCached) => {}
+            Err(err) => {
+                failed += 1;
+                // Clear the progress line first.
+                let clear = if terminal { "\r\x1b[K" } else { "" };
+                eprintln!("{}{}: {}", clear, date, err);
+            }
+        }
+        if terminal {
+            let done = (date - from).num_days() + 1;
+            eprint!(
+                "\r\x1b[KSyncing {}: {} of {} days, {} downloaded",
+                date,
+                done,
+                dates.len(),
+                downloaded
+            );
+        }
+    });
+    if terminal {
+        eprint!("\r\x1b[K");
+    }
+    eprintln!(
+        "{} of {} days from {} to {} are in {}, {} downloaded just now",
+        dates.len() - failed,
+        dates.len(),
+        from,
+        to,
+        archive.dir().display(),
+        downloaded
+    );
+    if failed > 0 {
+        eprintln!("Run this again to retry the failed days");
+        std::process::exit(1);
+    }
+    std::process::exit(0)
+}
+
+/// Prints the result of each check and a summary. Exits with 1 if any failed.
+fn doctor(matches: &ArgMatches<'_>) -> ! {
+    let style = Style::new(matches);
+    let checks =
+        wowcpe::diagnose(&wowcpe::Client::new(), cache(matches).as_ref());
+    for check in &checks {
+        let (mark, color) = match check.status {
+            CheckStatus::Ok => ("✓", GREEN),
+            CheckStatus::Warning => ("!", YELLOW),
+            CheckStatus::Failed => ("✗", RED),
+        };
+        let name = format!("{:<11}", check.name);
+        println!(
+            "{} {}{}",
+            style.paint(color, mark),
+            style.paint(BOLD, &name),
+            check.detail
+        );
+    }
+    let count = |status| checks.iter().filter(|c| c.status == status).count();
+    let (failed, warnings) =
+        (count(CheckStatus::Failed), count(CheckStatus::Warning));
+    println!();
+    match (failed, warnings) {
+        (0, 0) => println!("All checks passed"),
+        (0, _) => println!("Lookups should work, but see the warnings above"),
+        _ => println!("{} of {} checks failed", failed, checks.len()),
+    }
+    std::process::exit(if failed > 0 { 1 } else { 0 })
+}
+
+/// Parses a positive age like "30d" or "4w" into a number of days.
+fn parse_age(arg: &str) -> Option<chrono::Duration> {
+    let unit = arg.chars().last()?;
+    let amount: u32 = arg[..arg.len() - unit.len_utf8()].parse().ok()?;
+    if amount == 0 {
+        return None;
+    }
+    match unit {
+        'd' => Some(chrono::Duration::days(amount.into())),
+        'w' => Some(chrono::Duration::weeks(amount.into())),
+        _ => None,
+    }
+}
+
+/// Formats `bytes` for people, e.g., "1.5 MiB".
+fn format_bytes(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["KiB", "MiB", "GiB"] {
+        size /= 1024.0;
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, unit);
+        }
+    }
+    format!("{:.1} TiB", size / 1024.0)
+}
+
+fn current_time() -> DateTime<Local> {
+    Local::now().with_nanosecond(0).unwrap()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn invalid_arg(arg: &str) -> ! {
+    eprintln!("{}: Invalid argument", arg);
+    eprintln!("For more information try --help");
+    std::process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    #[test]
+    fn test_pango_escape() {
+        assert_eq!(
+            "Tasso: Lament &amp; &lt;b&gt;",
+            pango_escape("Tasso: Lament & <b>")
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("Symphony", truncate("Symphony", 8, "…"));
+        assert_eq!("Symphony…", truncate("Symphony No. 2", 10, "…"));
+        assert_eq!("Sym...", truncate("Symphony No. 2", 6, "..."));
+        assert_eq!("S", truncate("Symphony", 1, "…"));
+        assert_eq!("Sy", truncate("Symphony", 2, ".."));
+        assert_eq!("", truncate("Symphony", 0, "…"));
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(Some(chrono::Duration::days(30)), parse_age("30d"));
+        assert_eq!(Some(chrono::Duration::weeks(4)), parse_age("4w"));
+        assert_eq!(None, parse_age("30"));
+        assert_eq!(None, parse_age(""));
+        assert_eq!(None, parse_age("30é"));
+        assert_eq!(None, parse_age("-3d"));
+        assert_eq!(None, parse_age("0d"));
+        assert_eq!(None, parse_age("0w"));
+        assert_eq!(None, parse_age("99999999999999d"));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!("0.5 KiB", format_bytes(512));
+        assert_eq!("1.5 MiB", format_bytes(3 * 512 * 1024));
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let start = Local.ymd(2020, 9, 1).and_hms(20, 0, 0);
+        let end = Local.ymd(2020, 9, 1).and_hms(20, 8, 20);
+        let now = Local.ymd(2020, 9, 1).and_hms(20, 4, 10);
+        assert_eq!(
+            "█████░░░░░ 4:10 / 8:20",
+            progress_bar(&start, &end, &now, 10)
+        );
+        let later = Local.ymd(2020, 9, 1).and_hms(21, 0, 0);
+        assert_eq!(
+            "██████████ 8:20 / 8:20",
+            progress_bar(&start, &end, &later, 10)
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        assert_eq!("0:05", clock(5));
+        assert_eq!("1:02:05", clock(3725));
+    }
+
+    #[test]
+    fn test_select_fields() {
+        let values = ["Sleepers, Awake!", "Franz Liszt", "Tasso\tLament"]
+            .map(String::from);
+        let columns = ["program", "composer", "title"];
+        let fields = ["title", "composer", "index"].map(String::from);
+        assert_eq!(
+            "Tasso Lament\tFranz Liszt\t",
+            select_fields(&fields, &columns, &values)
+        );
+        assert_eq!(
+            "Tasso\tLament\0Franz Liszt\0\0",
+            nul_fields(&fields, &columns, &values)
+        );
+    }
+
+    #[test]
+    fn test_read_key() {
+        let mut input: &[u8] = b"\x1b[D\x1b[Ca\x7f\r\x03";
+        let keys: Vec<Key> =
+            (0..6).map(|_| read_key(&mut input).unwrap()).collect();
+        assert_eq!(
+            vec![
+                Key::Left,
+                Key::Right,
+                Key::Char('a'),
+                Key::Backspace,
+                Key::Enter,
+                Key::Interrupt
+            ],
+            keys
+        );
+        assert!(read_key(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_date_picker() {
+        let now = Local.ymd(2020, 9, 2).and_hms(20, 0, 0);
+        let mut picker = DatePicker::new(now);
+        picker.latest = NaiveDate::from_ymd(2020, 9, 2);
+        assert_eq!(None, picker.handle(Key::Right));
+        assert_eq!(None, picker.handle(Key::Left));
+        assert_eq!(
+            Some(NaiveDate::from_ymd(2020, 9, 1)),
+            picker.handle(Key::Enter)
+        );
+
+        for c in "nope".chars() {
+            picker.handle(Key::Char(c));
+        }
+        assert_eq!(None, picker.handle(Key::Enter));
+        assert!(picker.line().ends_with("(invalid date)"));
+
+        picker.typed = "yesterday".to_string();
+        assert_eq!(
+            Some(NaiveDate::from_ymd(2020, 9, 1)),
+            picker.handle(Key::Enter)
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(EXIT_NO_DATA, exit_code(&Error::NoEntry));
+        assert_eq!(
+            EXIT_NO_DATA,
+            exit_code(&Error::HttpStatus(404, "HTTP/1.1 404".to_string()))
+        );
+        assert_eq!(
+            EXIT_NO_DATA,
+            exit_code(&Error::NotCached(NaiveDate::from_ymd(2020, 9, 3)))
+        );
+        assert_eq!(
+            EXIT_NETWORK,
+            exit_code(&Error::HttpStatus(503, "HTTP/1.1 503".to_string()))
+        );
+        assert_eq!(EXIT_PARSE, exit_code(&Error::BadScrape));
+    }
+
+    #[test]
+    fn test_program_reminder() {
+        let program = Program::ThursdayNightOperaHouse;
+        assert_eq!(
+            "Thursday Night Opera House starts in 30 minutes",
+            program_reminder(&program, 30)
+        );
+        assert_eq!(
+            "Thursday Night Opera House starts in 1 minute",
+            program_reminder(&program, 1)
+        );
+    }
+
+    #[test]
+    fn test_reminder() {
+        let start = Local.ymd(2020, 9, 1).and_hms(20, 4, 0);
+        let mut entry = Entry {
+            index: 0,
+            hour: None,
+            program: Program::ConcertHall,
+            start_time: start.into(),
+            end_time: (start + chrono::Duration::minutes(35)).into(),
+            composer: Some("Jean Sibelius".to_string()),
+            title: Some("Violin Concerto".to_string()),
+            performers: None,
+            record_label: None,
+            raw: Default::default(),
+        };
+        assert_eq!(
+            "Sibelius: Violin Concerto starts at 8:04 PM",
+            reminder(&entry)
+        );
+        entry.composer = None;
+        entry.title = None;
+        assert_eq!("Concert Hall starts at 8:04 PM", reminder(&entry));
+    }
+
+    #[test]
+    fn test_xbar_text() {
+        assert_eq!("Bach │ Handel", xbar_text("Bach | Handel"));
+        assert_eq!(" Intermission", xbar_text("--- Intermission"));
+    }
+
+    #[test]
+    fn test_css_class() {
+        assert_eq!("concert-hall", css_class("Concert Hall"));
+        assert_eq!("sleepers-awake", css_class("Sleepers, Awake!"));
+    }
+
+    #[test]
+    fn test_csv_row() {
+        assert_eq!("a,,b c", csv_row(&["a", "", "b c"]));
+        assert_eq!(
+            "\"Sleepers, Awake!\",\"Op. 1 \"\"Spring\"\"\",\"a\nb\"",
+            csv_row(&["Sleepers, Awake!", "Op. 1 \"Spring\"", "a\nb"])
+        );
+    }
 }
@@ -0,0 +1,102 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! An in-memory cache of recently used playlists.
+
+use {crate::Playlist, chrono::NaiveDate, std::collections::VecDeque};
+
+/// A least-recently-used cache of playlists, keyed by date.
+///
+/// Capacities are small (a handful of days), so a linear scan is fine.
+#[derive(Debug)]
+pub(crate) struct MemoryCache {
+    capacity: usize,
+    // Ordered from least to most recently used.
+    playlists: VecDeque<Playlist>,
+}
+
+impl MemoryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        MemoryCache {
+            capacity,
+            playlists: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Sets the capacity, evicting playlists if there are too many.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the playlist for `date` and marks it as most recently used.
+    pub(crate) fn get(&mut self, date: NaiveDate) -> Option<&Playlist> {
+        let index = self.playlists.iter().position(|p| p.date() == date)?;
+        let playlist = self.playlists.remove(index)?;
+        self.playlists.push_back(playlist);
+        self.playlists.back()
+    }
+
+    /// Inserts `playlist`, replacing any existing one for the same date.
+    pub(crate) fn insert(&mut self, playlist: Playlist) {
+        self.playlists.retain(|p| p.date() != playlist.date());
+        self.playlists.push_back(playlist);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.playlists.len() > self.capacity {
+            self.playlists.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(day: u32) -> Playlist {
+        Playlist::new(NaiveDate::from_ymd(2020, 9, day), Vec::new(), Vec::new())
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2020, 9, day)
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut cache = MemoryCache::new(2);
+        cache.insert(playlist(1));
+        cache.insert(playlist(2));
+        assert!(cache.get(date(1)).is_some());
+        cache.insert(playlist(3));
+
+        assert!(cache.get(date(1)).is_some());
+        assert!(cache.get(date(2)).is_none());
+        assert!(cache.get(date(3)).is_some());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut cache = MemoryCache::new(2);
+        cache.insert(playlist(1));
+        cache.insert(playlist(1));
+        cache.insert(playlist(2));
+
+        assert!(cache.get(date(1)).is_some());
+        assert!(cache.get(date(2)).is_some());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut cache = MemoryCache::new(0);
+        cache.insert(playlist(1));
+        assert!(cache.get(date(1)).is_none());
+
+        cache.set_capacity(2);
+        cache.insert(playlist(1));
+        cache.insert(playlist(2));
+        cache.set_capacity(1);
+        assert!(cache.get(date(1)).is_none());
+        assert!(cache.get(date(2)).is_some());
+    }
+}
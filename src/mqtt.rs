@@ -0,0 +1,281 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Publishing messages to an MQTT broker.
+
+use {
+    rumqttc::{
+        Client, ConnectReturnCode, ConnectionError, Event, Incoming,
+        MqttOptions, Outgoing, QoS, Transport,
+    },
+    std::{
+        fmt, io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+    tracing::warn,
+};
+
+/// How long to wait for the broker before giving up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to ping the broker when there is nothing to publish.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+/// How long to wait before reconnecting after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A connection to an MQTT broker (version 3.1.1) for publishing messages, so
+/// that home automation like Node-RED can react to what is playing.
+///
+/// Messages are sent at most once (QoS 0). A background thread keeps the
+/// connection alive between messages and reconnects if it drops. Connecting
+/// over TLS needs the `native-tls` feature.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// let mqtt = wowcpe::Mqtt::connect("localhost:1883", "wowcpe", None, false)?;
+/// mqtt.publish("wowcpe/now", b"{}", true)?;
+/// mqtt.disconnect()
+/// # }
+/// ```
+pub struct Mqtt {
+    client: Client,
+    closing: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Mqtt {
+    /// Connects to the broker at `addr`, e.g., "localhost:1883" or
+    /// "[::1]:1883", with a username and password if given. Uses TLS if `tls`
+    /// is true. Fails if the broker does not accept the connection in time.
+    pub fn connect(
+        addr: &str,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+        tls: bool,
+    ) -> io::Result<Self> {
+        let (host, port) = split_addr(addr)?;
+        check_length(client_id)?;
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some((username, password)) = credentials {
+            check_length(username)?;
+            check_length(password)?;
+            options.set_credentials(username, password);
+        }
+        if tls {
+            options.set_transport(tls_transport()?);
+        }
+        let (client, mut connection) = Client::new(options, 10);
+        match connection.recv_timeout(TIMEOUT) {
+            Ok(Ok(Event::Incoming(Incoming::ConnAck(_)))) => {}
+            Ok(Err(ConnectionError::ConnectionRefused(code))) => {
+                return Err(io::Error::other(format!(
+                    "The broker refused the connection: {}",
+                    refusal(code)
+                )))
+            }
+            Ok(Err(err)) => return Err(io::Error::other(err)),
+            Ok(Ok(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Expected CONNACK from the broker",
+                ))
+            }
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The broker did not answer",
+                ))
+            }
+        }
+        let closing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&closing);
+        let thread = thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(_) if stop.load(Ordering::SeqCst) => break,
+                    Err(err) => {
+                        warn!(%err, "lost the MQTT connection, reconnecting");
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+        Ok(Mqtt {
+            client,
+            closing,
+            thread,
+        })
+    }
+
+    /// Publishes `payload` to `topic`. If `retain` is true, the broker keeps
+    /// it to send to new subscribers. The message is sent in the background,
+    /// so this only fails if `topic` is invalid.
+    pub fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> io::Result<()> {
+        check_length(topic)?;
+        self.client
+            .publish(topic, QoS::AtMostOnce, retain, payload)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    /// Sends any pending messages and disconnects cleanly, so the broker
+    /// doesn't treat it as a failure.
+    pub fn disconnect(self) -> io::Result<()> {
+        self.closing.store(true, Ordering::SeqCst);
+        self.client.disconnect().map_err(io::Error::other)?;
+        drop(self.client);
+        self.thread
+            .join()
+            .map_err(|_| io::Error::other("The MQTT thread panicked"))
+    }
+}
+
+impl fmt::Debug for Mqtt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mqtt").finish_non_exhaustive()
+    }
+}
+
+/// Splits "HOST:PORT" into its parts. IPv6 hosts must be in brackets.
+fn split_addr(addr: &str) -> io::Result<(&str, u16)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: Expected HOST:PORT", addr),
+        )
+    };
+    let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let host = match host.strip_prefix('[') {
+        Some(rest) => rest.strip_suffix(']').ok_or_else(invalid)?,
+        None if host.contains(':') => return Err(invalid()),
+        None => host,
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host, port))
+}
+
+/// Checks that `text` fits in an MQTT string, which has a 16-bit length.
+fn check_length(text: &str) -> io::Result<()> {
+    if text.len() > usize::from(u16::MAX) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("MQTT strings are at most {} bytes", u16::MAX),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native-tls")]
+fn tls_transport() -> io::Result<Transport> {
+    Ok(Transport::tls_with_config(
+        rumqttc::TlsConfiguration::Native,
+    ))
+}
+
+#[cfg(not(feature = "native-tls"))]
+fn tls_transport() -> io::Result<Transport> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "MQTT over TLS needs the native-tls feature",
+    ))
+}
+
+fn refusal(code: ConnectReturnCode) -> &'static str {
+    match code {
+        ConnectReturnCode::RefusedProtocolVersion => {
+            "unacceptable protocol version"
+        }
+        ConnectReturnCode::BadClientId => "client identifier rejected",
+        ConnectReturnCode::ServiceUnavailable => "server unavailable",
+        ConnectReturnCode::BadUserNamePassword => "bad user name or password",
+        ConnectReturnCode::NotAuthorized => "not authorized",
+        ConnectReturnCode::Success => "unknown reason",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{Read, Write},
+            net::TcpListener,
+        },
+    };
+
+    #[test]
+    fn test_split_addr() {
+        assert_eq!(("localhost", 1883), split_addr("localhost:1883").unwrap());
+        assert_eq!(("::1", 8883), split_addr("[::1]:8883").unwrap());
+        assert!(split_addr("::1:1883").is_err());
+        assert!(split_addr("localhost").is_err());
+        assert!(split_addr(":1883").is_err());
+    }
+
+    #[test]
+    fn test_too_long() {
+        let long = "x".repeat(70_000);
+        let err = Mqtt::connect("localhost:1883", &long, None, false);
+        assert_eq!(io::ErrorKind::InvalidInput, err.unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = [0; 20];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).unwrap();
+            (connect, rest)
+        });
+        let mqtt = Mqtt::connect(&addr, "wowcpe", None, false).unwrap();
+        mqtt.publish("a/b", b"{}", true).unwrap();
+        let err = mqtt.publish(&"x".repeat(70_000), b"{}", true).unwrap_err();
+        mqtt.disconnect().unwrap();
+        let (connect, rest) = broker.join().unwrap();
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        // The keep-alive is 60 seconds.
+        assert_eq!(
+            b"\x10\x12\x00\x04MQTT\x04\x02\x00\x3c\x00\x06wowcpe",
+            &connect
+        );
+        assert_eq!(b"\x31\x07\x00\x03a/b{}\xe0\x00".to_vec(), rest);
+    }
+
+    #[test]
+    fn test_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = [0; 20];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 2, 0, 5]).unwrap();
+        });
+        let err = Mqtt::connect(&addr, "wowcpe", None, false).unwrap_err();
+        assert_eq!(
+            "The broker refused the connection: not authorized",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Rate limiting for downloads from the station's website.
+
+use {
+    crate::{CancellationToken, Error, Result},
+    std::{
+        sync::Mutex,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// Limits how often pages are downloaded from the station's website.
+///
+/// Up to `burst` downloads can happen back to back, after which they are
+/// spaced out to average `per_minute`. By default, this is 30 per minute with a
+/// burst of 5, which is plenty for interactive use but keeps bulk operations
+/// from hammering the site.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Maximum number of downloads per minute, on average.
+    pub per_minute: u32,
+    /// Maximum number of downloads that can happen without waiting.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    const DEFAULT: RateLimit = RateLimit {
+        per_minute: 30,
+        burst: 5,
+    };
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit::DEFAULT
+    }
+}
+
+static LIMITER: Mutex<Option<RateLimiter>> =
+    Mutex::new(Some(RateLimiter::new(RateLimit::DEFAULT)));
+
+/// Sets the rate limit used for all downloads in this process. Passing `None`
+/// disables rate limiting.
+pub fn set_rate_limit(limit: Option<RateLimit>) {
+    *LIMITER.lock().unwrap() = limit.map(RateLimiter::new);
+}
+
+/// How often `wait` checks for cancellation while sleeping.
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Blocks until the global rate limit allows another download. Returns
+/// `Error::Cancelled` as soon as `token` is cancelled, even while sleeping.
+pub(crate) fn wait(token: &CancellationToken) -> Result<()> {
+    if token.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    let delay = match LIMITER.lock().unwrap().as_mut() {
+        Some(limiter) => limiter.reserve(Instant::now()),
+        None => return Ok(()),
+    };
+    let deadline = Instant::now() + delay;
+    loop {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Ok(());
+        }
+        thread::sleep(remaining.min(CANCEL_POLL));
+    }
+}
+
+/// A token bucket implementing `RateLimit`.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) const fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            tokens: limit.burst as f64,
+            last: None,
+        }
+    }
+
+    /// Reserves a download at `now`, returning how long to wait before it.
+    pub(crate) fn reserve(&mut self, now: Instant) -> Duration {
+        let capacity = self.limit.burst.max(1) as f64;
+        let per_second = self.limit.per_minute.max(1) as f64 / 60.0;
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * per_second).min(capacity);
+        }
+        self.last = Some(now);
+        // Going negative represents downloads that are already waiting.
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, assert_matches::assert_matches};
+
+    #[test]
+    fn test_wait_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_matches!(wait(&token), Err(Error::Cancelled));
+    }
+
+    #[test]
+    fn test_burst() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_minute: 60,
+            burst: 3,
+        });
+        let now = Instant::now();
+        assert_eq!(Duration::from_secs(0), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(0), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(0), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(1), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(2), limiter.reserve(now));
+    }
+
+    #[test]
+    fn test_refill() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_minute: 30,
+            burst: 1,
+        });
+        let now = Instant::now();
+        assert_eq!(Duration::from_secs(0), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(2), limiter.reserve(now));
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(Duration::from_secs(0), limiter.reserve(later));
+    }
+}
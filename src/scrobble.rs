@@ -0,0 +1,338 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Submitting what is playing to scrobbling services like Last.fm and
+//! ListenBrainz.
+
+use {
+    crate::{Error, Response, Result},
+    curl::easy::{Easy, List},
+    md5::{Digest, Md5},
+    serde_json::{json, Value},
+    tracing::debug,
+};
+
+/// A service that keeps a history of what the user listened to.
+///
+/// Pieces are submitted with the composer as the artist and the title as the
+/// track, since that is how classical music is usually scrobbled. Pieces
+/// without a composer or title are skipped.
+pub trait Scrobbler {
+    /// Tells the service that `response` just started playing.
+    fn now_playing(&self, response: &Response) -> Result<()>;
+
+    /// Records that the user listened to `response`, after it finished.
+    fn scrobble(&self, response: &Response) -> Result<()>;
+}
+
+/// The Last.fm API endpoint.
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// A `Scrobbler` for [Last.fm](https://www.last.fm), authenticated with an API
+/// account's key and secret and a session key for the user.
+///
+/// To get a session key, pass a token from `LastFm::auth_token` to
+/// `LastFm::auth_url`, have the user open the URL and allow access, and then
+/// call `LastFm::session_key`. Session keys don't expire.
+///
+/// The performers are submitted as the album artist.
+#[derive(Clone, Debug)]
+pub struct LastFm {
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+}
+
+impl LastFm {
+    /// Creates a scrobbler for the user with `session_key`.
+    pub fn new(api_key: &str, api_secret: &str, session_key: &str) -> Self {
+        LastFm {
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+            session_key: session_key.to_string(),
+        }
+    }
+
+    /// Requests a token for the user to authorize.
+    pub fn auth_token(api_key: &str, api_secret: &str) -> Result<String> {
+        let params = [("method", "auth.getToken"), ("api_key", api_key)];
+        let reply = lastfm_call(&params, api_secret)?;
+        json_string(&reply["token"])
+    }
+
+    /// Returns the page where the user authorizes `token`.
+    pub fn auth_url(api_key: &str, token: &str) -> String {
+        format!(
+            "https://www.last.fm/api/auth/?api_key={}&token={}",
+            api_key, token
+        )
+    }
+
+    /// Exchanges `token`, once the user has authorized it, for a session key.
+    pub fn session_key(
+        api_key: &str,
+        api_secret: &str,
+        token: &str,
+    ) -> Result<String> {
+        let params = [
+            ("method", "auth.getSession"),
+            ("api_key", api_key),
+            ("token", token),
+        ];
+        let reply = lastfm_call(&params, api_secret)?;
+        json_string(&reply["session"]["key"])
+    }
+
+    fn track_call(
+        &self,
+        method: &str,
+        response: &Response,
+        timestamp: bool,
+    ) -> Result<()> {
+        let (composer, title) = match (&response.composer, &response.title) {
+            (Some(composer), Some(title)) => (composer, title),
+            _ => return Ok(()),
+        };
+        let start = response.start_time.timestamp().to_string();
+        let duration = (response.end_time - response.start_time)
+            .num_seconds()
+            .to_string();
+        let mut params = vec![
+            ("method", method),
+            ("api_key", &self.api_key),
+            ("sk", &self.session_key),
+            ("artist", composer),
+            ("track", title),
+            ("duration", &duration),
+        ];
+        if let Some(performers) = &response.performers {
+            params.push(("albumArtist", performers));
+        }
+        if timestamp {
+            params.push(("timestamp", &start));
+        }
+        lastfm_call(&params, &self.api_secret).map(drop)
+    }
+}
+
+impl Scrobbler for LastFm {
+    fn now_playing(&self, response: &Response) -> Result<()> {
+        self.track_call("track.updateNowPlaying", response, false)
+    }
+
+    fn scrobble(&self, response: &Response) -> Result<()> {
+        self.track_call("track.scrobble", response, true)
+    }
+}
+
+/// The ListenBrainz endpoint for submitting listens.
+const LISTENBRAINZ_API: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+/// A `Scrobbler` for [ListenBrainz](https://listenbrainz.org), authenticated
+/// with the user token from the user's settings page.
+///
+/// The performers and record label are submitted as additional info.
+#[derive(Clone, Debug)]
+pub struct ListenBrainz {
+    token: String,
+}
+
+impl ListenBrainz {
+    /// Creates a scrobbler for the user with `token`.
+    pub fn new(token: &str) -> Self {
+        ListenBrainz {
+            token: token.to_string(),
+        }
+    }
+
+    fn submit(&self, listen_type: &str, response: &Response) -> Result<()> {
+        let listen = match listen(response, listen_type == "single") {
+            Some(listen) => listen,
+            None => return Ok(()),
+        };
+        let body = json!({ "listen_type": listen_type, "payload": [listen] });
+        let auth = format!("Authorization: Token {}", self.token);
+        let (code, reply) = post(
+            &mut Easy::new(),
+            LISTENBRAINZ_API,
+            "Content-Type: application/json",
+            &[auth],
+            &body.to_string(),
+        )?;
+        if !(200..300).contains(&code) {
+            let reply: Value = serde_json::from_str(&reply).unwrap_or_default();
+            let message = reply["error"].as_str().unwrap_or_default();
+            return Err(Error::Transport(
+                format!("ListenBrainz error {}: {}", code, message).into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Scrobbler for ListenBrainz {
+    fn now_playing(&self, response: &Response) -> Result<()> {
+        self.submit("playing_now", response)
+    }
+
+    fn scrobble(&self, response: &Response) -> Result<()> {
+        self.submit("single", response)
+    }
+}
+
+/// Returns the ListenBrainz listen for `response`, with `listened_at` if
+/// `finished`, or `None` if it has no composer or title.
+fn listen(response: &Response, finished: bool) -> Option<Value> {
+    let duration = response.end_time - response.start_time;
+    let mut listen = json!({
+        "track_metadata": {
+            "artist_name": response.composer.as_ref()?,
+            "track_name": response.title.as_ref()?,
+            "additional_info": {
+                "duration_ms": duration.num_milliseconds(),
+                "performers": response.performers,
+                "record_label": response.record_label,
+                "music_service_name": "WCPE",
+                "submission_client": "wowcpe",
+                "submission_client_version": env!("CARGO_PKG_VERSION"),
+            },
+        },
+    });
+    if finished {
+        listen["listened_at"] = json!(response.start_time.timestamp());
+    }
+    Some(listen)
+}
+
+/// Calls a Last.fm API method, signing `params` with `api_secret`.
+fn lastfm_call(params: &[(&str, &str)], api_secret: &str) -> Result<Value> {
+    let mut body = String::new();
+    let mut easy = Easy::new();
+    for (name, value) in params {
+        body.push_str(&format!(
+            "{}={}&",
+            name,
+            easy.url_encode(value.as_bytes())
+        ));
+    }
+    body.push_str(&format!(
+        "api_sig={}&format=json",
+        lastfm_signature(params, api_secret)
+    ));
+    let content_type = "Content-Type: application/x-www-form-urlencoded";
+    let (code, reply) = post(&mut easy, LASTFM_API, content_type, &[], &body)?;
+    let reply: Value = serde_json::from_str(&reply).unwrap_or_default();
+    // Errors can come with a 200 status, so check the body too.
+    if let Some(err) = reply.get("error") {
+        let message = reply["message"].as_str().unwrap_or_default();
+        return Err(Error::Transport(
+            format!("Last.fm error {}: {}", err, message).into(),
+        ));
+    }
+    if !(200..300).contains(&code) {
+        return Err(Error::HttpStatus(code, code.to_string()));
+    }
+    Ok(reply)
+}
+
+/// Returns the `api_sig` for `params`: the MD5 of each name and value, sorted
+/// by name, followed by the secret.
+fn lastfm_signature(params: &[(&str, &str)], api_secret: &str) -> String {
+    let mut params = params.to_vec();
+    params.sort();
+    let mut text = String::new();
+    for (name, value) in params {
+        text.push_str(name);
+        text.push_str(value);
+    }
+    text.push_str(api_secret);
+    Md5::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn json_string(value: &Value) -> Result<String> {
+    match value.as_str() {
+        Some(value) => Ok(value.to_string()),
+        None => Err(Error::Transport("Unexpected reply from the API".into())),
+    }
+}
+
+/// Posts `body` to `url` with `content_type` and the other `headers`, and
+/// returns the status code and response body.
+pub(crate) fn post(
+    easy: &mut Easy,
+    url: &str,
+    content_type: &str,
+    headers: &[String],
+    body: &str,
+) -> Result<(u32, String)> {
+    let mut list = List::new();
+    list.append(content_type)?;
+    for header in headers {
+        list.append(header)?;
+    }
+    easy.url(url)?;
+    easy.post(true)?;
+    easy.post_fields_copy(body.as_bytes())?;
+    easy.http_headers(list)?;
+    let mut reply = Vec::new();
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            reply.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer.perform()?;
+    }
+    let code = easy.response_code()?;
+    debug!(url, code, "posted");
+    Ok((code, String::from_utf8_lossy(&reply).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::Program,
+        chrono::{Local, TimeZone},
+    };
+
+    #[test]
+    fn test_listen() {
+        let time = |s| Local.timestamp_opt(s, 0).unwrap();
+        let mut response = Response {
+            program: Program::ConcertHall,
+            start_time: time(1598981400),
+            end_time: time(1598983200),
+            program_start: time(1598981400),
+            program_end: time(1598992200),
+            composer: Some("Jean Sibelius".to_string()),
+            title: Some("Finlandia".to_string()),
+            performers: None,
+            record_label: None,
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+        let listen = listen(&response, true).unwrap();
+        assert_eq!(1598981400, listen["listened_at"]);
+        let metadata = &listen["track_metadata"];
+        assert_eq!("Jean Sibelius", metadata["artist_name"]);
+        assert_eq!("Finlandia", metadata["track_name"]);
+        assert_eq!(1800000, metadata["additional_info"]["duration_ms"]);
+
+        response.title = None;
+        assert_eq!(None, super::listen(&response, false));
+    }
+
+    #[test]
+    fn test_lastfm_signature() {
+        let params = [("method", "auth.getToken"), ("api_key", "key")];
+        // The MD5 of "api_keykeymethodauth.getTokensecret".
+        assert_eq!(
+            "b4705499705a550b07ca058a15bde9b0",
+            lastfm_signature(&params, "secret")
+        );
+    }
+}
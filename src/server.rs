@@ -0,0 +1,425 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! A small HTTP server that answers lookups with JSON.
+
+use {
+    crate::{playlist_atom, Cache, Error, Request, Response, Result},
+    chrono::{DateTime, Local, NaiveDate},
+    serde_json::{json, Value},
+    std::{
+        fmt::Write as _,
+        io::{self, BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    },
+    tracing::{debug, warn},
+};
+
+/// The most bytes the server reads of a request's line and headers. Requests
+/// are only ever a path, so anything longer is rejected.
+const MAX_HEAD_LEN: u64 = 8 * 1024;
+
+/// How long the server waits on a client to send its request or read the
+/// response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server that answers lookups over HTTP with JSON, so that dashboards and
+/// phone shortcuts on the local network can share one cache instead of each
+/// downloading playlists.
+///
+/// It answers `GET` requests for these paths:
+///
+/// - `/now`: what is playing now, with the fields of `Response`.
+/// - `/next`: an array of the pieces listed to start after the current one,
+///   which is usually empty (see `Playlist::after`).
+/// - `/day/YYYY-MM-DD`: an array of every piece in the playlist for the date.
+/// - `/feed`: an Atom feed of the pieces played so far today, newest first
+///   (see `playlist_atom`), so that feed readers can follow the station.
+/// - `/metrics`: Prometheus metrics, with gauges for the time left in the
+///   current piece labeled by program and composer, and counters of
+///   successful and failed lookups.
+///
+/// Errors are JSON objects with a single `error` field. Each connection gets
+/// one response. Requests with more than 8 KiB of headers get a 431, and
+/// clients that take more than 10 seconds to send them get a 408.
+///
+/// ```sh
+/// curl http://localhost:8080/now
+/// ```
+#[derive(Clone)]
+pub struct Server {
+    cache: Cache,
+    lookups: Arc<Lookups>,
+}
+
+/// Counts of the lookups a `Server` has made, shared by its clones.
+#[derive(Debug, Default)]
+struct Lookups {
+    successes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Server {
+    /// Creates a server that looks up playlists through `cache`.
+    pub fn new(cache: Cache) -> Self {
+        Server {
+            cache,
+            lookups: Arc::default(),
+        }
+    }
+
+    /// Counts the lookup that produced `result`.
+    fn count<T>(&self, result: Result<T>) -> Result<T> {
+        let counter = match result {
+            Ok(_) => &self.lookups.successes,
+            Err(_) => &self.lookups.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Serves requests from connections to `listener` until an I/O error
+    /// occurs, handling each connection on its own thread.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                if let Err(err) = server.respond(stream) {
+                    warn!(%err, "failed to respond to HTTP client");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn respond(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let line = read_head(&stream);
+        let (status, content_type, body) = match &line {
+            Ok(line) => self.route(line),
+            Err(err) => json_body(err.clone()),
+        };
+        let request = line.as_deref().unwrap_or_default().trim();
+        debug!(request, status, "answered HTTP request");
+        let head = format!(
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Connection: close\r\n\r\n",
+            status,
+            reason(status),
+            content_type,
+            body.len()
+        );
+        (&stream).write_all(head.as_bytes())?;
+        (&stream).write_all(body.as_bytes())
+    }
+
+    /// Answers a request with a status code, content type, and body.
+    fn route(&self, line: &str) -> (u32, &'static str, String) {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => {
+                // Ignore the query string.
+                let path = target.split('?').next().unwrap_or_default();
+                match path.trim_end_matches('/') {
+                    "/feed" => self.feed(),
+                    "/metrics" => self.metrics(),
+                    _ => json_body(self.answer(path)),
+                }
+            }
+            (Some(_), Some(_)) => {
+                json_body((405, error("Only GET is supported")))
+            }
+            _ => json_body((400, error("Malformed request"))),
+        }
+    }
+
+    /// Answers a request for the Atom feed with a status code, content type,
+    /// and body. Errors are JSON like for other paths.
+    fn feed(&self) -> (u32, &'static str, String) {
+        let now = Local::now();
+        match self.count(self.cache.lookup_playlist(&Request { time: now })) {
+            Ok(playlist) => {
+                (200, "application/atom+xml", playlist_atom(&playlist, now))
+            }
+            Err(err) => json_body(ok_or_error(Err(err))),
+        }
+    }
+
+    /// Answers a request for metrics in the Prometheus text format. It always
+    /// succeeds, leaving out the piece if the lookup fails.
+    fn metrics(&self) -> (u32, &'static str, String) {
+        let now = Local::now();
+        let response = self.count(self.cache.lookup(&Request { time: now }));
+        let text = render_metrics(
+            response.as_ref().ok(),
+            &now,
+            self.lookups.successes.load(Ordering::Relaxed),
+            self.lookups.errors.load(Ordering::Relaxed),
+        );
+        (200, "text/plain; version=0.0.4", text)
+    }
+
+    /// Answers a request for `path` with a status code and JSON.
+    pub(crate) fn answer(&self, path: &str) -> (u32, Value) {
+        let now = || Request { time: Local::now() };
+        let result = match path.trim_end_matches('/') {
+            "/now" => self.cache.lookup(&now()).map(|r| json!(r)),
+            "/next" => self
+                .cache
+                .lookup_playlist(&now())
+                .map(|p| json!(p.after(Local::now()))),
+            path => match path.strip_prefix("/day/") {
+                Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                {
+                    Ok(date) => self
+                        .cache
+                        .lookup_playlist(&Request::whole_day(date))
+                        .map(|p| json!(p.entries())),
+                    Err(_) => {
+                        return (400, error("Expected a YYYY-MM-DD date"))
+                    }
+                },
+                None => return (404, error("Not found")),
+            },
+        };
+        ok_or_error(self.count(result))
+    }
+}
+
+/// Reads the request line, and skips the headers since none of them matter.
+/// Fails with 431 if they are longer than `MAX_HEAD_LEN`, and with 408 if the
+/// client stops sending them for longer than `CLIENT_TIMEOUT`.
+fn read_head(stream: &TcpStream) -> std::result::Result<String, (u32, Value)> {
+    let mut reader = BufReader::new(Read::take(stream, MAX_HEAD_LEN));
+    let mut read_line = |buf: &mut String| match reader.read_line(buf) {
+        Ok(_) if !buf.ends_with('\n') && reader.get_ref().limit() == 0 => {
+            Err((431, error("Request headers are too large")))
+        }
+        Ok(len) => Ok(len),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Err((408, error("Timed out reading the request")))
+        }
+        Err(_) => Err((400, error("Malformed request"))),
+    };
+    let mut line = String::new();
+    read_line(&mut line)?;
+    let mut header = String::new();
+    while read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    Ok(line)
+}
+
+/// Renders metrics about `response`, the piece playing at `now`, and the
+/// number of lookups that succeeded and failed.
+fn render_metrics(
+    response: Option<&Response>,
+    now: &DateTime<Local>,
+    successes: u64,
+    errors: u64,
+) -> String {
+    let mut text = String::new();
+    let mut metric =
+        |name: &str, kind: &str, help: &str, samples: &[(String, i64)]| {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(text, "{}{} {}", name, labels, value);
+            }
+        };
+    metric(
+        "wowcpe_up",
+        "gauge",
+        "Whether the last lookup found what is playing.",
+        &[(String::new(), response.is_some() as i64)],
+    );
+    if let Some(r) = response {
+        fn field(value: &Option<String>) -> &str {
+            value.as_deref().unwrap_or_default()
+        }
+        let piece = labels(&[
+            ("program", r.program.name()),
+            ("composer", field(&r.composer)),
+        ]);
+        let info = labels(&[
+            ("program", r.program.name()),
+            ("composer", field(&r.composer)),
+            ("title", field(&r.title)),
+            ("performers", field(&r.performers)),
+            ("record_label", field(&r.record_label)),
+        ]);
+        metric(
+            "wowcpe_piece_info",
+            "gauge",
+            "The piece playing now, in the labels.",
+            &[(info, 1)],
+        );
+        metric(
+            "wowcpe_piece_elapsed_seconds",
+            "gauge",
+            "Seconds since the current piece started.",
+            &[(piece.clone(), (*now - r.start_time).num_seconds())],
+        );
+        metric(
+            "wowcpe_piece_remaining_seconds",
+            "gauge",
+            "Seconds until the current piece ends.",
+            &[(piece.clone(), (r.end_time - *now).num_seconds().max(0))],
+        );
+        metric(
+            "wowcpe_piece_start_timestamp_seconds",
+            "gauge",
+            "When the current piece started, in seconds since the epoch.",
+            &[(piece, r.start_time.timestamp())],
+        );
+    }
+    metric(
+        "wowcpe_lookups_total",
+        "counter",
+        "Lookups of what is playing, by result.",
+        &[
+            (labels(&[("result", "success")]), successes as i64),
+            (labels(&[("result", "error")]), errors as i64),
+        ],
+    );
+    text
+}
+
+/// Formats Prometheus labels like `{name="value"}`, escaping the values.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Returns 404 if there is no playlist or entry, and 502 for other errors,
+/// since they are failures to get the playlist from the station.
+fn ok_or_error(result: Result<Value>) -> (u32, Value) {
+    match result {
+        Ok(value) => (200, value),
+        Err(
+            err @ (Error::NoData | Error::NoEntry | Error::HttpStatus(404, _)),
+        ) => (404, error(&err.to_string())),
+        Err(err) => (502, error(&err.to_string())),
+    }
+}
+
+fn json_body((status, value): (u32, Value)) -> (u32, &'static str, String) {
+    (status, "application/json", value.to_string())
+}
+
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+fn reason(status: u32) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        431 => "Request Header Fields Too Large",
+        _ => "Bad Gateway",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::Program, chrono::TimeZone};
+
+    fn server() -> Server {
+        let dir = std::env::temp_dir().join("wowcpe-test-server");
+        Server::new(Cache::new(dir))
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let time = |s| Local.timestamp_opt(s, 0).unwrap();
+        let response = Response {
+            program: Program::ConcertHall,
+            start_time: time(1000),
+            end_time: time(1600),
+            program_start: time(0),
+            program_end: time(10800),
+            composer: Some("Jean Sibelius".to_string()),
+            title: Some("Finlandia".to_string()),
+            performers: None,
+            record_label: None,
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+        let text = render_metrics(Some(&response), &time(1100), 3, 1);
+        assert!(text.contains("wowcpe_up 1\n"));
+        assert!(text.contains(
+            "wowcpe_piece_remaining_seconds{program=\"Concert Hall\",\
+             composer=\"Jean Sibelius\"} 500\n"
+        ));
+        assert!(text.contains("wowcpe_lookups_total{result=\"error\"} 1\n"));
+
+        let text = render_metrics(None, &time(1100), 3, 1);
+        assert!(text.contains("wowcpe_up 0\n"));
+        assert!(!text.contains("wowcpe_piece"));
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            r#"{title="Op. 1 \"Spring\"",a="b\\c\nd"}"#,
+            labels(&[("title", "Op. 1 \"Spring\""), ("a", "b\\c\nd")])
+        );
+    }
+
+    #[test]
+    fn test_serve_large_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server().serve(listener));
+
+        // Exactly the limit, so that the server reads all of it.
+        let mut request = "GET /now HTTP/1.1\r\nX-Padding: ".to_string();
+        request.push_str(&"x".repeat(MAX_HEAD_LEN as usize - request.len()));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).unwrap();
+        assert_eq!("HTTP/1.1 431 Request Header Fields Too Large\r\n", status);
+    }
+
+    #[test]
+    fn test_answer_not_found() {
+        assert_eq!((404, error("Not found")), server().answer("/later"));
+    }
+
+    #[test]
+    fn test_answer_bad_date() {
+        assert_eq!(
+            (400, error("Expected a YYYY-MM-DD date")),
+            server().answer("/day/yesterday")
+        );
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Saving downloaded HTML when scraping fails, for bug reports.
+
+use {
+    chrono::Local,
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+    },
+    tracing::warn,
+};
+
+/// Saves a snapshot of `html` from `url` in `dir`, logging the result.
+///
+/// Each snapshot is a timestamped file containing the URL in a comment followed
+/// by the HTML.
+pub(crate) fn save(dir: &Path, url: &str, html: &str) {
+    match write(dir, url, html) {
+        Ok(path) => warn!(path = %path.display(), "saved snapshot"),
+        Err(err) => warn!(%err, "failed to save snapshot"),
+    }
+}
+
+fn write(dir: &Path, url: &str, html: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let name =
+        format!("wowcpe-{}.html", Local::now().format("%Y%m%dT%H%M%S%.f"));
+    let path = dir.join(name);
+    fs::write(&path, format!("<!-- {} -->\n{}", url, html))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join("wowcpe-test-snapshot");
+        let path = write(&dir, "https://example.com", "<html></html>").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(path.starts_with(&dir));
+        assert_eq!("<!-- https://example.com -->\n<html></html>", contents);
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! A cache of playlists for many days, with least-recently-used eviction.
+
+use {
+    crate::{
+        cache::{self, cache_dir},
+        eastern_noon, playlist_url, Client, Error, Playlist, Request, Response,
+        Result,
+    },
+    chrono::{NaiveDate, TimeZone, Utc},
+    std::{
+        collections::BTreeSet,
+        fs, io,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+        time::SystemTime,
+    },
+    tracing::{debug, warn},
+};
+
+/// The name of the file listing pinned dates, one per line.
+const PINS_FILE: &str = "pinned.txt";
+
+/// A directory of cached playlists, one file per day.
+///
+/// Lookups go through the cache like `lookup_cached`, but each day has its own
+/// file, so looking up yesterday doesn't throw away today. Once the cache holds
+/// more than `max_entries` days or `max_bytes` bytes, the least recently used
+/// days are evicted. Dates marked with `pin` are never evicted, though they
+/// still count toward the limits.
+///
+/// Pins are stored in the directory, so they persist across processes.
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    client: Client,
+    max_entries: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+/// What `Cache::sync` did for a day.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Synced {
+    /// The day was already cached and fresh, so nothing was downloaded.
+    AlreadyCached,
+    /// The day was downloaded into the cache.
+    Downloaded,
+}
+
+/// A cached day, for eviction.
+struct CachedDay {
+    date: NaiveDate,
+    path: PathBuf,
+    bytes: u64,
+    used: SystemTime,
+}
+
+impl Cache {
+    /// Creates a cache in `dir`, which is created when first needed. There are
+    /// no limits by default.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache {
+            dir: dir.into(),
+            client: Client::new(),
+            max_entries: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Returns a cache in the `playlists` subdirectory of `cache_dir`, keeping
+    /// at most 100 days and 50 MiB. Returns `None` if there is no cache
+    /// directory.
+    pub fn open_default() -> Option<Self> {
+        let cache = Cache::new(cache_dir()?.join("playlists"))
+            .with_max_entries(Some(100))
+            .with_max_bytes(Some(50 * 1024 * 1024));
+        Some(cache)
+    }
+
+    /// Returns a cache in the `archive` subdirectory of `cache_dir` with no
+    /// limits, for keeping every day's playlist. Fill it with `sync`. Returns
+    /// `None` if there is no cache directory.
+    pub fn open_archive() -> Option<Self> {
+        Some(Cache::new(cache_dir()?.join("archive")))
+    }
+
+    /// Downloads playlists with `client` instead of a new one.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the maximum number of days to keep, or `None` for no limit.
+    pub fn with_max_entries(mut self, max_entries: Option<usize>) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets the maximum total size of the cache files in bytes, or `None` for
+    /// no limit.
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Returns the cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Looks up what is playing on WCPE, using the cached playlist for the day
+    /// if it is fresh (see `lookup_cached`). Marks the day as recently used and
+    /// evicts old days if the cache is over its limits.
+    pub fn lookup<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Response<Tz>> {
+        let result = self.lookup_without_evicting(request);
+        self.evict();
+        result
+    }
+
+    fn lookup_without_evicting<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Response<Tz>> {
+        let date = request.station_time().date();
+        self.create_dir();
+        let path = self.path(date);
+        let result = self.client.lookup_cached(request, &path);
+        self.touch(&path);
+        result
+    }
+
+    /// Looks up the whole playlist for the day containing `request.time`,
+    /// using the cached playlist if it is fresh (see
+    /// `Client::lookup_playlist_cached`). Marks the day as recently used and
+    /// evicts old days if the cache is over its limits.
+    pub fn lookup_playlist<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Playlist> {
+        let date = request.station_time().date();
+        self.create_dir();
+        let path = self.path(date);
+        let result = self.client.lookup_playlist_cached(request, &path);
+        self.touch(&path);
+        self.evict();
+        result
+    }
+
+    /// Downloads the playlists for `dates` into the cache, so that later
+    /// lookups on those days are instant. Skips days that are already cached
+    /// and fresh. Downloads at most `concurrency` days at a time (at least
+    /// one), subject to the global rate limit. Returns a result for each date,
+    /// in the same order.
+    pub fn prefetch(
+        &self,
+        dates: &[NaiveDate],
+        concurrency: usize,
+    ) -> Vec<Result<()>> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<Result<()>>>> =
+            dates.iter().map(|_| Mutex::new(None)).collect();
+        thread::scope(|scope| {
+            for _ in 0..concurrency.clamp(1, dates.len().max(1)) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let date = match dates.get(index) {
+                        Some(date) => *date,
+                        None => break,
+                    };
+                    let request = Request::whole_day(date);
+                    let result = match self.lookup_without_evicting(&request) {
+                        // The playlist was cached even if nothing was playing
+                        // at noon.
+                        Ok(_) | Err(Error::NoEntry) => Ok(()),
+                        Err(err) => Err(err),
+                    };
+                    debug!(%date, ok = result.is_ok(), "prefetched");
+                    *results[index].lock().unwrap() = Some(result);
+                });
+            }
+        });
+        self.evict();
+        results
+            .into_iter()
+            .map(|result| result.into_inner().unwrap().unwrap())
+            .collect()
+    }
+
+    /// Downloads the playlists for `dates` one at a time, in order, calling
+    /// `progress` with each date and what happened. Days that are already
+    /// cached and fresh are skipped, so an interrupted sync resumes where it
+    /// left off. Downloads are subject to the global rate limit, so this can
+    /// take a while for many days.
+    pub fn sync(
+        &self,
+        dates: &[NaiveDate],
+        mut progress: impl FnMut(NaiveDate, Result<Synced>),
+    ) {
+        self.create_dir();
+        for &date in dates {
+            let fresh = self
+                .get(date)
+                .is_some_and(|playlist| cache::is_fresh(&playlist, Utc::now()));
+            let result = if fresh {
+                Ok(Synced::AlreadyCached)
+            } else {
+                let request = Request::whole_day(date);
+                let path = self.path(date);
+                let result =
+                    self.client.lookup_playlist_cached(&request, &path);
+                self.touch(&path);
+                result.map(|_| Synced::Downloaded)
+            };
+            debug!(%date, ok = result.is_ok(), "synced");
+            progress(date, result);
+        }
+        self.evict();
+    }
+
+    /// Returns the cached playlist for `date` without downloading anything, or
+    /// `None` if it is not cached. This does not count as using the day.
+    pub fn get(&self, date: NaiveDate) -> Option<Playlist> {
+        let url = playlist_url(eastern_noon(date).ok()?);
+        cache::read(&self.path(date), &url)
+    }
+
+    /// Returns the dates that are cached, in order.
+    pub fn dates(&self) -> Vec<NaiveDate> {
+        self.days().into_iter().map(|day| day.date).collect()
+    }
+
+    /// Returns the total size of the cached days in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.days().iter().map(|day| day.bytes).sum()
+    }
+
+    /// Returns true if the playlist for `date` is cached.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.path(date).exists()
+    }
+
+    /// Marks `date` as important, so it is never evicted.
+    pub fn pin(&self, date: NaiveDate) -> io::Result<()> {
+        let mut pins = self.pins();
+        pins.insert(date);
+        self.write_pins(&pins)
+    }
+
+    /// Undoes `pin`, allowing `date` to be evicted again.
+    pub fn unpin(&self, date: NaiveDate) -> io::Result<()> {
+        let mut pins = self.pins();
+        pins.remove(&date);
+        self.write_pins(&pins)
+    }
+
+    /// Returns the pinned dates, in order.
+    pub fn pinned(&self) -> Vec<NaiveDate> {
+        self.pins().into_iter().collect()
+    }
+
+    /// Removes the least recently used days until the cache is within its
+    /// limits, skipping pinned days.
+    pub fn evict(&self) {
+        let mut days = self.days();
+        let pins = self.pins();
+        // Oldest first.
+        days.sort_by_key(|day| day.used);
+        let mut entries = days.len();
+        let mut bytes: u64 = days.iter().map(|day| day.bytes).sum();
+        for day in days {
+            let over_entries =
+                self.max_entries.is_some_and(|max| entries > max);
+            let over_bytes = self.max_bytes.is_some_and(|max| bytes > max);
+            if !over_entries && !over_bytes {
+                break;
+            }
+            if pins.contains(&day.date) {
+                continue;
+            }
+            match fs::remove_file(&day.path) {
+                Ok(()) => {
+                    debug!(date = %day.date, "evicted from cache");
+                    entries -= 1;
+                    bytes -= day.bytes;
+                }
+                Err(err) => {
+                    warn!(path = %day.path.display(), %err, "failed to evict")
+                }
+            }
+        }
+    }
+
+    /// Removes every cached day, including pinned ones, and returns how many
+    /// were removed. The pins themselves are kept, so pinned days are kept
+    /// again once they are downloaded.
+    pub fn clear(&self) -> io::Result<usize> {
+        self.remove(|_| true)
+    }
+
+    /// Removes the cached days before `date`, skipping pinned days, and returns
+    /// how many were removed.
+    pub fn prune(&self, date: NaiveDate) -> io::Result<usize> {
+        let pins = self.pins();
+        self.remove(|day| day.date < date && !pins.contains(&day.date))
+    }
+
+    fn remove(&self, filter: impl Fn(&CachedDay) -> bool) -> io::Result<usize> {
+        let mut removed = 0;
+        for day in self.days().into_iter().filter(filter) {
+            fs::remove_file(&day.path)?;
+            debug!(date = %day.date, "removed from cache");
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn path(&self, date: NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}.json", date.format("%Y-%m-%d")))
+    }
+
+    fn create_dir(&self) {
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            let path = self.dir.display();
+            warn!(path = %path, %err, "failed to create cache directory");
+        }
+    }
+
+    /// Marks the day cached in `path` as used now, if it exists.
+    fn touch(&self, path: &Path) {
+        let file = match fs::File::options().append(true).open(path) {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        if let Err(err) = file.set_modified(SystemTime::now()) {
+            warn!(path = %path.display(), %err, "failed to touch cache file");
+        }
+    }
+
+    /// Returns the cached days, in order of date.
+    fn days(&self) -> Vec<CachedDay> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut days: Vec<CachedDay> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let stem = path.file_stem()?.to_str()?;
+                let date = NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok()?;
+                if path.extension()? != "json" {
+                    return None;
+                }
+                let metadata = fs::metadata(&path).ok()?;
+                Some(CachedDay {
+                    date,
+                    bytes: metadata.len(),
+                    used: metadata.modified().ok()?,
+                    path,
+                })
+            })
+            .collect();
+        days.sort_by_key(|day| day.date);
+        days
+    }
+
+    fn pins(&self) -> BTreeSet<NaiveDate> {
+        let text = fs::read_to_string(self.dir.join(PINS_FILE));
+        text.unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect()
+    }
+
+    fn write_pins(&self, pins: &BTreeSet<NaiveDate>) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let text: String =
+            pins.iter().map(|date| format!("{}\n", date)).collect();
+        fs::write(self.dir.join(PINS_FILE), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::time::Duration};
+
+    /// Returns an empty cache in a temporary directory named after `test`.
+    fn cache(test: &str) -> Cache {
+        let dir = std::env::temp_dir().join(format!("wowcpe-test-{}", test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Cache::new(dir)
+    }
+
+    /// Adds a fake cached day, last used `age` seconds ago.
+    fn add(cache: &Cache, day: u32, age: u64) {
+        let path = cache.path(NaiveDate::from_ymd(2020, 9, day));
+        fs::write(&path, "{}").unwrap();
+        let used = SystemTime::now() - Duration::from_secs(age);
+        let file = fs::File::options().append(true).open(&path).unwrap();
+        file.set_modified(used).unwrap();
+    }
+
+    fn days(cache: &Cache) -> Vec<u32> {
+        use chrono::Datelike;
+        cache.dates().iter().map(|date| date.day()).collect()
+    }
+
+    #[test]
+    fn test_evict_entries() {
+        let cache = cache("evict-entries").with_max_entries(Some(2));
+        add(&cache, 1, 10);
+        add(&cache, 2, 30);
+        add(&cache, 3, 20);
+        cache.evict();
+        assert_eq!(vec![1, 3], days(&cache));
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_evict_bytes() {
+        // Each fake day is 2 bytes.
+        let cache = cache("evict-bytes").with_max_bytes(Some(3));
+        add(&cache, 1, 30);
+        add(&cache, 2, 10);
+        cache.evict();
+        assert_eq!(vec![2], days(&cache));
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_evict_pinned() {
+        let cache = cache("evict-pinned").with_max_entries(Some(1));
+        add(&cache, 1, 30);
+        add(&cache, 2, 20);
+        add(&cache, 3, 10);
+        cache.pin(NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        cache.evict();
+        assert_eq!(vec![1], days(&cache));
+
+        cache.unpin(NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        assert!(cache.pinned().is_empty());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        let cache = cache("prune");
+        add(&cache, 1, 0);
+        add(&cache, 2, 0);
+        add(&cache, 3, 0);
+        add(&cache, 4, 0);
+        cache.pin(NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        assert_eq!(1, cache.prune(NaiveDate::from_ymd(2020, 9, 3)).unwrap());
+        assert_eq!(vec![1, 3, 4], days(&cache));
+        assert_eq!(6, cache.bytes());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_clear() {
+        let cache = cache("clear");
+        add(&cache, 1, 0);
+        add(&cache, 2, 0);
+        cache.pin(NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        assert_eq!(2, cache.clear().unwrap());
+        assert!(cache.dates().is_empty());
+        assert_eq!(vec![NaiveDate::from_ymd(2020, 9, 1)], cache.pinned());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
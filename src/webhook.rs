@@ -0,0 +1,210 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Posting JSON to webhooks, signed so that receivers can verify it.
+
+use {
+    crate::{scrobble::post, Error, Result},
+    curl::easy::Easy,
+    ring::hmac,
+    std::{thread, time::Duration},
+    tracing::warn,
+};
+
+/// The header with the signature of the body, like "sha256=" followed by the
+/// hex HMAC-SHA256 of the body keyed with the secret.
+pub const SIGNATURE_HEADER: &str = "X-Wowcpe-Signature";
+
+/// A URL to post JSON to, e.g., each time the piece changes.
+///
+/// If it has a secret, each request has a `SIGNATURE_HEADER` that receivers
+/// can check to make sure the request came from someone who knows it. Failed
+/// requests are retried a few times, waiting longer after each one, unless the
+/// receiver rejected them with a 4xx status.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    url: String,
+    secret: Option<String>,
+    retries: u32,
+    delay: Duration,
+}
+
+impl Webhook {
+    /// Creates a webhook that posts to `url`, retrying 3 times starting after
+    /// 1 second.
+    pub fn new(url: &str) -> Self {
+        Webhook {
+            url: url.to_string(),
+            secret: None,
+            retries: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Signs requests with `secret`.
+    pub fn with_secret(self, secret: &str) -> Self {
+        Webhook {
+            secret: Some(secret.to_string()),
+            ..self
+        }
+    }
+
+    /// Retries failed requests `retries` times, waiting `delay` before the
+    /// first retry and twice as long before each one after.
+    pub fn with_retries(self, retries: u32, delay: Duration) -> Self {
+        Webhook {
+            retries,
+            delay,
+            ..self
+        }
+    }
+
+    /// Returns the URL requests are posted to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Posts the JSON `body` for a new piece. Returns the last error if every
+    /// attempt fails.
+    pub fn send(&self, body: &str) -> Result<()> {
+        self.send_event("piece", body)
+    }
+
+    /// Posts the JSON `body` for `event`, e.g., "reminder", which receivers
+    /// can tell apart by the `X-Wowcpe-Event` header.
+    pub fn send_event(&self, event: &str, body: &str) -> Result<()> {
+        let mut headers = vec![format!("X-Wowcpe-Event: {}", event)];
+        if let Some(secret) = &self.secret {
+            headers.push(format!(
+                "{}: {}",
+                SIGNATURE_HEADER,
+                sign(secret, body)
+            ));
+        }
+        let mut easy = Easy::new();
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            let result = post(
+                &mut easy,
+                &self.url,
+                "Content-Type: application/json",
+                &headers,
+                body,
+            );
+            let err = match result {
+                Ok((code, _)) if (200..300).contains(&code) => return Ok(()),
+                Ok((code, _)) => Error::HttpStatus(code, code.to_string()),
+                Err(err) => err,
+            };
+            // Client errors will fail again, except for rate limiting.
+            let permanent = matches!(
+                err,
+                Error::HttpStatus(code, _) if (400..500).contains(&code) && code != 429
+            );
+            if permanent || attempt == self.retries {
+                return Err(err);
+            }
+            warn!(url = %self.url, %err, "retrying webhook");
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// Returns the signature header's value for `body`.
+fn sign(secret: &str, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body.as_bytes());
+    let hex: String = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{BufRead, BufReader, Read, Write},
+            net::TcpListener,
+        },
+    };
+
+    #[test]
+    fn test_sign() {
+        // From RFC 4231, test case 2.
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign("Jefe", "what do ya want for nothing?")
+        );
+    }
+
+    /// Serves one response for each of `statuses`, and returns the URL and a
+    /// handle that returns each request's headers and body.
+    fn server(
+        statuses: &'static [u32],
+    ) -> (String, thread::JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut head = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                requests.push((head, String::from_utf8(body).unwrap()));
+                write!(
+                    &stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_send_retries() {
+        let (url, handle) = server(&[503, 200]);
+        let webhook = Webhook::new(&url)
+            .with_secret("key")
+            .with_retries(3, Duration::ZERO);
+        webhook.send("{\"a\":1}").unwrap();
+        let requests = handle.join().unwrap();
+        assert_eq!(2, requests.len());
+        let (head, body) = &requests[1];
+        assert_eq!("{\"a\":1}", body);
+        let signature = format!("{}: {}", SIGNATURE_HEADER, sign("key", body));
+        assert!(head.contains(&signature));
+        assert!(head.contains("X-Wowcpe-Event: piece\r\n"));
+    }
+
+    #[test]
+    fn test_send_client_error() {
+        let (url, handle) = server(&[404]);
+        let webhook = Webhook::new(&url).with_retries(3, Duration::ZERO);
+        let err = webhook.send("{}").unwrap_err();
+        assert!(matches!(err, Error::HttpStatus(404, _)));
+        assert_eq!(1, handle.join().unwrap().len());
+    }
+}
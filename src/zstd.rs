@@ -0,0 +1,70 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Compressing cache files with zstd, enabled by the `zstd` feature.
+
+use {
+    crate::cache::ZSTD_MAGIC,
+    std::io::{self, Read},
+};
+
+/// The compression level. Level 3 is zstd's default, and compresses playlists
+/// well without slowing down lookups.
+const LEVEL: i32 = 3;
+
+/// The most bytes `decompress` produces. A cache file holds one day's playlist,
+/// and optionally its HTML, which is far smaller than this. The limit stops a
+/// corrupt or malicious file from exhausting memory.
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Compresses `data` into a single zstd frame, which starts with `ZSTD_MAGIC`.
+pub(crate) fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(data, LEVEL)
+}
+
+/// Decompresses a zstd frame written by `compress`. Streams the output rather
+/// than trusting the size recorded in the frame, and fails if it exceeds
+/// `MAX_DECOMPRESSED_SIZE`.
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    debug_assert!(data.starts_with(&ZSTD_MAGIC));
+    let decoder = zstd::stream::read::Decoder::new(data)?;
+    let mut out = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut out)?;
+    if out.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "zstd frame is too large",
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = "Bach ".repeat(1000);
+        let compressed = compress(data.as_bytes()).unwrap();
+        assert!(compressed.starts_with(&ZSTD_MAGIC));
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(data.as_bytes(), decompress(&compressed).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let mut data = compress(b"Handel").unwrap();
+        data.truncate(data.len() - 1);
+        assert!(decompress(&data).is_err());
+    }
+
+    #[test]
+    fn test_decompress_too_large() {
+        let size = MAX_DECOMPRESSED_SIZE as usize + 1;
+        let data = compress(&vec![0; size]).unwrap();
+        let err = decompress(&data).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -1,20 +1,170 @@
 use {
     chrono::{Duration, Local, TimeZone},
-    wowcpe::Request,
+    wowcpe::{
+        Cache, Client, Eastern, Error, Fixtures, Program, Request, Server,
+        Synced,
+    },
 };
 
+/// Returns a client that replays the pages in tests/fixtures. Set
+/// `WOWCPE_RECORD` to record them again from the live site.
+fn client() -> Client {
+    let client = Client::new();
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    client.set_fixtures(Some(Fixtures::from_env(dir)));
+    client
+}
+
 #[test]
+fn test_fixture() {
+    let request = Request {
+        time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+    };
+    let response = client().lookup(&request).unwrap();
+
+    assert!(response.start_time <= request.time);
+    assert!(response.end_time >= request.time);
+    assert_eq!(Program::SleepersAwake, response.program);
+    assert_eq!(Some("Franz Liszt"), response.composer.as_deref());
+    assert!(response.title.is_some());
+}
+
+#[test]
+fn test_fixture_playlist() {
+    let request = Request::whole_day(chrono::NaiveDate::from_ymd(2020, 9, 1));
+    let playlist = client().lookup_playlist(&request).unwrap();
+
+    assert_eq!(2, playlist.entries().len());
+    assert!(playlist.warnings().is_empty());
+}
+
+#[test]
+fn test_fixture_http_error() {
+    let request = Request {
+        time: Eastern.ymd(2020, 9, 2).and_hms(12, 0, 0),
+    };
+    let err = client().lookup(&request).unwrap_err();
+
+    assert!(matches!(err, Error::HttpStatus(404, _)));
+}
+
+#[test]
+fn test_fixture_lookup_many() {
+    let at = |d, h| Request {
+        time: Eastern.ymd(2020, 9, d).and_hms(h, 0, 0),
+    };
+    let requests = [at(1, 3), at(2, 12), at(1, 7), at(2, 13)];
+    let results = client().lookup_many(&requests);
+
+    assert_eq!(4, results.len());
+    let composer = |i: usize| results[i].as_ref().unwrap().composer.clone();
+    assert_eq!(Some("Franz Liszt".to_string()), composer(0));
+    assert_eq!(Some("George Frideric Handel".to_string()), composer(2));
+    assert!(matches!(results[1], Err(Error::HttpStatus(404, _))));
+    assert!(matches!(results[3], Err(Error::HttpStatus(404, _))));
+}
+
+#[test]
+fn test_fixture_lookup_previous() {
+    let request = Request {
+        time: Eastern.ymd(2020, 9, 1).and_hms(7, 0, 0),
+    };
+    let previous = client().lookup_previous(&request, 1).unwrap();
+
+    assert_eq!(1, previous.len());
+    assert_eq!(Some("Franz Liszt"), previous[0].composer.as_deref());
+}
+
+#[test]
+fn test_fixture_is_available() {
+    let client = client();
+    let date = |d| chrono::NaiveDate::from_ymd(2020, 9, d);
+
+    assert!(client.is_available(date(1)).unwrap());
+    assert!(!client.is_available(date(2)).unwrap());
+}
+
+#[test]
+fn test_fixture_cache() {
+    let dir = std::env::temp_dir().join("wowcpe-test-fixture-cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir).with_client(client());
+    let request = Request {
+        time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+    };
+    let response = cache.lookup(&request).unwrap();
+    let dates = cache.dates();
+    let playlist = cache.lookup_playlist(&request).unwrap();
+    let cached = cache.get(chrono::NaiveDate::from_ymd(2020, 9, 1));
+    let uncached = cache.get(chrono::NaiveDate::from_ymd(2020, 9, 2));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        Some(playlist.entries()),
+        cached.as_ref().map(|p| p.entries())
+    );
+    assert!(uncached.is_none());
+
+    assert_eq!(Some("Franz Liszt"), response.composer.as_deref());
+    assert_eq!(vec![chrono::NaiveDate::from_ymd(2020, 9, 1)], dates);
+    let upcoming = playlist.after(request.time);
+    assert_eq!(1, upcoming.len());
+    assert_eq!(
+        Some("George Frideric Handel"),
+        upcoming[0].composer.as_deref()
+    );
+}
+
+#[test]
+fn test_fixture_prefetch() {
+    let dir = std::env::temp_dir().join("wowcpe-test-fixture-prefetch");
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir).with_client(client());
+    let date = |d| chrono::NaiveDate::from_ymd(2020, 9, d);
+    let results = cache.prefetch(&[date(1), date(2)], 2);
+    let dates = cache.dates();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::HttpStatus(404, _))));
+    assert_eq!(vec![date(1)], dates);
+}
+
+#[test]
+fn test_fixture_sync() {
+    let dir = std::env::temp_dir().join("wowcpe-test-fixture-sync");
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir).with_client(client());
+    let date = |d| chrono::NaiveDate::from_ymd(2020, 9, d);
+    let mut first = Vec::new();
+    cache.sync(&[date(1), date(2)], |date, result| {
+        first.push((date, result))
+    });
+    let mut second = Vec::new();
+    cache.sync(&[date(1)], |date, result| second.push((date, result)));
+    let dates = cache.dates();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(first[0], (d, Ok(Synced::Downloaded)) if d == date(1)));
+    assert!(matches!(first[1], (_, Err(Error::HttpStatus(404, _)))));
+    assert!(matches!(second[0], (_, Ok(Synced::AlreadyCached))));
+    assert_eq!(vec![date(1)], dates);
+}
+
+#[test]
+#[ignore = "needs network access"]
 fn test_now() {
     let request = Request { time: Local::now() };
     let response = wowcpe::lookup(&request).unwrap();
 
     assert!(response.start_time <= request.time);
     assert!(response.end_time >= request.time);
-    assert!(!response.program.is_empty());
-    assert!(!response.title.is_empty());
+    assert!(!response.program.name().is_empty());
+    assert!(response.title.is_some());
 }
 
 #[test]
+#[ignore = "needs network access"]
 fn test_6_days_ago() {
     let request = Request {
         time: Local::now() - Duration::days(6),
@@ -23,8 +173,8 @@ fn test_6_days_ago() {
 
     assert!(response.start_time <= request.time);
     assert!(response.end_time >= request.time);
-    assert!(!response.program.is_empty());
-    assert!(!response.title.is_empty());
+    assert!(!response.program.name().is_empty());
+    assert!(response.title.is_some());
 }
 
 #[test]
@@ -36,3 +186,36 @@ fn test_long_ago() {
 
     assert!(err.to_string().contains("no data"));
 }
+
+#[test]
+fn test_fixture_server() {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    let dir = std::env::temp_dir().join("wowcpe-test-fixture-server");
+    let _ = std::fs::remove_dir_all(&dir);
+    let server = Server::new(Cache::new(&dir).with_client(client()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || server.serve(listener));
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)
+            .unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+    };
+    let day = get("/day/2020-09-01");
+    let missing = get("/day/2020-09-02");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(day.starts_with("HTTP/1.1 200 OK\r\n"));
+    let body = day.split("\r\n\r\n").nth(1).unwrap();
+    let entries: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!("Franz Liszt", entries[0]["composer"]);
+    assert_eq!(2, entries.as_array().unwrap().len());
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
@@ -0,0 +1,140 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Converts the built-in TOML data in `src` into Rust, so that the crate only
+//! needs a TOML parser at run time for the optional `toml` feature.
+
+use {
+    std::{env, fmt::Write, fs, path::Path},
+    toml::{value::Table, Value},
+};
+
+fn main() {
+    let out = env::var_os("OUT_DIR").unwrap();
+    let out = Path::new(&out);
+    fs::write(out.join("programs.rs"), programs()).unwrap();
+    fs::write(out.join("specialty.rs"), specialty()).unwrap();
+}
+
+/// Generates the entries of `programs.toml` as a `Vec` of names and
+/// `ProgramInfo`s.
+fn programs() -> String {
+    let table = read("programs.toml");
+    let mut code = String::from("vec![\n");
+    for (name, info) in &table {
+        let info = fields(name, info, &["description", "url", "hosts"]);
+        writeln!(
+            code,
+            "({:?}, ProgramInfo {{ description: {}, url: {}, hosts: {} }}),",
+            name,
+            string(name, info.get("description")),
+            optional(name, info.get("url")),
+            strings(name, info.get("hosts")),
+        )
+        .unwrap();
+    }
+    code.push(']');
+    code
+}
+
+/// Generates the contents of `specialty.toml` as a `ScheduleFile`.
+fn specialty() -> String {
+    let table = read("specialty.toml");
+    let mut code = String::from("ScheduleFile { holiday: vec![\n");
+    for holiday in array(&table, "holiday") {
+        let h =
+            fields("holiday", holiday, &["program", "date", "start", "end"]);
+        writeln!(
+            code,
+            "HolidayFile {{ program: {}, date: {}, start: {}, end: {} }},",
+            string("holiday", h.get("program")),
+            string("holiday", h.get("date")),
+            optional("holiday", h.get("start")),
+            optional("holiday", h.get("end")),
+        )
+        .unwrap();
+    }
+    code.push_str("], rule: vec![\n");
+    for rule in array(&table, "rule") {
+        let r = fields(
+            "rule",
+            rule,
+            &["program", "weekday", "start", "end", "days"],
+        );
+        let days = match r.get("days").map(|days| days.as_array()) {
+            None => "None".to_string(),
+            Some(Some(days)) if days.len() == 2 => format!(
+                "Some(({}, {}))",
+                days[0].as_integer().expect("days are integers"),
+                days[1].as_integer().expect("days are integers"),
+            ),
+            Some(_) => panic!("rule days must be a pair"),
+        };
+        writeln!(
+            code,
+            "RuleFile {{ program: {}, weekday: {}, start: {}, end: {}, \
+             days: {} }},",
+            string("rule", r.get("program")),
+            string("rule", r.get("weekday")),
+            string("rule", r.get("start")),
+            string("rule", r.get("end")),
+            days,
+        )
+        .unwrap();
+    }
+    code.push_str("] }");
+    code
+}
+
+fn read(name: &str) -> Table {
+    let path = Path::new("src").join(name);
+    println!("cargo:rerun-if-changed={}", path.display());
+    let input = fs::read_to_string(&path).unwrap();
+    toml::from_str(&input)
+        .unwrap_or_else(|err| panic!("{} is invalid: {}", name, err))
+}
+
+/// Returns the entries of the array of tables `key` in `table`.
+fn array<'a>(table: &'a Table, key: &str) -> &'a [Value] {
+    match table.get(key) {
+        Some(Value::Array(values)) => values,
+        Some(_) => panic!("{} must be an array of tables", key),
+        None => &[],
+    }
+}
+
+/// Returns `value` as a table, checking that it has only the `allowed` keys.
+fn fields<'a>(context: &str, value: &'a Value, allowed: &[&str]) -> &'a Table {
+    let table = value
+        .as_table()
+        .unwrap_or_else(|| panic!("{} must be a table", context));
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str()))
+    {
+        panic!("unknown field {:?} in {}", key, context);
+    }
+    table
+}
+
+fn string(context: &str, value: Option<&Value>) -> String {
+    match value.and_then(Value::as_str) {
+        Some(s) => format!("{:?}.to_owned()", s),
+        None => panic!("missing string in {}", context),
+    }
+}
+
+fn optional(context: &str, value: Option<&Value>) -> String {
+    match value {
+        Some(_) => format!("Some({})", string(context, value)),
+        None => "None".to_string(),
+    }
+}
+
+fn strings(context: &str, value: Option<&Value>) -> String {
+    let values = match value {
+        Some(Value::Array(values)) => values.as_slice(),
+        Some(_) => panic!("expected an array in {}", context),
+        None => &[],
+    };
+    let values: Vec<String> =
+        values.iter().map(|v| string(context, Some(v))).collect();
+    format!("vec![{}]", values.join(", "))
+}
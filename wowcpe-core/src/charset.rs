@@ -0,0 +1,116 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Decoding HTML in whatever charset the server used.
+
+use {
+    crate::trace::warn,
+    encoding_rs::{Encoding, UTF_8},
+};
+
+/// Decodes the bytes of an HTML page into a string.
+///
+/// The charset is taken from `content_type` (the value of the Content-Type
+/// header) if given, otherwise from a `<meta>` tag near the start of the page,
+/// and otherwise assumed to be UTF-8. A byte order mark overrides all of these.
+/// Rather than failing on invalid bytes, this replaces them with U+FFFD and
+/// emits a warning.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(charset_from_content_type)
+        .map(str::to_string)
+        .or_else(|| charset_from_meta(bytes))
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, actual, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        warn!(charset = actual.name(), "replaced invalid bytes in HTML");
+    }
+    text.into_owned()
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = split_once(param.trim(), '=')?;
+        if name.eq_ignore_ascii_case("charset") {
+            Some(value.trim_matches(['"', '\'']))
+        } else {
+            None
+        }
+    })
+}
+
+fn charset_from_meta(bytes: &[u8]) -> Option<String> {
+    // Browsers only look this far for the meta tag.
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let index = head.find("<meta")?;
+    let index = index + head[index..].find("charset=")? + "charset=".len();
+    let label: String = head[index..]
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|&c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        .collect();
+    if label.is_empty() {
+        None
+    } else {
+        Some(label)
+    }
+}
+
+fn split_once(s: &str, delimiter: char) -> Option<(&str, &str)> {
+    let index = s.find(delimiter)?;
+    Some((&s[..index], &s[index + delimiter.len_utf8()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_utf8() {
+        let html = "<p>Café</p>";
+        assert_eq!(html, decode_html(html.as_bytes(), None));
+        assert_eq!(
+            html,
+            decode_html(html.as_bytes(), Some("text/html; charset=UTF-8"))
+        );
+    }
+
+    #[test]
+    fn test_decode_header_latin1() {
+        let bytes = b"<p>Caf\xe9</p>";
+        assert_eq!(
+            "<p>Café</p>",
+            decode_html(bytes, Some("text/html; charset=ISO-8859-1"))
+        );
+    }
+
+    #[test]
+    fn test_decode_meta_latin1() {
+        let bytes = b"<meta charset=\"iso-8859-1\"><p>Caf\xe9</p>";
+        assert_eq!(
+            "<meta charset=\"iso-8859-1\"><p>Café</p>",
+            decode_html(bytes, None)
+        );
+
+        let bytes = b"<meta http-equiv=\"Content-Type\" \
+            content=\"text/html; charset=windows-1252\"><p>\x93Hi\x94</p>";
+        assert!(decode_html(bytes, Some("text/html")).ends_with("“Hi”</p>"));
+    }
+
+    #[test]
+    fn test_decode_invalid_lossy() {
+        let bytes = b"<p>Caf\xe9</p>";
+        assert_eq!("<p>Caf\u{fffd}</p>", decode_html(bytes, None));
+    }
+
+    #[test]
+    fn test_charset_from_content_type() {
+        assert_eq!(None, charset_from_content_type("text/html"));
+        assert_eq!(
+            Some("utf-8"),
+            charset_from_content_type("text/html; Charset=\"utf-8\"")
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Rendering a day's playlist as a digest for reading, e.g., in an email.
+
+use crate::{feed::escape, Entry, Playlist, Program};
+
+/// Returns the title of the digest for `playlist`, e.g., "WCPE Playlist for
+/// Tuesday, September 1, 2020".
+pub fn digest_title(playlist: &Playlist) -> String {
+    format!(
+        "WCPE Playlist for {}",
+        playlist.date().format("%A, %B %-d, %Y")
+    )
+}
+
+/// Returns every piece in `playlist` as plain text, grouped under a heading for
+/// each program. Times are in the station's time zone, since the digest
+/// recaps the station's day.
+pub fn digest_text(playlist: &Playlist) -> String {
+    let title = digest_title(playlist);
+    let mut text = format!(
+        "{}\n{}\nTimes are Eastern.\n",
+        title,
+        "=".repeat(title.chars().count())
+    );
+    for (program, entries) in programs(playlist) {
+        let name = program.name();
+        text.push_str(&format!(
+            "\n{}\n{}\n",
+            name,
+            "-".repeat(name.chars().count())
+        ));
+        for entry in entries {
+            let time = start(entry);
+            let mut lines = piece(entry).into_iter();
+            text.push_str(&format!(
+                "{:>8}  {}\n",
+                time,
+                lines.next().unwrap_or_default()
+            ));
+            for line in lines {
+                text.push_str(&format!("{:8}  {}\n", "", line));
+            }
+        }
+    }
+    text
+}
+
+/// Returns every piece in `playlist` as an HTML document, with a table for
+/// each program. Like `digest_text`, times are in the station's time zone.
+pub fn digest_html(playlist: &Playlist) -> String {
+    let title = escape(&digest_title(playlist));
+    let mut html = format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body>\n\
+         <h1>{title}</h1>\n\
+         <p>Times are Eastern.</p>\n",
+        title = title
+    );
+    for (program, entries) in programs(playlist) {
+        html.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n",
+            escape(program.name())
+        ));
+        for entry in entries {
+            let mut lines = piece(entry).into_iter().map(|line| escape(&line));
+            let first = lines.next().unwrap_or_default();
+            let rest: String = lines
+                .map(|line| format!("<br><small>{}</small>", line))
+                .collect();
+            html.push_str(&format!(
+                "<tr><td valign=\"top\" nowrap>{}</td><td><b>{}</b>{}</td></tr>\n",
+                start(entry),
+                first,
+                rest
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Splits `playlist` into runs of entries in the same program.
+fn programs(playlist: &Playlist) -> Vec<(&Program, &[Entry])> {
+    let entries = playlist.entries();
+    let mut runs = Vec::new();
+    let mut first = 0;
+    for i in 1..=entries.len() {
+        if i == entries.len() || entries[i].program != entries[first].program {
+            runs.push((&entries[first].program, &entries[first..i]));
+            first = i;
+        }
+    }
+    runs
+}
+
+fn start(entry: &Entry) -> String {
+    entry
+        .start_time
+        .format("%l:%M %p")
+        .to_string()
+        .trim()
+        .to_string()
+}
+
+/// Returns the lines describing the piece: "Composer: Title", then the
+/// performers and record label if listed.
+fn piece(entry: &Entry) -> Vec<String> {
+    let mut lines = vec![match (&entry.composer, &entry.title) {
+        (Some(composer), Some(title)) => format!("{}: {}", composer, title),
+        (Some(name), None) | (None, Some(name)) => name.clone(),
+        (None, None) => "Unknown piece".to_string(),
+    }];
+    let recording: Vec<&str> = [&entry.performers, &entry.record_label]
+        .iter()
+        .filter_map(|field| field.as_deref())
+        .collect();
+    if !recording.is_empty() {
+        lines.push(recording.join(" · "));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::HTML, chrono::NaiveDate};
+
+    fn playlist() -> Playlist {
+        Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap()
+    }
+
+    #[test]
+    fn test_digest_title() {
+        assert_eq!(
+            "WCPE Playlist for Tuesday, September 1, 2020",
+            digest_title(&playlist())
+        );
+    }
+
+    #[test]
+    fn test_digest_text() {
+        let text = digest_text(&playlist());
+        assert!(text
+            .starts_with("WCPE Playlist for Tuesday, September 1, 2020\n===="));
+        assert!(text.contains(
+            "\nSleepers, Awake!\n----------------\n12:01 AM  Franz Liszt: Tasso"
+        ));
+        assert!(text.contains("\n 6:00 AM  George Frideric Handel"));
+    }
+
+    #[test]
+    fn test_digest_html() {
+        let html = digest_html(&playlist());
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.ends_with("</html>\n"));
+        assert!(html.contains("<h2>Sleepers, Awake!</h2>"));
+        assert!(html.contains("<b>Franz Liszt: Tasso: Lament &amp;"));
+        assert_eq!(2, html.matches("<tr>").count());
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! The station's time zone.
+
+use {
+    chrono::{
+        offset::LocalResult, Datelike, Duration, FixedOffset, NaiveDate,
+        NaiveDateTime, Offset, TimeZone, Weekday,
+    },
+    std::fmt,
+};
+
+/// The US Eastern time zone, where the station is.
+///
+/// This implements the United States daylight saving rules since 1967 rather
+/// than reading the tz database, so that this crate does not need `chrono-tz`.
+/// Times before 1967 are in standard time.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Eastern;
+
+/// The offset of a time in `Eastern`. Displays as "EST" or "EDT".
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EasternOffset {
+    /// Eastern Standard Time, UTC−5.
+    Standard,
+    /// Eastern Daylight Time, UTC−4.
+    Daylight,
+}
+
+impl Offset for EasternOffset {
+    fn fix(&self) -> FixedOffset {
+        let hours = match self {
+            EasternOffset::Standard => 5,
+            EasternOffset::Daylight => 4,
+        };
+        FixedOffset::west_opt(hours * 3600).unwrap()
+    }
+}
+
+impl fmt::Display for EasternOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EasternOffset::Standard => "EST",
+            EasternOffset::Daylight => "EDT",
+        })
+    }
+}
+
+impl TimeZone for Eastern {
+    type Offset = EasternOffset;
+
+    fn from_offset(_: &EasternOffset) -> Self {
+        Eastern
+    }
+
+    fn offset_from_local_date(
+        &self,
+        local: &NaiveDate,
+    ) -> LocalResult<EasternOffset> {
+        // Transitions happen at 2am, so noon always has a single offset.
+        LocalResult::Single(
+            self.offset_from_local_datetime(
+                &local.and_hms_opt(12, 0, 0).unwrap(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn offset_from_local_datetime(
+        &self,
+        local: &NaiveDateTime,
+    ) -> LocalResult<EasternOffset> {
+        // Try the local time under each offset, and keep the ones that agree
+        // with the offset in effect at the resulting moment.
+        let valid = |offset: EasternOffset| {
+            let shift =
+                Duration::seconds(offset.fix().local_minus_utc().into());
+            self.offset_from_utc_datetime(&(*local - shift)) == offset
+        };
+        match (
+            valid(EasternOffset::Daylight),
+            valid(EasternOffset::Standard),
+        ) {
+            (true, true) => LocalResult::Ambiguous(
+                EasternOffset::Daylight,
+                EasternOffset::Standard,
+            ),
+            (true, false) => LocalResult::Single(EasternOffset::Daylight),
+            (false, true) => LocalResult::Single(EasternOffset::Standard),
+            (false, false) => LocalResult::None,
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> EasternOffset {
+        self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> EasternOffset {
+        match daylight_period(utc.year()) {
+            Some((start, end)) if start <= *utc && *utc < end => {
+                EasternOffset::Daylight
+            }
+            _ => EasternOffset::Standard,
+        }
+    }
+}
+
+/// Returns the start and end of daylight saving time in `year`, in UTC.
+/// Clocks spring forward at 2am standard time and fall back at 2am daylight
+/// time.
+fn daylight_period(year: i32) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let (start, end) = match year {
+        2007.. => (sunday(year, 3, 2), sunday(year, 11, 1)),
+        1987..=2006 => (sunday(year, 4, 1), last_sunday(year, 10)),
+        // The energy crisis brought daylight saving time forward.
+        1974 => (NaiveDate::from_ymd_opt(1974, 1, 6)?, last_sunday(year, 10)),
+        1975 => (NaiveDate::from_ymd_opt(1975, 2, 23)?, last_sunday(year, 10)),
+        1967..=1986 => (last_sunday(year, 4), last_sunday(year, 10)),
+        _ => return None,
+    };
+    Some((start.and_hms_opt(7, 0, 0)?, end.and_hms_opt(6, 0, 0)?))
+}
+
+/// Returns the `n`th Sunday of a month.
+fn sunday(year: i32, month: u32, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n).unwrap()
+}
+
+/// Returns the last Sunday of a month.
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, 5)
+        .unwrap_or_else(|| sunday(year, month, 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Utc};
+
+    #[test]
+    fn test_offsets() {
+        let summer = Eastern.ymd(2020, 9, 1).and_hms(12, 0, 0);
+        let winter = Eastern.ymd(2020, 12, 1).and_hms(12, 0, 0);
+        assert_eq!(Utc.ymd(2020, 9, 1).and_hms(16, 0, 0), summer);
+        assert_eq!(Utc.ymd(2020, 12, 1).and_hms(17, 0, 0), winter);
+        assert_eq!("2020-09-01 12:00:00 EDT", summer.to_string());
+        assert_eq!("2020-12-01 12:00:00 EST", winter.to_string());
+    }
+
+    #[test]
+    fn test_transitions() {
+        let spring = NaiveDate::from_ymd(2021, 3, 14);
+        assert_eq!(
+            LocalResult::None,
+            Eastern.from_local_datetime(&spring.and_hms(2, 30, 0))
+        );
+        let fall = NaiveDate::from_ymd(2021, 11, 7).and_hms(1, 30, 0);
+        assert_eq!(
+            LocalResult::Ambiguous(
+                Utc.ymd(2021, 11, 7).and_hms(5, 30, 0),
+                Utc.ymd(2021, 11, 7).and_hms(6, 30, 0),
+            ),
+            Eastern
+                .from_local_datetime(&fall)
+                .map(|time| time.with_timezone(&Utc))
+        );
+    }
+
+    #[test]
+    fn test_matches_tz_database() {
+        // Transitions happen on the hour, so hourly steps catch each one.
+        let tz = chrono_tz::US::Eastern;
+        let mut time = Utc.ymd(1967, 1, 1).and_hms(0, 0, 0);
+        while time.year() < 2038 {
+            assert_eq!(
+                time.with_timezone(&tz).naive_local(),
+                time.with_timezone(&Eastern).naive_local(),
+                "{}",
+                time
+            );
+            time += Duration::hours(1);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Rendering a playlist as an Atom feed.
+
+use crate::{eastern_noon, playlist_url, Playlist, StationTime};
+
+/// Returns an Atom feed (RFC 4287) with an entry for each piece in `playlist`
+/// that has started by `now`, newest first, so that feed readers can follow
+/// the station. Rendering the playlist again as it gets new entries adds items
+/// without changing the old ones, since their ids come from their start times.
+pub fn playlist_atom(
+    playlist: &Playlist,
+    now: impl Into<StationTime>,
+) -> String {
+    let now = now.into();
+    let started: Vec<_> = playlist
+        .iter()
+        .take_while(|entry| entry.start_time <= now)
+        .collect();
+    let link = match playlist.source_url() {
+        Some(url) => url.to_string(),
+        None => eastern_noon(playlist.date())
+            .map(playlist_url)
+            .unwrap_or_default(),
+    };
+    let updated = started.last().map_or(now, |entry| entry.start_time);
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <title>WCPE Playlist for {date}</title>\n\
+         <id>tag:theclassicalstation.org,2017:playlist/{date}</id>\n\
+         <link href=\"{link}\"/>\n\
+         <updated>{updated}</updated>\n\
+         <author><name>WCPE</name></author>\n",
+        date = playlist.date().format("%Y-%m-%d"),
+        link = escape(&link),
+        updated = updated.to_utc().to_rfc3339(),
+    );
+    for entry in started.iter().rev() {
+        let start = entry.start_time.to_utc().to_rfc3339();
+        let title = match (&entry.composer, &entry.title) {
+            (Some(composer), Some(title)) => format!("{}: {}", composer, title),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => entry.program.name().to_string(),
+        };
+        let mut summary = format!("Program: {}", entry.program.name());
+        if let Some(performers) = &entry.performers {
+            summary.push_str(&format!("\nPerformers: {}", performers));
+        }
+        if let Some(label) = &entry.record_label {
+            summary.push_str(&format!("\nRecord label: {}", label));
+        }
+        feed.push_str(&format!(
+            "<entry>\n\
+             <title>{title}</title>\n\
+             <id>tag:theclassicalstation.org,2017:playlist/{id}</id>\n\
+             <link href=\"{link}\"/>\n\
+             <published>{start}</published>\n\
+             <updated>{start}</updated>\n\
+             <summary>{summary}</summary>\n\
+             </entry>\n",
+            title = escape(&title),
+            id = entry.start_time.to_utc().format("%Y-%m-%dT%H:%M:%SZ"),
+            link = escape(&link),
+            start = start,
+            summary = escape(&summary),
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// Escapes `text` for use in XML content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{tests::HTML, Eastern},
+        chrono::{NaiveDate, TimeZone},
+    };
+
+    #[test]
+    fn test_playlist_atom() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let feed =
+            playlist_atom(&playlist, Eastern.ymd(2020, 9, 1).and_hms(7, 0, 0));
+        assert!(feed.starts_with("<?xml"));
+        assert!(feed.ends_with("</feed>\n"));
+        assert!(feed.contains("<updated>2020-09-01T10:00:00+00:00</updated>"));
+        assert!(feed.contains("?date=2020-09-01"));
+        let handel = feed.find("<title>George Frideric Handel").unwrap();
+        let liszt = feed
+            .find("<title>Franz Liszt: Tasso: Lament &amp;")
+            .unwrap();
+        assert!(handel < liszt);
+    }
+
+    #[test]
+    fn test_playlist_atom_not_started() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let feed =
+            playlist_atom(&playlist, Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0));
+        assert_eq!(1, feed.matches("<entry>").count());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot;", escape("a <b> & \"c\""));
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Deciding how long a downloaded page stays fresh from its caching headers.
+
+use chrono::{DateTime, Duration, Utc};
+
+/// The longest a response is considered fresh, in seconds, however large its
+/// `max-age`. Capping it at a year keeps the arithmetic from overflowing.
+const MAX_FRESHNESS_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// The caching headers of an HTTP response.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheHeaders {
+    cache_control: Option<String>,
+    expires: Option<String>,
+    age: Option<String>,
+}
+
+impl CacheHeaders {
+    /// Records `header` if it is one of the caching headers. Call this with
+    /// each raw header line of the response.
+    pub fn add(&mut self, header: &[u8]) {
+        let line = match std::str::from_utf8(header) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if let Some((name, value)) = line.split_once(':') {
+            self.insert(name, value);
+        }
+    }
+
+    /// Records the header `name` with `value` if it is one of the caching
+    /// headers.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let (name, value) = (name.trim(), value.trim().to_string());
+        if name.eq_ignore_ascii_case("cache-control") {
+            self.cache_control = Some(value);
+        } else if name.eq_ignore_ascii_case("expires") {
+            self.expires = Some(value);
+        } else if name.eq_ignore_ascii_case("age") {
+            self.age = Some(value);
+        }
+    }
+
+    /// Returns when a response received at `fetched_at` stops being fresh, or
+    /// `None` if the headers don't say.
+    ///
+    /// This follows RFC 9111: `no-cache` and `no-store` make the response stale
+    /// immediately, `max-age` (less the `Age`) takes precedence over `Expires`,
+    /// and an `Expires` that can't be parsed means already expired. A `max-age`
+    /// is capped at one year.
+    pub fn expires_at(
+        &self,
+        fetched_at: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let mut max_age = None;
+        if let Some(cache_control) = &self.cache_control {
+            for directive in cache_control.split(',') {
+                let directive = directive.trim().to_ascii_lowercase();
+                if directive == "no-cache" || directive == "no-store" {
+                    return Some(fetched_at);
+                }
+                if let Some(seconds) = directive.strip_prefix("max-age=") {
+                    max_age = seconds.trim_matches('"').parse::<i64>().ok();
+                }
+            }
+        }
+        if let Some(max_age) = max_age {
+            let age = self.age.as_ref().and_then(|age| age.parse().ok());
+            let remaining = max_age.saturating_sub(age.unwrap_or(0));
+            let remaining = remaining.clamp(0, MAX_FRESHNESS_SECS);
+            return Duration::try_seconds(remaining).and_then(|remaining| {
+                fetched_at.checked_add_signed(remaining)
+            });
+        }
+        let expires = self.expires.as_ref()?;
+        Some(
+            DateTime::parse_from_rfc2822(expires)
+                .map(|expires| expires.with_timezone(&Utc))
+                .unwrap_or(fetched_at),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    fn headers(lines: &[&str]) -> CacheHeaders {
+        let mut headers = CacheHeaders::default();
+        for line in lines {
+            headers.add(format!("{}\r\n", line).as_bytes());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let now = Utc.ymd(2020, 9, 1).and_hms(12, 0, 0);
+        let at = |lines: &[&str]| headers(lines).expires_at(now);
+
+        assert_eq!(None, at(&[]));
+        assert_eq!(
+            None,
+            at(&["Content-Type: text/html", "Cache-Control: public"])
+        );
+        assert_eq!(Some(now), at(&["Cache-Control: no-cache, max-age=60"]));
+        assert_eq!(Some(now), at(&["cache-control: private, No-Store"]));
+        assert_eq!(
+            Some(now + Duration::seconds(60)),
+            at(&["Cache-Control: public, max-age=60"])
+        );
+        assert_eq!(
+            Some(now + Duration::seconds(45)),
+            at(&["Cache-Control: max-age=60", "Age: 15"])
+        );
+        assert_eq!(Some(now), at(&["Cache-Control: max-age=60", "Age: 600"]));
+        assert_eq!(
+            Some(now + Duration::seconds(60)),
+            at(&[
+                "Expires: Tue, 01 Sep 2020 13:00:00 GMT",
+                "Cache-Control: max-age=60",
+            ])
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 1).and_hms(13, 0, 0)),
+            at(&["Expires: Tue, 01 Sep 2020 13:00:00 GMT"])
+        );
+        assert_eq!(Some(now), at(&["Expires: 0"]));
+    }
+
+    #[test]
+    fn test_expires_at_huge_max_age() {
+        let now = Utc.ymd(2020, 9, 1).and_hms(12, 0, 0);
+        let year = Duration::days(365);
+        let at = |lines: &[&str]| headers(lines).expires_at(now);
+
+        assert_eq!(
+            Some(now + year),
+            at(&["Cache-Control: max-age=9223372036854775807"])
+        );
+        assert_eq!(
+            Some(now + year),
+            at(&["Cache-Control: max-age=60", "Age: -9223372036854775808"])
+        );
+        assert_eq!(
+            None,
+            headers(&["Cache-Control: max-age=60"])
+                .expires_at(DateTime::<Utc>::MAX_UTC)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Looking up playlists through an HTTP client provided by the caller.
+
+use {
+    crate::trace::debug_span,
+    crate::{
+        decode_html, freshness::CacheHeaders, playlist_url, Error, Playlist,
+        Request, Response, Result,
+    },
+    chrono::{NaiveDate, TimeZone, Utc},
+};
+
+/// An HTTP client that can download playlist pages.
+///
+/// Applications that already manage their own HTTP clients, with their own
+/// metrics, retries, and proxies, can implement this and pass it to
+/// [`lookup_with_client`] to use `wowcpe`'s parsing without `curl`.
+///
+/// [`lookup_with_client`]: fn.lookup_with_client.html
+pub trait HttpClient {
+    /// Sends a GET request for `url`, following redirects, and returns the
+    /// final response. Non-2xx responses should be returned like any other,
+    /// and only failures to get a response at all should be errors. Use
+    /// `Error::Transport` to wrap the client's own error type.
+    fn get(&self, url: &str) -> Result<HttpResponse>;
+}
+
+impl<T: HttpClient + ?Sized> HttpClient for &T {
+    fn get(&self, url: &str) -> Result<HttpResponse> {
+        (**self).get(url)
+    }
+}
+
+/// A response returned by an `HttpClient`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HttpResponse {
+    /// The status code, e.g., 200.
+    pub status: u32,
+    /// The headers as name-value pairs, in the order they were received.
+    pub headers: Vec<(String, String)>,
+    /// The body, before decoding its charset.
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Returns the value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Looks up what is playing on WCPE, downloading the playlist page with
+/// `client`. This is like `wowcpe::lookup`, but it works without `curl`.
+///
+/// Returns `Error::HttpStatus` if the response status is not 2xx. Unlike
+/// `wowcpe::lookup`, it does not check whether the station has data for
+/// `request.time` before downloading.
+pub fn lookup_with_client<Tz: TimeZone>(
+    client: &impl HttpClient,
+    request: &Request<Tz>,
+) -> Result<Response<Tz>> {
+    lookup_playlist_with_client(client, request)?.lookup(request)
+}
+
+/// Looks up the whole playlist for the station's day containing
+/// `request.time`, downloading it with `client`. This is like
+/// `wowcpe::lookup_playlist`, but it works without `curl`.
+pub fn lookup_playlist_with_client<Tz: TimeZone>(
+    client: &impl HttpClient,
+    request: &Request<Tz>,
+) -> Result<Playlist> {
+    download_playlist(client, request.station_time().date(), None)
+}
+
+/// Downloads the playlist for `date` with `client`. If there is a `stale` copy
+/// of it, only the entries added since then are parsed.
+#[doc(hidden)]
+pub fn download_playlist(
+    client: &impl HttpClient,
+    date: NaiveDate,
+    stale: Option<Playlist>,
+) -> Result<Playlist> {
+    let url = playlist_url(crate::eastern_noon(date)?);
+    let response = {
+        let _span = debug_span!("download", url = %url).entered();
+        client.get(&url)?
+    };
+    if !(200..300).contains(&response.status) {
+        let line = format!("status {}", response.status);
+        return Err(Error::HttpStatus(response.status, line));
+    }
+    let fetched_at = Utc::now();
+    let mut headers = CacheHeaders::default();
+    for (name, value) in &response.headers {
+        headers.insert(name, value);
+    }
+    let html = decode_html(&response.body, response.header("content-type"));
+    let playlist = match stale {
+        Some(mut playlist) => {
+            playlist.refresh(&html)?;
+            playlist
+        }
+        None => Playlist::parse(&html, date)?,
+    };
+    Ok(playlist
+        .with_source(url, fetched_at)
+        .with_expiry(headers.expires_at(fetched_at)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::tests::HTML, crate::Eastern,
+        assert_matches::assert_matches, std::cell::RefCell,
+    };
+
+    /// A client that returns a canned response and records the URLs.
+    #[derive(Default)]
+    struct FakeClient {
+        response: HttpResponse,
+        urls: RefCell<Vec<String>>,
+    }
+
+    impl HttpClient for FakeClient {
+        fn get(&self, url: &str) -> Result<HttpResponse> {
+            self.urls.borrow_mut().push(url.to_string());
+            Ok(self.response.clone())
+        }
+    }
+
+    fn request() -> Request<Eastern> {
+        Request {
+            time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+        }
+    }
+
+    #[test]
+    fn test_lookup_with_client() {
+        let client = FakeClient {
+            response: HttpResponse {
+                status: 200,
+                headers: vec![(
+                    "Cache-Control".to_string(),
+                    "max-age=60".to_string(),
+                )],
+                body: HTML.as_bytes().to_vec(),
+            },
+            ..FakeClient::default()
+        };
+        let response = lookup_with_client(&client, &request()).unwrap();
+        assert_eq!(Some("Franz Liszt"), response.composer.as_deref());
+        let url = playlist_url(request().time);
+        assert_eq!(vec![url.clone()], *client.urls.borrow());
+        assert_eq!(Some(url), response.source_url);
+
+        let playlist =
+            lookup_playlist_with_client(&client, &request()).unwrap();
+        let fetched_at = playlist.fetched_at().unwrap();
+        assert_eq!(
+            Some(fetched_at + chrono::Duration::seconds(60)),
+            playlist.expires_at()
+        );
+    }
+
+    #[test]
+    fn test_lookup_with_client_status() {
+        let client = FakeClient {
+            response: HttpResponse {
+                status: 404,
+                ..HttpResponse::default()
+            },
+            ..FakeClient::default()
+        };
+        assert_matches!(
+            lookup_with_client(&client, &request()),
+            Err(Error::HttpStatus(404, _))
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let response = HttpResponse {
+            headers: vec![
+                ("Content-Type".to_string(), "text/html".to_string()),
+                ("content-type".to_string(), "text/plain".to_string()),
+            ],
+            ..HttpResponse::default()
+        };
+        assert_eq!(Some("text/html"), response.header("CONTENT-TYPE"));
+        assert_eq!(None, response.header("Age"));
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Exporting the weekly program schedule and playlists as iCalendar files.
+
+use {
+    crate::{regular_program, specialty, Entry, Program},
+    chrono::{
+        Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday,
+    },
+};
+
+/// Programs start and end on the hour or half hour.
+const SLOT_MINUTES: u32 = 30;
+
+const SLOTS_PER_DAY: u32 = 24 * 60 / SLOT_MINUTES;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// The rules for US Eastern time since 2007.
+const VTIMEZONE: &str = "BEGIN:VTIMEZONE\r\n\
+TZID:America/New_York\r\n\
+BEGIN:DAYLIGHT\r\n\
+TZOFFSETFROM:-0500\r\n\
+TZOFFSETTO:-0400\r\n\
+TZNAME:EDT\r\n\
+DTSTART:20070311T020000\r\n\
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU\r\n\
+END:DAYLIGHT\r\n\
+BEGIN:STANDARD\r\n\
+TZOFFSETFROM:-0400\r\n\
+TZOFFSETTO:-0500\r\n\
+TZNAME:EST\r\n\
+DTSTART:20071104T020000\r\n\
+RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU\r\n\
+END:STANDARD\r\n\
+END:VTIMEZONE\r\n";
+
+/// A program airing in the same slot on some days of the week, and on some
+/// days of the month.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Block {
+    program: Program,
+    /// The first slot of the day, starting at midnight.
+    start: u32,
+    /// The slot after the last one, so 48 is midnight at the end of the day.
+    end: u32,
+}
+
+/// Returns an iCalendar (RFC 5545) file with a recurring event for each
+/// program in the weekly schedule, starting on or after `since`.
+///
+/// Programs in the regular lineup recur weekly, e.g., Concert Hall on weekday
+/// evenings. Specialty programs that only air on a particular week of the month
+/// recur monthly, and the regular program they preempt recurs on the other
+/// weeks. The events use the station's time zone, so calendar apps show them at
+/// the right local time all year round.
+///
+/// Holidays and the Metropolitan Opera season change from year to year, so
+/// they are left out. The specialty schedule comes from
+/// `set_specialty_schedule` if it was called.
+pub fn schedule_ics(since: NaiveDate) -> String {
+    let mut ics = begin("WCPE Program Schedule");
+    ics.push_str(VTIMEZONE);
+    let stamp = since.format("%Y%m%dT000000Z");
+    for (block, days, weekdays) in recurrences() {
+        let first = first_date(since, &weekdays, &days);
+        let start = at_slot(first, block.start);
+        let end = at_slot(first, block.end);
+        let byday = weekdays.iter().map(|w| byday(*w)).collect::<Vec<_>>();
+        let rrule = if days.len() == 31 {
+            format!("FREQ=WEEKLY;BYDAY={}", byday.join(","))
+        } else {
+            let days = days.iter().map(u32::to_string).collect::<Vec<_>>();
+            format!(
+                "FREQ=MONTHLY;BYDAY={};BYMONTHDAY={}",
+                byday.join(","),
+                days.join(",")
+            )
+        };
+        let uid = format!(
+            "{}-{}-{}@wowcpe",
+            slug(block.program.name()),
+            start.format("%Y%m%dT%H%M"),
+            byday.join("").to_ascii_lowercase()
+        );
+        ics.push_str("BEGIN:VEVENT\r\n");
+        line(&mut ics, &format!("UID:{}", uid));
+        line(&mut ics, &format!("DTSTAMP:{}", stamp));
+        line(
+            &mut ics,
+            &format!("DTSTART;TZID=America/New_York:{}", ical(start)),
+        );
+        line(
+            &mut ics,
+            &format!("DTEND;TZID=America/New_York:{}", ical(end)),
+        );
+        line(&mut ics, &format!("RRULE:{}", rrule));
+        line(
+            &mut ics,
+            &format!("SUMMARY:{}", escape(block.program.name())),
+        );
+        if let Some(info) = block.program.info() {
+            line(
+                &mut ics,
+                &format!("DESCRIPTION:{}", escape(&info.description)),
+            );
+            if let Some(url) = &info.url {
+                line(&mut ics, &format!("URL:{}", url));
+            }
+        }
+        ics.push_str("END:VEVENT\r\n");
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+/// Returns an iCalendar (RFC 5545) file with an event for each of `entries`,
+/// e.g., to see what played alongside other events in a calendar app.
+///
+/// Each event is titled "Composer: Title", and describes the program,
+/// performers, and record label. Times are in UTC.
+pub fn playlist_ics(entries: &[Entry]) -> String {
+    let mut ics = begin("WCPE Playlist");
+    for entry in entries {
+        let utc = |time: &crate::StationTime| {
+            time.naive_utc().format("%Y%m%dT%H%M%SZ").to_string()
+        };
+        let start = utc(&entry.start_time);
+        let summary = match (&entry.composer, &entry.title) {
+            (Some(composer), Some(title)) => format!("{}: {}", composer, title),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => entry.program.name().to_string(),
+        };
+        let mut description = format!("Program: {}", entry.program.name());
+        if let Some(performers) = &entry.performers {
+            description.push_str(&format!("\nPerformers: {}", performers));
+        }
+        if let Some(label) = &entry.record_label {
+            description.push_str(&format!("\nRecord label: {}", label));
+        }
+        ics.push_str("BEGIN:VEVENT\r\n");
+        line(&mut ics, &format!("UID:{}@wowcpe", start));
+        line(&mut ics, &format!("DTSTAMP:{}", start));
+        line(&mut ics, &format!("DTSTART:{}", start));
+        line(&mut ics, &format!("DTEND:{}", utc(&entry.end_time)));
+        line(&mut ics, &format!("SUMMARY:{}", escape(&summary)));
+        line(&mut ics, &format!("DESCRIPTION:{}", escape(&description)));
+        ics.push_str("END:VEVENT\r\n");
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+/// Returns the start of a calendar called `name`, up to its first component.
+fn begin(name: &str) -> String {
+    let mut ics = String::from(
+        "BEGIN:VCALENDAR\r\n\
+         VERSION:2.0\r\n\
+         PRODID:-//wowcpe//WCPE schedule//EN\r\n\
+         CALSCALE:GREGORIAN\r\n",
+    );
+    line(&mut ics, &format!("X-WR-CALNAME:{}", escape(name)));
+    ics.push_str("X-WR-TIMEZONE:America/New_York\r\n");
+    ics
+}
+
+/// Groups the blocks of the weekly schedule into recurrences, each made of a
+/// block, the days of the month it airs on, and the days of the week it airs
+/// on. They are in order of their first airing in the week.
+fn recurrences() -> Vec<(Block, Vec<u32>, Vec<Weekday>)> {
+    let mut days_by_block: Vec<(Weekday, Block, Vec<u32>)> = Vec::new();
+    for weekday in WEEKDAYS {
+        for day in 1..=31 {
+            for block in blocks(weekday, day) {
+                match days_by_block
+                    .iter_mut()
+                    .find(|(w, b, _)| *w == weekday && *b == block)
+                {
+                    Some((_, _, days)) => days.push(day),
+                    None => days_by_block.push((weekday, block, vec![day])),
+                }
+            }
+        }
+    }
+    let mut recurrences: Vec<(Block, Vec<u32>, Vec<Weekday>)> = Vec::new();
+    for (weekday, block, days) in days_by_block {
+        match recurrences
+            .iter_mut()
+            .find(|(b, d, _)| *b == block && *d == days)
+        {
+            Some((_, _, weekdays)) => weekdays.push(weekday),
+            None => recurrences.push((block, days, vec![weekday])),
+        }
+    }
+    recurrences
+}
+
+/// Returns the blocks airing on `weekday` when it is day `day` of the month.
+fn blocks(weekday: Weekday, day: u32) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for slot in 0..SLOTS_PER_DAY {
+        let minute = slot * SLOT_MINUTES;
+        let program = specialty::rule_at(weekday, day, minute)
+            .unwrap_or_else(|| regular_program(weekday, minute / 60));
+        match blocks.last_mut() {
+            Some(block) if block.program == program => block.end = slot + 1,
+            _ => blocks.push(Block {
+                program,
+                start: slot,
+                end: slot + 1,
+            }),
+        }
+    }
+    blocks
+}
+
+/// Returns the first date on or after `since` that falls on one of `weekdays`
+/// and one of `days` of the month.
+fn first_date(
+    since: NaiveDate,
+    weekdays: &[Weekday],
+    days: &[u32],
+) -> NaiveDate {
+    since
+        .iter_days()
+        .find(|date| {
+            weekdays.contains(&date.weekday()) && days.contains(&date.day())
+        })
+        .expect("every weekday falls on every day of the month eventually")
+}
+
+fn at_slot(date: NaiveDate, slot: u32) -> NaiveDateTime {
+    date.and_time(NaiveTime::MIN)
+        + Duration::minutes(i64::from(slot * SLOT_MINUTES))
+}
+
+fn ical(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn byday(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_ascii_lowercase()
+}
+
+/// Escapes a TEXT value (RFC 5545 section 3.3.11).
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends a content line, folding it so that no line exceeds 75 octets (RFC
+/// 5545 section 3.1).
+fn line(ics: &mut String, content: &str) {
+    let mut width = 0;
+    for c in content.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(ics: &str) -> Vec<String> {
+        ics.split("BEGIN:VEVENT\r\n")
+            .skip(1)
+            .map(|event| event.replace("\r\n ", ""))
+            .collect()
+    }
+
+    fn find<'a>(events: &'a [String], summary: &str, rrule: &str) -> &'a str {
+        events
+            .iter()
+            .find(|e| {
+                e.contains(&format!("SUMMARY:{}\r\n", escape(summary)))
+                    && e.contains(&format!("RRULE:{}\r\n", rrule))
+            })
+            .unwrap_or_else(|| {
+                panic!("no event for {} with {}", summary, rrule)
+            })
+    }
+
+    #[test]
+    fn test_schedule_ics() {
+        let ics = schedule_ics(NaiveDate::from_ymd(2020, 9, 1));
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        let events = events(&ics);
+
+        let concert_hall =
+            find(&events, "Concert Hall", "FREQ=WEEKLY;BYDAY=TU,WE,FR");
+        assert!(concert_hall
+            .contains("DTSTART;TZID=America/New_York:20200901T190000\r\n"));
+        assert!(concert_hall
+            .contains("DTEND;TZID=America/New_York:20200901T220000\r\n"));
+
+        let opera = find(
+            &events,
+            "Thursday Night Opera House",
+            "FREQ=WEEKLY;BYDAY=TH",
+        );
+        assert!(
+            opera.contains("DTSTART;TZID=America/New_York:20200903T190000\r\n")
+        );
+
+        let fare = find(
+            &events,
+            "Renaissance Fare",
+            "FREQ=MONTHLY;BYDAY=MO;BYMONTHDAY=8,9,10,11,12,13,14",
+        );
+        assert!(
+            fare.contains("DTSTART;TZID=America/New_York:20200914T190000\r\n")
+        );
+
+        let sleepers = find(
+            &events,
+            "Sleepers, Awake!",
+            "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR,SA,SU",
+        );
+        assert!(sleepers.contains("SUMMARY:Sleepers\\, Awake!\r\n"));
+        assert!(sleepers
+            .contains("DTEND;TZID=America/New_York:20200901T060000\r\n"));
+    }
+
+    #[test]
+    fn test_playlist_ics() {
+        let playlist = crate::Playlist::parse(
+            crate::tests::HTML,
+            NaiveDate::from_ymd(2020, 9, 1),
+        )
+        .unwrap();
+        let ics = playlist_ics(playlist.entries());
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        let events = events(&ics);
+        assert_eq!(2, events.len());
+        assert!(events[0].contains("DTSTART:20200901T040100Z\r\n"));
+        assert!(events[0].contains("DTEND:20200901T100000Z\r\n"));
+        assert!(events[0].contains("SUMMARY:Franz Liszt: Tasso"));
+        assert!(events[0]
+            .contains("DESCRIPTION:Program: Sleepers\\, Awake!\\nPerformers:"));
+    }
+
+    #[test]
+    fn test_line() {
+        let mut ics = String::new();
+        line(&mut ics, &"x".repeat(100));
+        assert_eq!(
+            format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(25)),
+            ics
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a\\, b\\; c\\\\d\\ne", escape("a, b; c\\d\ne"));
+    }
+}
@@ -0,0 +1,1160 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! The core of _What's On WCPE?_: parsing playlist pages and computing the
+//! schedule of the [classical radio station WCPE](https://theclassicalstation.org),
+//! with no I/O.
+//!
+//! Most users want the `wowcpe` crate, which downloads pages and re-exports
+//! everything here. Depend on this crate directly to avoid `curl` and the
+//! other networking dependencies, e.g., when targeting WASM or an embedded
+//! device. Fetch the page at [`playlist_url`] yourself and pass the HTML to
+//! [`lookup_in_html`], or to [`parse_playlist`] to get every [`Entry`] for the
+//! day. If you have raw bytes, [`decode_html`] handles the charset. The
+//! parser tries each known page layout in turn; [`parse_playlist_with_strategy`]
+//! reports which [`Strategy`] matched. To answer several queries about the
+//! same day, parse the page into a [`Playlist`] once and look up each time in
+//! it. The results are [`Response`]s, and titles can be broken down further
+//! into a [`Work`] with catalogue numbers, key, and nickname.
+//!
+//! To bring your own HTTP stack instead of fetching pages yourself, implement
+//! [`HttpClient`] and call [`lookup_with_client`]. To write code that works
+//! with any way of finding out what is playing, use [`NowPlayingSource`].
+//!
+//! The station's schedule is in US Eastern time, which is the [`Eastern`]
+//! time zone. It has the daylight saving rules built in, so this crate does
+//! not need `chrono-tz`.
+//!
+//! Diagnostics are emitted as [`tracing`] events rather than printed, with a
+//! span around the parse phase. Entries skipped because of daylight saving
+//! time or unparsable times are also returned as [`Warning`]s in
+//! [`Response::warnings`] and [`Playlist::warnings`].
+//!
+//! These default features can be turned off to leave out their dependencies:
+//!
+//! - `html`: the playlist parser, and everything that takes HTML.
+//! - `tracing`: the diagnostic events.
+//! - `json`: [`MetOperaSeason::from_json`].
+//! - `toml`: [`SpecialtySchedule::from_toml`]. The built-in schedule does not
+//!   need it.
+//!
+//! The `curl` feature only adds the `curl`-specific variants of [`Error`], so
+//! that the `wowcpe` crate can share the error type.
+//!
+//! [`Eastern`]: struct.Eastern.html
+//! [`MetOperaSeason::from_json`]: struct.MetOperaSeason.html#method.from_json
+//! [`SpecialtySchedule::from_toml`]: struct.SpecialtySchedule.html#method.from_toml
+//! [`Response`]: struct.Response.html
+//! [`Work`]: struct.Work.html
+//! [`Warning`]: enum.Warning.html
+//! [`Error`]: enum.Error.html
+//! [`Response::warnings`]: struct.Response.html#structfield.warnings
+//! [`Playlist::warnings`]: struct.Playlist.html#method.warnings
+//! [`playlist_url`]: fn.playlist_url.html
+//! [`lookup_in_html`]: fn.lookup_in_html.html
+//! [`parse_playlist`]: fn.parse_playlist.html
+//! [`decode_html`]: fn.decode_html.html
+//! [`Entry`]: struct.Entry.html
+//! [`parse_playlist_with_strategy`]: fn.parse_playlist_with_strategy.html
+//! [`Strategy`]: enum.Strategy.html
+//! [`Playlist`]: struct.Playlist.html
+//! [`HttpClient`]: trait.HttpClient.html
+//! [`lookup_with_client`]: fn.lookup_with_client.html
+//! [`NowPlayingSource`]: trait.NowPlayingSource.html
+//! [`tracing`]: https://docs.rs/tracing
+
+#[cfg(feature = "html")]
+mod charset;
+mod digest;
+mod eastern;
+mod feed;
+mod freshness;
+#[cfg(feature = "html")]
+mod http;
+mod ics;
+mod met_opera;
+mod natural;
+#[cfg(feature = "html")]
+mod parse;
+mod playlist;
+mod program;
+mod relative;
+mod schedule;
+mod source;
+mod specialty;
+mod station_time;
+mod stats;
+mod stream;
+mod template;
+#[cfg(feature = "html")]
+mod trace;
+mod warning;
+mod work;
+
+#[cfg(feature = "html")]
+pub use crate::{
+    charset::decode_html,
+    http::{
+        lookup_playlist_with_client, lookup_with_client, HttpClient,
+        HttpResponse,
+    },
+    parse::{parse_playlist, parse_playlist_with_strategy, Strategy},
+};
+pub use crate::{
+    digest::{digest_html, digest_text, digest_title},
+    eastern::{Eastern, EasternOffset},
+    feed::playlist_atom,
+    ics::{playlist_ics, schedule_ics},
+    met_opera::{set_met_opera_season, Broadcast, MetOperaSeason},
+    natural::parse_natural_time,
+    playlist::Playlist,
+    program::{Program, ProgramInfo},
+    relative::relative_time,
+    schedule::next_occurrence,
+    source::{Fallback, NowPlayingSource},
+    specialty::{set_specialty_schedule, SpecialtySchedule},
+    station_time::StationTime,
+    stats::{last_played, repeats, Count, Play, Repeat, Stats},
+    stream::{Stream, STREAMS},
+    template::{ParseTemplateError, Template},
+    warning::Warning,
+    work::{Catalogue, CatalogueNumber, Key, Mode, Work},
+};
+
+// Used by the `wowcpe` crate, but not part of the public API.
+#[doc(hidden)]
+pub use crate::freshness::CacheHeaders;
+#[doc(hidden)]
+#[cfg(feature = "html")]
+pub use crate::{http::download_playlist, parse::has_playlist};
+
+use {
+    chrono::{
+        DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveTime,
+        TimeZone, Timelike, Utc, Weekday,
+    },
+    serde::{Deserialize, Serialize},
+    std::{error, fmt, result, str::FromStr},
+};
+
+/// Request to look up what is playing on WCPE.
+///
+/// The time can be in any time zone. It defaults to `Local`, but servers might
+/// prefer `Utc`, and `Eastern` matches the station itself. The
+/// `Response` will use the same time zone as the request.
+pub struct Request<Tz: TimeZone = Local> {
+    /// The moment in time to look up.
+    pub time: DateTime<Tz>,
+}
+
+impl<Tz: TimeZone> Request<Tz> {
+    /// Returns the requested time in the station's time zone. Its `date` is
+    /// the day of the playlist that answers the request.
+    pub fn station_time(&self) -> StationTime {
+        self.time.clone().into()
+    }
+}
+
+impl Request<Local> {
+    /// Creates a request for `time` on `date` in the local time zone, e.g.,
+    /// 3pm last Tuesday. Returns `None` if that time does not exist because of
+    /// a daylight saving transition. If it occurs twice, uses the first one.
+    pub fn on_date(date: NaiveDate, time: NaiveTime) -> Option<Self> {
+        Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|time| Request { time })
+    }
+
+    /// Creates a request for the station's whole day on `date`, which is
+    /// interpreted in the station's time zone (US Eastern). Pass it to
+    /// [`lookup_playlist`] to get every entry for the day. The request's time
+    /// is noon Eastern, so `lookup` returns whatever was playing then.
+    ///
+    /// [`lookup_playlist`]: fn.lookup_playlist.html
+    pub fn whole_day(date: NaiveDate) -> Self {
+        // Daylight saving transitions never skip or repeat noon.
+        let noon = eastern_noon(date).unwrap();
+        Request {
+            time: noon.with_timezone(&Local),
+        }
+    }
+}
+
+/// Information about a piece playing on WCPE.
+pub struct Response<Tz: TimeZone = Local> {
+    /// The current program, e.g., Sleepers, Awake!
+    pub program: Program,
+    /// Time the piece started playing.
+    pub start_time: DateTime<Tz>,
+    /// Time the piece stopped (or will stop) playing.
+    pub end_time: DateTime<Tz>,
+    /// Time the current program started, according to the station's schedule.
+    pub program_start: DateTime<Tz>,
+    /// Time the current program ends, according to the station's schedule.
+    pub program_end: DateTime<Tz>,
+    /// Composer of the piece, if listed.
+    pub composer: Option<String>,
+    /// Title of the piece, if listed.
+    pub title: Option<String>,
+    /// Perfomers in the recording of the piece, if listed.
+    pub performers: Option<String>,
+    /// Record label of the recording of the piece, if listed.
+    pub record_label: Option<String>,
+    /// URL of the playlist page the response came from, or `None` if it came
+    /// from HTML passed to `lookup_in_html`.
+    pub source_url: Option<String>,
+    /// When the playlist page was downloaded, or `None` if it came from HTML
+    /// passed to `lookup_in_html`. For a cached response, this is when the page
+    /// was originally downloaded, not when the cache was read.
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Warnings about entries skipped while parsing the day's playlist, which
+    /// may make the response less accurate.
+    pub warnings: Vec<Warning>,
+}
+
+/// An entry in a day's playlist, with times in the station's time zone.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// Position of the entry within the day's playlist, starting at 0.
+    pub index: usize,
+    /// Heading of the section of the playlist page the entry appears under,
+    /// e.g., "12am". This is `None` for page layouts without such sections.
+    pub hour: Option<String>,
+    /// The program at the start of the piece, e.g., Sleepers, Awake!
+    pub program: Program,
+    /// Time the piece started playing.
+    pub start_time: StationTime,
+    /// Time the piece stopped (or will stop) playing. For the last entry of
+    /// the day, this is the end of the day.
+    pub end_time: StationTime,
+    /// Composer of the piece, if listed.
+    pub composer: Option<String>,
+    /// Title of the piece, if listed.
+    pub title: Option<String>,
+    /// Perfomers in the recording of the piece, if listed.
+    pub performers: Option<String>,
+    /// Record label of the recording of the piece, if listed.
+    pub record_label: Option<String>,
+    /// The fields as they appear in the HTML, before decoding entities and
+    /// normalizing. This is useful for archiving and debugging the parser.
+    pub raw: RawFields,
+}
+
+/// The fields of an `Entry` as they appear in the HTML of the playlist page.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RawFields {
+    /// The start time, e.g., "12:01am".
+    pub time: String,
+    /// Title of the piece, if listed.
+    pub title: Option<String>,
+    /// Composer of the piece, if listed.
+    pub composer: Option<String>,
+    /// Perfomers in the recording of the piece, if listed.
+    pub performers: Option<String>,
+    /// Record label of the recording of the piece, if listed.
+    pub record_label: Option<String>,
+}
+
+impl<Tz: TimeZone> Response<Tz> {
+    /// Parses the title into a structured `Work`, if there is a title.
+    pub fn work(&self) -> Option<Work> {
+        self.title.as_deref().map(Work::parse)
+    }
+
+    /// Returns an object implementing `Display` that shows the response in
+    /// labeled lines, with `<missing>` in place of fields that are not listed.
+    pub fn display(&self) -> ResponseDisplay<'_, Tz> {
+        ResponseDisplay(self)
+    }
+
+    /// Returns true if the piece was playing at `time`. The start time is
+    /// inclusive and the end time is exclusive, so at the moment one piece
+    /// ends and the next begins, only the next one contains the time.
+    pub fn contains<Tz2: TimeZone>(&self, time: &DateTime<Tz2>) -> bool {
+        let time = time.naive_utc();
+        self.start_time.naive_utc() <= time && time < self.end_time.naive_utc()
+    }
+
+    /// Returns true if the piece was playing at any time from `start`
+    /// (inclusive) to `end` (exclusive). Intervals that only touch at an
+    /// endpoint do not overlap, and neither do empty ones.
+    pub fn overlaps<Tz2: TimeZone>(
+        &self,
+        start: &DateTime<Tz2>,
+        end: &DateTime<Tz2>,
+    ) -> bool {
+        let (start, end) = (start.naive_utc(), end.naive_utc());
+        start < end
+            && start < self.end_time.naive_utc()
+            && self.start_time.naive_utc() < end
+    }
+
+    /// Returns true if the piece is playing right now.
+    pub fn is_current(&self) -> bool {
+        self.contains(&Utc::now())
+    }
+
+    /// Converts the response to a different time zone.
+    pub fn with_timezone<Tz2: TimeZone>(&self, tz: &Tz2) -> Response<Tz2> {
+        Response {
+            program: self.program.clone(),
+            start_time: self.start_time.with_timezone(tz),
+            end_time: self.end_time.with_timezone(tz),
+            program_start: self.program_start.with_timezone(tz),
+            program_end: self.program_end.with_timezone(tz),
+            composer: self.composer.clone(),
+            title: self.title.clone(),
+            performers: self.performers.clone(),
+            record_label: self.record_label.clone(),
+            source_url: self.source_url.clone(),
+            fetched_at: self.fetched_at,
+            warnings: self.warnings.clone(),
+        }
+    }
+}
+
+impl FromStr for Request<FixedOffset> {
+    type Err = ParseRequestError;
+
+    /// Parses an RFC 3339 timestamp like "2023-05-01T14:00:00-04:00", or a bare
+    /// date like "2023-05-01", which means the same as `Request::whole_day`.
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Request { time });
+        }
+        let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| ParseRequestError(()))?;
+        let time = Request::whole_day(date).time.with_timezone(&Eastern);
+        Ok(Request {
+            time: time.fixed_offset(),
+        })
+    }
+}
+
+/// An error returned when parsing a `Request` from a string fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseRequestError(());
+
+impl fmt::Display for ParseRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected an RFC 3339 timestamp or a YYYY-MM-DD date")
+    }
+}
+
+impl error::Error for ParseRequestError {}
+
+/// Helper for showing a `Response`, returned by `Response::display`.
+pub struct ResponseDisplay<'a, Tz: TimeZone>(&'a Response<Tz>);
+
+impl<Tz: TimeZone> fmt::Display for ResponseDisplay<'_, Tz>
+where
+    Tz::Offset: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = self.0;
+        let field = |value: &Option<String>| {
+            value.as_deref().unwrap_or("<missing>").to_string()
+        };
+        let format = "%l:%M %p";
+        let start = r.start_time.format(format).to_string();
+        let end = r.end_time.format(format).to_string();
+        writeln!(f, "Program       {}", r.program)?;
+        writeln!(f, "Time          {} - {}", start.trim(), end.trim())?;
+        writeln!(f, "Composer      {}", field(&r.composer))?;
+        writeln!(f, "Title         {}", field(&r.title))?;
+        writeln!(f, "Performers    {}", field(&r.performers))?;
+        write!(f, "Record Label  {}", field(&r.record_label))
+    }
+}
+
+// These impls are written by hand because deriving them would require bounds
+// on `Tz` itself (which `Local` does not satisfy) rather than on `DateTime<Tz>`.
+
+impl<Tz: TimeZone> Clone for Request<Tz> {
+    fn clone(&self) -> Self {
+        Request {
+            time: self.time.clone(),
+        }
+    }
+}
+
+impl<Tz: TimeZone> Copy for Request<Tz> where Tz::Offset: Copy {}
+
+impl<Tz: TimeZone> fmt::Debug for Request<Tz> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request").field("time", &self.time).finish()
+    }
+}
+
+impl<Tz: TimeZone> PartialEq for Request<Tz> {
+    fn eq(&self, other: &Self) -> bool {
+        self.time == other.time
+    }
+}
+
+impl<Tz: TimeZone> Eq for Request<Tz> {}
+
+impl<Tz: TimeZone> Clone for Response<Tz> {
+    fn clone(&self) -> Self {
+        self.with_timezone(&self.start_time.timezone())
+    }
+}
+
+impl<Tz: TimeZone> fmt::Debug for Response<Tz> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("program", &self.program)
+            .field("start_time", &self.start_time)
+            .field("end_time", &self.end_time)
+            .field("program_start", &self.program_start)
+            .field("program_end", &self.program_end)
+            .field("composer", &self.composer)
+            .field("title", &self.title)
+            .field("performers", &self.performers)
+            .field("record_label", &self.record_label)
+            .field("source_url", &self.source_url)
+            .field("fetched_at", &self.fetched_at)
+            .field("warnings", &self.warnings)
+            .finish()
+    }
+}
+
+impl<Tz: TimeZone> PartialEq for Response<Tz> {
+    fn eq(&self, other: &Self) -> bool {
+        self.program == other.program
+            && self.start_time == other.start_time
+            && self.end_time == other.end_time
+            && self.program_start == other.program_start
+            && self.program_end == other.program_end
+            && self.composer == other.composer
+            && self.title == other.title
+            && self.performers == other.performers
+            && self.record_label == other.record_label
+            && self.source_url == other.source_url
+            && self.fetched_at == other.fetched_at
+            && self.warnings == other.warnings
+    }
+}
+
+impl<Tz: TimeZone> Eq for Response<Tz> {}
+
+/// Serializes the program as its name and the times in RFC 3339 format, e.g.,
+/// for printing JSON.
+impl<Tz: TimeZone> Serialize for Response<Tz> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("Response", 12)?;
+        s.serialize_field("program", self.program.name())?;
+        s.serialize_field("start_time", &self.start_time)?;
+        s.serialize_field("end_time", &self.end_time)?;
+        s.serialize_field("program_start", &self.program_start)?;
+        s.serialize_field("program_end", &self.program_end)?;
+        s.serialize_field("composer", &self.composer)?;
+        s.serialize_field("title", &self.title)?;
+        s.serialize_field("performers", &self.performers)?;
+        s.serialize_field("record_label", &self.record_label)?;
+        s.serialize_field("source_url", &self.source_url)?;
+        s.serialize_field("fetched_at", &self.fetched_at)?;
+        s.serialize_field("warnings", &self.warnings)?;
+        s.end()
+    }
+}
+
+/// Serializes the program as its name and the times in RFC 3339 format, like
+/// `Response`. The raw fields are left out.
+impl Serialize for Entry {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("Entry", 9)?;
+        s.serialize_field("index", &self.index)?;
+        s.serialize_field("hour", &self.hour)?;
+        s.serialize_field("program", self.program.name())?;
+        s.serialize_field("start_time", &*self.start_time)?;
+        s.serialize_field("end_time", &*self.end_time)?;
+        s.serialize_field("composer", &self.composer)?;
+        s.serialize_field("title", &self.title)?;
+        s.serialize_field("performers", &self.performers)?;
+        s.serialize_field("record_label", &self.record_label)?;
+        s.end()
+    }
+}
+
+/// An error that occurs while processing a request.
+#[derive(Debug)]
+pub enum Error {
+    #[cfg(feature = "curl")]
+    Curl(curl::Error),
+    /// The lookup was cancelled with a `CancellationToken`.
+    #[cfg(feature = "curl")]
+    Cancelled,
+    /// The playlist for the date is not in the cache, and downloading is
+    /// turned off, e.g., with `wowcpe --offline`.
+    #[cfg(feature = "curl")]
+    NotCached(NaiveDate),
+    /// The server responded with a non-2xx status code. Contains the code and
+    /// the full status line, e.g., "HTTP/1.1 503 Service Unavailable".
+    HttpStatus(u32, String),
+    /// An `HttpClient` failed to get a response. Contains the client's error.
+    Transport(Box<dyn error::Error + Send + Sync>),
+    /// The downloaded page was larger than the limit set by
+    /// `Client::set_max_body_size`, which it contains.
+    BodyTooLarge(usize),
+    NoData,
+    NoEntry,
+    BadUtf8,
+    BadScrape,
+    BadTime,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "curl")]
+            Error::Curl(err) => err.fmt(f),
+            #[cfg(feature = "curl")]
+            Error::Cancelled => write!(f, "The lookup was cancelled"),
+            #[cfg(feature = "curl")]
+            Error::NotCached(date) => {
+                write!(f, "The playlist for {} is not in the cache", date)
+            }
+            Error::HttpStatus(_, line) => {
+                write!(f, "The server responded with {}", line)
+            }
+            Error::Transport(err) => err.fmt(f),
+            Error::BodyTooLarge(limit) => {
+                write!(f, "The page exceeded the limit of {} bytes", limit)
+            }
+            Error::NoData => write!(f, "There is no data for the given time"),
+            Error::NoEntry => write!(f, "Cannot find entry for the given time"),
+            Error::BadUtf8 => write!(f, "Failed to parse HTML as UTF-8"),
+            Error::BadScrape => write!(f, "Failed to scrape the HTML"),
+            Error::BadTime => write!(f, "Failed to parse a time in the HTML"),
+        }
+    }
+}
+
+#[cfg(feature = "curl")]
+impl From<curl::Error> for Error {
+    fn from(err: curl::Error) -> Self {
+        Error::Curl(err)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "curl")]
+            Error::Curl(err) => Some(err),
+            Error::Transport(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// A specialized `Result` type for the `wowcpe` crate.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Returns the URL of the playlist page covering `time`, which can be a
+/// `DateTime` in any time zone. The page is chosen by the station's date.
+pub fn playlist_url(time: impl Into<StationTime>) -> String {
+    // The slash before the query string avoids a 301 Moved Permanently
+    // response. The client follows redirects, but this saves a round trip.
+    format!(
+        "https://theclassicalstation.org/listen/playlist/?date={}",
+        time.into().date().format("%Y-%m-%d")
+    )
+}
+
+/// Looks up what is playing on WCPE at `request.time` in the HTML of a playlist
+/// page that was already downloaded from [`playlist_url`].
+///
+/// Unlike `lookup`, this does no I/O, so it works without the `curl` feature.
+/// Returns an error if extracting the desired information from the HTML fails.
+///
+/// [`playlist_url`]: fn.playlist_url.html
+#[cfg(feature = "html")]
+pub fn lookup_in_html<Tz: TimeZone>(
+    request: &Request<Tz>,
+    html: &str,
+) -> Result<Response<Tz>> {
+    let date = request.station_time().date();
+    Playlist::parse(html, date)?.lookup(request)
+}
+
+#[doc(hidden)]
+pub fn eastern_noon(date: NaiveDate) -> Result<DateTime<Eastern>> {
+    Eastern
+        .from_local_datetime(&date.and_hms(12, 0, 0))
+        .single()
+        .ok_or(Error::BadTime)
+}
+
+/// Returns the start and end of the program block containing `time`, e.g., 7pm
+/// and 10pm for Concert Hall.
+pub(crate) fn program_bounds<Tz: TimeZone>(
+    time: DateTime<Tz>,
+) -> (DateTime<Tz>, DateTime<Tz>) {
+    // Programs start and end on the hour or half hour, so step outward in
+    // half-hour increments until the program changes. No block lasts longer
+    // than a day.
+    let step = Duration::minutes(30);
+    let max_steps = 48;
+    let tz = time.timezone();
+    let program = get_program(time.clone());
+    let time = time.with_timezone(&Eastern);
+    let minute = if time.minute() < 30 { 0 } else { 30 };
+    let mut start = time
+        .with_minute(minute)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(time);
+    for _ in 0..max_steps {
+        let previous = start - step;
+        if get_program(previous) != program {
+            break;
+        }
+        start = previous;
+    }
+    let mut end = start + step;
+    for _ in 0..max_steps {
+        if get_program(end) != program {
+            break;
+        }
+        end += step;
+    }
+    (start.with_timezone(&tz), end.with_timezone(&tz))
+}
+
+#[doc(hidden)]
+pub fn get_program<Tz: TimeZone>(time: DateTime<Tz>) -> Program {
+    let time = time.with_timezone(&Eastern);
+
+    if let Some(program) = specialty::program_at(&time) {
+        return program;
+    }
+
+    if time.weekday() == Weekday::Sat {
+        match met_opera::is_on_air(&time) {
+            Some(true) => return Program::MetropolitanOpera,
+            Some(false) => (),
+            // Without a season, guess. Sometimes starts earlier or ends later.
+            None => match (time.month(), time.hour()) {
+                (12, 13..=17) => return Program::MetropolitanOpera,
+                (1..=5, 13..=17) => return Program::MetropolitanOpera,
+                _ => (),
+            },
+        }
+    }
+
+    regular_program(time.weekday(), time.hour())
+}
+
+/// Returns the program in the regular weekly lineup on `weekday` during `hour`
+/// (Eastern), ignoring specialty programs and the Metropolitan Opera.
+pub(crate) fn regular_program(weekday: Weekday, hour: u32) -> Program {
+    // Regular programs: https://theclassicalstation.org/about-us/
+    match weekday {
+        Weekday::Sat => match hour {
+            0..=5 => Program::SleepersAwake,
+            6..=17 => Program::WeekendClassics,
+            18..=23 => Program::SaturdayEveningRequestProgram,
+            _ => unreachable!(),
+        },
+        Weekday::Sun => match hour {
+            0..=5 => Program::SleepersAwake,
+            6..=17 => Program::WeekendClassics,
+            _ => unreachable!(),
+        },
+        _ => match hour {
+            0..=5 => Program::SleepersAwake,
+            6..=9 => Program::RiseAndShine,
+            10..=12 => Program::ClassicalCafe,
+            13..=15 => Program::AsYouLikeIt,
+            16..=18 => Program::Allegro,
+            19..=21 => Program::ConcertHall,
+            22..=23 => Program::MusicInTheNight,
+            _ => unreachable!(),
+        },
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn parse_eastern_time<Tz: TimeZone>(
+    base: DateTime<Tz>,
+    input: &str,
+) -> Result<DateTime<Tz>> {
+    let (hour, minute) = parse_clock_time(input)?;
+    let tz = base.timezone();
+    base.with_timezone(&Eastern)
+        .with_hour(hour)
+        .and_then(|t| t.with_minute(minute))
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .map(|t| t.with_timezone(&tz))
+        .ok_or(Error::BadTime)
+}
+
+/// Parses a time like "3:04pm" into an hour (0-23) and minute.
+#[cfg(any(feature = "html", test))]
+pub(crate) fn parse_clock_time(input: &str) -> Result<(u32, u32)> {
+    let input = input.trim();
+    let index = input.find(':').ok_or(Error::BadTime)?;
+    let (hh, colon_mm_ampm) = input.split_at(index);
+    let mm_ampm = &colon_mm_ampm[1..];
+    if mm_ampm.len() != 4 || !mm_ampm.is_char_boundary(2) {
+        return Err(Error::BadTime);
+    }
+    let (mm, ampm) = mm_ampm.split_at(2);
+    match (hh.parse::<u32>(), mm.parse::<u32>(), ampm) {
+        (Ok(0), _, _) => Err(Error::BadTime),
+        (Ok(h), _, _) if h > 12 => Err(Error::BadTime),
+        (_, Ok(m), _) if m > 59 => Err(Error::BadTime),
+        (Ok(12), Ok(m), "am") => Ok((0, m)),
+        (Ok(h), Ok(m), "am") => Ok((h, m)),
+        (Ok(12), Ok(m), "pm") => Ok((12, m)),
+        (Ok(h), Ok(m), "pm") => Ok((h + 12, m)),
+        _ => Err(Error::BadTime),
+    }
+}
+
+#[doc(hidden)]
+pub fn eastern_eod<Tz: TimeZone>(base: DateTime<Tz>) -> DateTime<Tz> {
+    let tz = base.timezone();
+    let eastern = base.with_timezone(&Eastern);
+    eastern
+        .with_hour(23)
+        .and_then(|t| t.with_minute(59))
+        .and_then(|t| t.with_second(59))
+        .and_then(|t| t.with_nanosecond(999_999_999))
+        .unwrap_or(eastern)
+        .with_timezone(&tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {assert_matches::assert_matches, chrono_tz::US::Pacific};
+
+    #[test]
+    fn test_response_display() {
+        let response = Response {
+            program: Program::ConcertHall,
+            start_time: Utc.ymd(2020, 9, 4).and_hms(19, 0, 0),
+            end_time: Utc.ymd(2020, 9, 4).and_hms(19, 30, 0),
+            program_start: Utc.ymd(2020, 9, 4).and_hms(19, 0, 0),
+            program_end: Utc.ymd(2020, 9, 4).and_hms(22, 0, 0),
+            composer: Some("Johann Sebastian Bach".to_string()),
+            title: Some("Cello Suite No. 1 in G, BWV 1007".to_string()),
+            performers: None,
+            record_label: None,
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+        assert_eq!(
+            "Program       Concert Hall
+Time          7:00 PM - 7:30 PM
+Composer      Johann Sebastian Bach
+Title         Cello Suite No. 1 in G, BWV 1007
+Performers    <missing>
+Record Label  <missing>",
+            response.display().to_string()
+        );
+        let work = response.work().unwrap();
+        assert_eq!(Catalogue::Bwv, work.catalogue[0].catalogue);
+    }
+
+    #[test]
+    fn test_response_intervals() {
+        let response = lookup_in_html(
+            &Request {
+                time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+            },
+            HTML,
+        )
+        .unwrap();
+        let at = |h, m| Eastern.ymd(2020, 9, 1).and_hms(h, m, 0);
+
+        assert!(!response.contains(&at(0, 0)));
+        assert!(response.contains(&at(0, 1)));
+        assert!(response.contains(&at(5, 59).with_timezone(&Utc)));
+        assert!(!response.contains(&at(6, 0)));
+
+        assert!(response.overlaps(&at(0, 0), &at(0, 2)));
+        assert!(response.overlaps(&at(5, 0), &at(7, 0)));
+        assert!(!response.overlaps(&at(0, 0), &at(0, 1)));
+        assert!(!response.overlaps(&at(6, 0), &at(7, 0)));
+        assert!(!response.overlaps(&at(3, 0), &at(3, 0)));
+        assert!(!response.is_current());
+    }
+
+    #[test]
+    fn test_response_json() {
+        let response = lookup_in_html(
+            &Request {
+                time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+            },
+            HTML,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!("Sleepers, Awake!", json["program"]);
+        assert_eq!("2020-09-01T00:01:00-04:00", json["start_time"]);
+        assert_eq!("Franz Liszt", json["composer"]);
+        assert_eq!(serde_json::Value::Null, json["source_url"]);
+    }
+
+    #[test]
+    fn test_entry_json() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let entries = parse_playlist(HTML, date).unwrap();
+        let json = serde_json::to_value(&entries[1]).unwrap();
+        assert_eq!(1, json["index"]);
+        assert_eq!("Rise and Shine", json["program"]);
+        assert_eq!("2020-09-01T06:00:00-04:00", json["start_time"]);
+        assert_eq!(serde_json::Value::Null, json["raw"]);
+    }
+
+    #[test]
+    fn test_request_on_date() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let request =
+            Request::on_date(date, NaiveTime::from_hms(15, 0, 0)).unwrap();
+        assert_eq!(date.and_hms(15, 0, 0), request.time.naive_local());
+    }
+
+    #[test]
+    fn test_request_whole_day() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let request = Request::whole_day(date);
+        assert_eq!(
+            Eastern.ymd(2020, 9, 1).and_hms(12, 0, 0),
+            request.time.with_timezone(&Eastern)
+        );
+    }
+
+    #[test]
+    fn test_request_from_str() {
+        let request: Request<FixedOffset> =
+            "2023-05-01T14:00:00-04:00".parse().unwrap();
+        assert_eq!(
+            Eastern.ymd(2023, 5, 1).and_hms(14, 0, 0),
+            request.time.with_timezone(&Eastern)
+        );
+
+        let request: Request<FixedOffset> = " 2023-05-01 ".parse().unwrap();
+        assert_eq!(Eastern.ymd(2023, 5, 1).and_hms(12, 0, 0), request.time);
+        assert_eq!(-4 * 3600, request.time.offset().local_minus_utc());
+
+        assert_matches!(
+            "2023-05-01T14:00:00".parse::<Request<FixedOffset>>(),
+            Err(_)
+        );
+        assert_matches!("yesterday".parse::<Request<FixedOffset>>(), Err(_));
+    }
+
+    #[test]
+    fn test_playlist_url_eastern() {
+        let monday = Eastern
+            .ymd(2017, 7, 3)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(
+            "https://theclassicalstation.org/listen/playlist/?date=2017-07-03",
+            playlist_url(monday)
+        );
+
+        let friday = Eastern
+            .ymd(2017, 7, 7)
+            .and_hms(23, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(
+            "https://theclassicalstation.org/listen/playlist/?date=2017-07-07",
+            playlist_url(friday)
+        );
+    }
+
+    #[test]
+    fn test_playlist_url_pacific() {
+        let monday = Pacific
+            .ymd(2017, 7, 3)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(
+            "https://theclassicalstation.org/listen/playlist/?date=2017-07-03",
+            playlist_url(monday)
+        );
+
+        let friday = Pacific
+            .ymd(2017, 7, 7)
+            .and_hms(23, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(
+            "https://theclassicalstation.org/listen/playlist/?date=2017-07-08",
+            playlist_url(friday)
+        );
+    }
+
+    #[test]
+    fn test_parse_eastern_time_err() {
+        let now = Local::now();
+
+        assert_matches!(parse_eastern_time(now, ""), Err(_));
+        assert_matches!(parse_eastern_time(now, "00"), Err(_));
+        assert_matches!(parse_eastern_time(now, "-1"), Err(_));
+        assert_matches!(parse_eastern_time(now, "24:00"), Err(_));
+        assert_matches!(parse_eastern_time(now, "A:B"), Err(_));
+        assert_matches!(parse_eastern_time(now, "01:02"), Err(_));
+        assert_matches!(parse_eastern_time(now, "01:02ZZ"), Err(_));
+        assert_matches!(parse_eastern_time(now, "01:02AM"), Err(_));
+        assert_matches!(parse_eastern_time(now, "00:01am"), Err(_));
+        assert_matches!(parse_eastern_time(now, "13:00pm"), Err(_));
+        assert_matches!(parse_eastern_time(now, "4294967295:00pm"), Err(_));
+        assert_matches!(parse_eastern_time(now, "1:aéb"), Err(_));
+    }
+
+    #[test]
+    fn test_parse_eastern_time_ok() {
+        let now = Local::now();
+
+        assert_matches!(parse_eastern_time(now, "12:00am"), Ok(_));
+        assert_matches!(parse_eastern_time(now, " 12:00am "), Ok(_));
+        assert_matches!(parse_eastern_time(now, "12:00am"), Ok(_));
+        assert_matches!(parse_eastern_time(now, "11:59pm"), Ok(_));
+        assert_matches!(parse_eastern_time(now, "3:34pm"), Ok(_));
+    }
+
+    #[test]
+    fn test_parse_eastern_time_daylight_savings() {
+        let base = Eastern
+            .ymd(2019, 11, 3)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+
+        assert_matches!(parse_eastern_time(base, "1:34am"), Err(_));
+    }
+
+    #[test]
+    fn test_parse_eastern_time_eastern() {
+        let base = Eastern
+            .ymd(2017, 7, 10)
+            .and_hms(23, 0, 0)
+            .with_timezone(&Local);
+
+        assert_eq!(
+            Eastern
+                .ymd(2017, 7, 10)
+                .and_hms(12, 0, 0)
+                .with_timezone(&Local),
+            parse_eastern_time(base, "12:00pm").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_eastern_time_pacific() {
+        let base = Pacific
+            .ymd(2017, 7, 10)
+            .and_hms(23, 0, 0)
+            .with_timezone(&Local);
+
+        assert_eq!(
+            Eastern
+                .ymd(2017, 7, 11)
+                .and_hms(12, 0, 0)
+                .with_timezone(&Local),
+            parse_eastern_time(base, "12:00pm").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_eastern_eod() {
+        let almost_one_minute = Duration::minutes(1) - Duration::nanoseconds(1);
+
+        let base = Local::now();
+        assert_eq!(
+            parse_eastern_time(base, "11:59pm").unwrap() + almost_one_minute,
+            eastern_eod(base)
+        );
+
+        let base = Pacific
+            .ymd(2017, 7, 10)
+            .and_hms(23, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(
+            parse_eastern_time(base, "11:59pm").unwrap() + almost_one_minute,
+            eastern_eod(base)
+        );
+    }
+
+    #[test]
+    fn test_program_bounds() {
+        let bounds = |h, m| {
+            let (start, end) =
+                program_bounds(Eastern.ymd(2020, 9, 3).and_hms(h, m, 0));
+            (start.hour(), start.minute(), end.hour(), end.minute())
+        };
+        // Thursday Night Opera House, 7-10pm.
+        assert_eq!((19, 0, 22, 0), bounds(20, 17));
+        // Music in the Night, 10pm until Sleepers, Awake! at midnight.
+        assert_eq!((22, 0, 0, 0), bounds(23, 59));
+
+        // Sing for Joy on Sunday, 7:30-8am.
+        let (start, end) =
+            program_bounds(Eastern.ymd(2020, 9, 6).and_hms(7, 45, 0));
+        assert_eq!(Eastern.ymd(2020, 9, 6).and_hms(7, 30, 0), start);
+        assert_eq!(Eastern.ymd(2020, 9, 6).and_hms(8, 0, 0), end);
+    }
+
+    #[test]
+    fn test_get_program_specialty() {
+        let time = Eastern
+            .ymd(2020, 9, 7)
+            .and_hms(19, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(Program::MyLifeInMusic, get_program(time));
+    }
+
+    #[test]
+    fn test_get_program_nfc() {
+        use unicode_normalization::is_nfc;
+
+        let time = Eastern
+            .ymd(2020, 9, 4)
+            .and_hms(12, 0, 0)
+            .with_timezone(&Local);
+        assert!(is_nfc(get_program(time).name()));
+    }
+
+    #[test]
+    fn test_get_program_regular() {
+        let time = Eastern
+            .ymd(2020, 9, 4)
+            .and_hms(12, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!("Classical Cafe", get_program(time).name());
+    }
+
+    const MISSING: &str = "<missing>";
+
+    #[test]
+    fn test_get_program_missing() {
+        let time = Eastern
+            .ymd(2020, 9, 5)
+            .and_hms(2, 0, 0)
+            .with_timezone(&Local);
+        assert_eq!(MISSING, get_program(time).name());
+    }
+
+    #[test]
+    fn test_lookup_in_html_parse_err() {
+        let request = Request { time: Local::now() };
+
+        assert_matches!(lookup_in_html(&request, ""), Err(_));
+        assert_matches!(lookup_in_html(&request, "<table></table>"), Err(_));
+        assert_matches!(
+            lookup_in_html(&request, "<table><tr></tr></table>"),
+            Err(_)
+        );
+    }
+
+    pub(crate) const HTML: &str = include_str!("../tests/playlist.html");
+
+    #[test]
+    fn test_lookup_in_html_too_early() {
+        let time = parse_eastern_time(Local::now(), "12:00am").unwrap();
+        assert_matches!(lookup_in_html(&Request { time }, HTML), Err(_));
+    }
+
+    #[test]
+    fn test_lookup_in_html_first() {
+        let t = Eastern
+            .ymd(2020, 9, 4)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+
+        let expected = Response {
+            program: Program::from_name("Sleepers Awake"),
+            start_time: parse_eastern_time(t, "12:01am").unwrap(),
+            end_time: parse_eastern_time(t, "6:00am").unwrap(),
+            program_start: t,
+            program_end: parse_eastern_time(t, "6:00am").unwrap(),
+            composer: Some("Franz Liszt".to_string()),
+            title: Some(
+                "Tasso: Lament & Trimuph (Symphonic Poem No. 2)".to_string(),
+            ),
+            performers: Some("Gewandhaus Orchestra/Masur".to_string()),
+            record_label: Some("Naxos".to_string()),
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+
+        let time = parse_eastern_time(t, "12:01am").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+
+        let time = parse_eastern_time(t, "12:02am").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+
+        let time = parse_eastern_time(t, "5:59am").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+    }
+
+    #[test]
+    fn test_lookup_in_html_last() {
+        let t = Eastern
+            .ymd(2020, 9, 4)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+
+        let expected = Response {
+            program: Program::from_name("Rise and Shine"),
+            start_time: parse_eastern_time(t, "6:00am").unwrap(),
+            end_time: eastern_eod(t),
+            program_start: parse_eastern_time(t, "6:00am").unwrap(),
+            program_end: parse_eastern_time(t, "10:00am").unwrap(),
+            composer: Some("George Frideric Handel".to_string()),
+            title: Some("Concerto Grosso in D, Op. 3 No. 6".to_string()),
+            performers: Some(
+                "Concentus Musicus of Vienna/Harnoncourt".to_string(),
+            ),
+            record_label: Some("MHS".to_string()),
+            source_url: None,
+            fetched_at: None,
+            warnings: Vec::new(),
+        };
+
+        let time = parse_eastern_time(t, "6:00am").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+
+        let time = parse_eastern_time(t, "6:01am").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+
+        let time = parse_eastern_time(t, "11:59pm").unwrap();
+        assert_eq!(expected, lookup_in_html(&Request { time }, HTML).unwrap());
+    }
+
+    #[test]
+    fn test_lookup_in_html_utc() {
+        let t = Eastern.ymd(2020, 9, 4).and_hms(0, 0, 0).with_timezone(&Utc);
+        let time = parse_eastern_time(t, "6:30am").unwrap();
+        let response = lookup_in_html(&Request { time }, HTML).unwrap();
+
+        assert_eq!(Utc, response.start_time.timezone());
+        assert_eq!(
+            Eastern.ymd(2020, 9, 4).and_hms(6, 0, 0),
+            response.start_time
+        );
+        assert_eq!(
+            eastern_eod(Local::now()).with_timezone(&Eastern).time(),
+            response.end_time.with_timezone(&Eastern).time()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Broadcast dates for the Metropolitan Opera on Saturday afternoons.
+
+use {
+    chrono::{DateTime, FixedOffset, TimeZone},
+    serde::{Deserialize, Serialize},
+    std::sync::Mutex,
+};
+
+/// A season of Metropolitan Opera radio broadcasts.
+///
+/// The station carries the Met's Saturday matinee broadcasts, but the season's
+/// dates and start times vary from year to year and operas run different
+/// lengths. Without a season, the program for Saturday afternoons is a guess
+/// based on the usual months and hours. Load the actual broadcast times and
+/// pass them to `set_met_opera_season` to label them correctly.
+///
+/// Seasons can be loaded from JSON like this:
+///
+/// ```json
+/// {
+///   "broadcasts": [
+///     {
+///       "start": "2020-12-05T13:00:00-05:00",
+///       "end": "2020-12-05T16:30:00-05:00"
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MetOperaSeason {
+    /// The broadcasts in the season.
+    pub broadcasts: Vec<Broadcast>,
+}
+
+/// A single Metropolitan Opera broadcast.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Broadcast {
+    /// Time the broadcast starts.
+    pub start: DateTime<FixedOffset>,
+    /// Time the broadcast ends.
+    pub end: DateTime<FixedOffset>,
+}
+
+impl MetOperaSeason {
+    /// Parses a season from JSON in the format shown above.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns true if a broadcast is on the air at `time`.
+    pub fn is_on_air<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> bool {
+        self.broadcasts
+            .iter()
+            .any(|b| b.start <= *time && *time < b.end)
+    }
+}
+
+static SEASON: Mutex<Option<MetOperaSeason>> = Mutex::new(None);
+
+/// Sets the Metropolitan Opera season used to determine programs for the rest
+/// of the process. Passing `None` goes back to guessing.
+pub fn set_met_opera_season(season: Option<MetOperaSeason>) {
+    *SEASON.lock().unwrap() = season;
+}
+
+/// Returns whether a broadcast is on the air at `time` according to the
+/// current season, or `None` if no season is set.
+pub(crate) fn is_on_air<Tz: TimeZone>(time: &DateTime<Tz>) -> Option<bool> {
+    SEASON
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|season| season.is_on_air(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Utc};
+
+    const JSON: &str = r#"{
+        "broadcasts": [
+            {
+                "start": "2020-12-05T13:00:00-05:00",
+                "end": "2020-12-05T16:30:00-05:00"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_json() {
+        let season = MetOperaSeason::from_json(JSON).unwrap();
+        assert_eq!(1, season.broadcasts.len());
+        assert!(MetOperaSeason::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_is_on_air() {
+        let season = MetOperaSeason::from_json(JSON).unwrap();
+        let at =
+            |h, m| season.is_on_air(&Utc.ymd(2020, 12, 5).and_hms(h, m, 0));
+        assert!(!at(17, 59));
+        assert!(at(18, 0));
+        assert!(at(21, 29));
+        assert!(!at(21, 30));
+    }
+}
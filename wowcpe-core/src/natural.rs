@@ -0,0 +1,209 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Parsing times written the way people say them.
+
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Weekday,
+};
+
+/// Parses a time written in natural language, relative to `now`.
+///
+/// This understands times like "8pm", "19:30", "noon", dates like "yesterday",
+/// "tomorrow", "thursday", "last thursday", and "2020-09-01", combinations of
+/// the two like "yesterday 8pm", and relative times like "now" and "2 hours
+/// ago". A date without a time keeps the time of day from `now`, and a
+/// weekday by itself means the most recent one (today if it matches). Parsing
+/// is case-insensitive. Returns `None` if the input is not understood, if a
+/// relative amount is not positive or too large, or if the time does not exist
+/// because of a daylight saving transition.
+pub fn parse_natural_time<Tz: TimeZone>(
+    input: &str,
+    now: DateTime<Tz>,
+) -> Option<DateTime<Tz>> {
+    let input = input.trim().to_lowercase();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    match words.as_slice() {
+        ["now"] => return Some(now),
+        [amount, unit, "ago"] => {
+            return now.checked_sub_signed(parse_duration(amount, unit)?)
+        }
+        _ => (),
+    }
+
+    let today = now.naive_local().date();
+    let (date, rest) = match words.as_slice() {
+        ["today", rest @ ..] => (today, rest),
+        ["yesterday", rest @ ..] => (today.pred_opt()?, rest),
+        ["tomorrow", rest @ ..] => (today.succ_opt()?, rest),
+        ["last", weekday, rest @ ..] => {
+            let weekday = parse_weekday(weekday)?;
+            (most_recent(weekday, today.pred_opt()?), rest)
+        }
+        [first, rest @ ..] => {
+            if let Some(weekday) = parse_weekday(first) {
+                (most_recent(weekday, today), rest)
+            } else if let Ok(date) =
+                NaiveDate::parse_from_str(first, "%Y-%m-%d")
+            {
+                (date, rest)
+            } else {
+                (today, &words[..])
+            }
+        }
+        [] => return None,
+    };
+    let time = match rest {
+        [] => now.naive_local().time(),
+        _ => parse_time_of_day(&rest.concat())?,
+    };
+    now.timezone()
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+}
+
+/// Parses a time of day like "8pm", "8:30 PM" (with the space already
+/// removed), "19:30", "noon", or "midnight".
+fn parse_time_of_day(input: &str) -> Option<NaiveTime> {
+    match input {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => (),
+    }
+    let (input, pm) = if let Some(rest) = input.strip_suffix("am") {
+        (rest, Some(false))
+    } else if let Some(rest) = input.strip_suffix("pm") {
+        (rest, Some(true))
+    } else {
+        (input, None)
+    };
+    let (hour, minute) = match input.find(':') {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => (input, "0"),
+    };
+    let (mut hour, minute): (u32, u32) =
+        (hour.parse().ok()?, minute.parse().ok()?);
+    match pm {
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some(false) if hour == 12 => hour = 0,
+        Some(true) if hour != 12 => hour += 12,
+        _ => (),
+    }
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Parses a positive duration like "2 hours". Returns `None` for zero, negative,
+/// or out of range amounts.
+fn parse_duration(amount: &str, unit: &str) -> Option<Duration> {
+    let amount: i64 = amount.parse().ok().filter(|&amount| amount > 0)?;
+    match unit.trim_end_matches('s') {
+        "sec" | "second" => Duration::try_seconds(amount),
+        "min" | "minute" => Duration::try_minutes(amount),
+        "hr" | "hour" => Duration::try_hours(amount),
+        "day" => Duration::try_days(amount),
+        "week" => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+fn parse_weekday(input: &str) -> Option<Weekday> {
+    // Weekday's FromStr accepts both full names and abbreviations.
+    input.parse().ok()
+}
+
+/// Returns the latest date on or before `date` that falls on `weekday`.
+fn most_recent(weekday: Weekday, date: NaiveDate) -> NaiveDate {
+    let days_back = (7 + date.weekday().num_days_from_monday()
+        - weekday.num_days_from_monday())
+        % 7;
+    date - Duration::days(days_back.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Utc};
+
+    // A Wednesday.
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2020, 9, 2).and_hms(10, 15, 0)
+    }
+
+    fn parse(input: &str) -> Option<DateTime<Utc>> {
+        parse_natural_time(input, now())
+    }
+
+    #[test]
+    fn test_time_only() {
+        assert_eq!(Some(Utc.ymd(2020, 9, 2).and_hms(20, 0, 0)), parse("8pm"));
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 2).and_hms(20, 30, 0)),
+            parse("8:30 PM")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 2).and_hms(19, 30, 0)),
+            parse("19:30")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 2).and_hms(0, 5, 0)),
+            parse("12:05am")
+        );
+        assert_eq!(Some(Utc.ymd(2020, 9, 2).and_hms(12, 0, 0)), parse("noon"));
+        assert_eq!(Some(Utc.ymd(2020, 9, 2).and_hms(7, 0, 0)), parse("7"));
+    }
+
+    #[test]
+    fn test_date_and_time() {
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 1).and_hms(20, 0, 0)),
+            parse("Yesterday 8pm")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 8, 27).and_hms(19, 30, 0)),
+            parse("last thursday 19:30")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 8, 26).and_hms(10, 15, 0)),
+            parse("last wed")
+        );
+        assert_eq!(Some(now()), parse("wednesday"));
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 3).and_hms(10, 15, 0)),
+            parse("tomorrow")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 1, 31).and_hms(6, 0, 0)),
+            parse("2020-01-31 6am")
+        );
+    }
+
+    #[test]
+    fn test_relative() {
+        assert_eq!(Some(now()), parse("now"));
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 2).and_hms(8, 15, 0)),
+            parse("2 hours ago")
+        );
+        assert_eq!(
+            Some(Utc.ymd(2020, 9, 2).and_hms(10, 14, 0)),
+            parse("1 min ago")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(None, parse(""));
+        assert_eq!(None, parse("13pm"));
+        assert_eq!(None, parse("0am"));
+        assert_eq!(None, parse("25:00"));
+        assert_eq!(None, parse("yesterday at 8"));
+        assert_eq!(None, parse("2 fortnights ago"));
+        assert_eq!(None, parse("last"));
+    }
+
+    #[test]
+    fn test_relative_out_of_range() {
+        assert_eq!(None, parse("-3 hours ago"));
+        assert_eq!(None, parse("0 days ago"));
+        assert_eq!(None, parse("9223372036854775807 weeks ago"));
+        assert_eq!(None, parse("9223372036854775807 seconds ago"));
+    }
+}
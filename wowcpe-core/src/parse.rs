@@ -0,0 +1,897 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Scraping playlist entries out of the station's HTML.
+
+use {
+    crate::trace::{debug, debug_span, warn},
+    crate::{
+        eastern_eod, get_program, parse_clock_time, Eastern, Entry, Error,
+        RawFields, Result, Warning,
+    },
+    chrono::{offset::LocalResult, Duration, NaiveDate, TimeZone},
+    once_cell::sync::Lazy,
+    scraper::{ElementRef, Html, Node, Selector},
+    unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization},
+};
+
+/// A layout of the playlist page that the parser knows how to scrape.
+///
+/// The station's website gets redesigned every few years, so the parser tries
+/// each strategy in turn and uses the first one that matches.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Strategy {
+    /// The current layout, with a `div.playlist-song` for each entry inside an
+    /// `article.block--playlist`.
+    PlaylistSong,
+    /// A table with a row for each entry, and columns for the time, composer,
+    /// title, performers, and record label.
+    Table,
+    /// A heuristic for unknown layouts: any element whose class mentions
+    /// "time" and which contains a time is assumed to start an entry, with the
+    /// rest of the entry found in its parent.
+    Generic,
+}
+
+const STRATEGIES: [Strategy; 3] =
+    [Strategy::PlaylistSong, Strategy::Table, Strategy::Generic];
+
+/// Parses all entries from the HTML of the playlist page for `date`, which is
+/// interpreted in the station's time zone (US Eastern).
+///
+/// Like `lookup_in_html`, this does no I/O. Entries are returned in the order
+/// they were played. Returns an error if extracting the entries fails.
+///
+/// This never panics, whatever the HTML. Malformed input results in an error,
+/// skipped entries, or fields with replacement characters. Use
+/// `Playlist::parse` to find out which entries were skipped and why.
+pub fn parse_playlist(html: &str, date: NaiveDate) -> Result<Vec<Entry>> {
+    parse_playlist_with_strategy(html, date).map(|(entries, _)| entries)
+}
+
+/// Like `parse_playlist`, but also returns the strategy that matched.
+pub fn parse_playlist_with_strategy(
+    html: &str,
+    date: NaiveDate,
+) -> Result<(Vec<Entry>, Strategy)> {
+    parse_playlist_with_warnings(html, date)
+        .map(|(entries, strategy, _)| (entries, strategy))
+}
+
+/// Like `parse_playlist_with_strategy`, but also returns warnings about
+/// entries that were skipped.
+pub(crate) fn parse_playlist_with_warnings(
+    html: &str,
+    date: NaiveDate,
+) -> Result<(Vec<Entry>, Strategy, Vec<Warning>)> {
+    let (entries, strategy, warnings) = parse_after(html, date, &[])?
+        .expect("there are no known entries to mismatch");
+    Ok((entries, strategy, warnings))
+}
+
+/// Returns true if `html` contains a playlist with at least one entry.
+pub fn has_playlist(html: &str) -> bool {
+    let root = Html::parse_fragment(html);
+    let found = root.select(&PLAYLIST_SONG).next().is_some();
+    found
+}
+
+/// Parses only the entries that come after `known`, which should be the
+/// entries from an earlier copy of the same page. Fields of the known entries
+/// are not extracted again, only their times, which must match. Returns
+/// `None` if they don't, in which case the page should be parsed from scratch.
+///
+/// The returned entries' indices continue from `known`, and the returned
+/// warnings are only about the new entries.
+pub(crate) fn parse_new_entries(
+    html: &str,
+    date: NaiveDate,
+    known: &[Entry],
+) -> Result<Option<(Vec<Entry>, Vec<Warning>)>> {
+    Ok(parse_after(html, date, known)?
+        .map(|(entries, _, warnings)| (entries, warnings)))
+}
+
+/// Entries parsed from a page, the strategy that found them, and warnings.
+type Parsed = (Vec<Entry>, Strategy, Vec<Warning>);
+
+fn parse_after(
+    html: &str,
+    date: NaiveDate,
+    known: &[Entry],
+) -> Result<Option<Parsed>> {
+    let _span = debug_span!("parse", bytes = html.len()).entered();
+    let root = Html::parse_fragment(html);
+    let mut first_error = None;
+    for &strategy in &STRATEGIES {
+        match strategy.scrape(root.root_element(), known.len()) {
+            Ok(Some(raw)) => {
+                debug!(?strategy, entries = raw.len(), "matched strategy");
+                return Ok(to_entries(raw, date, known)
+                    .map(|(entries, warnings)| (entries, strategy, warnings)));
+            }
+            Ok(None) => (),
+            Err(err) => {
+                debug!(?strategy, %err, "strategy failed");
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_error.unwrap_or(Error::BadScrape))
+}
+
+/// The inner HTML of an entry's fields, before any further processing.
+#[derive(Default)]
+struct RawEntry {
+    hour: Option<String>,
+    /// Index of the hour section the entry appears in, counting from 1, or 0
+    /// if it doesn't appear under a heading.
+    section: usize,
+    time: String,
+    title: Option<String>,
+    composer: Option<String>,
+    performers: Option<String>,
+    record_label: Option<String>,
+}
+
+impl RawEntry {
+    /// Fills in a field given an element like `<li>Composed by: Liszt</li>`.
+    fn add_labeled(&mut self, element: ElementRef<'_>) {
+        // Check the text first to avoid serializing unlabeled elements.
+        let text = element.text().next().unwrap_or_default().trim_start();
+        let (field, label) = if text.starts_with("Composed by:") {
+            (&mut self.composer, "Composed by:")
+        } else if text.starts_with("Performed by:") {
+            (&mut self.performers, "Performed by:")
+        } else if text.starts_with("Label:") {
+            (&mut self.record_label, "Label:")
+        } else {
+            return;
+        };
+        let mut html = inner_html(element);
+        let start = html.len() - html.trim_start().len();
+        if html[start..].starts_with(label) {
+            html.drain(..start + label.len());
+            *field = Some(html);
+        }
+    }
+}
+
+/// Declares selectors that are compiled once, on first use. Compiling only
+/// panics if a selector is invalid, and they are all literals covered by tests.
+macro_rules! selectors {
+    ($($(#[$attr:meta])* $vis:vis $name:ident = $selector:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: Lazy<Selector> =
+                Lazy::new(|| Selector::parse($selector).unwrap());
+        )*
+
+        #[cfg(test)]
+        static ALL_SELECTORS: &[&Lazy<Selector>] = &[$(&$name),*];
+    };
+}
+
+selectors! {
+    PLAYLIST = "article.block--playlist";
+    PLAYLIST_SONG = "article.block--playlist div.playlist-song";
+    SONG_OR_HOUR = "h3.playlist-hour, div.playlist-song";
+    SONG_TIME = "div.playlist-song__time";
+    SONG_TITLE = "h4.playlist-song__title";
+    SONG_META = "ul.playlist-song__meta > li";
+    TABLE_ROW = "table tr";
+    TABLE_CELL = "td";
+    GENERIC_TIME = r#"[class*="time"]"#;
+    GENERIC_TITLE = r#"[class*="title"], h3, h4"#;
+    GENERIC_LABELED = "li, p, span";
+}
+
+impl Strategy {
+    /// Scrapes raw entries from `root`. Returns `None` if the layout does not
+    /// match, and an error if it matches but is malformed. Only the time (and
+    /// hour heading) is extracted for the first `skip` entries.
+    fn scrape(
+        self,
+        root: ElementRef<'_>,
+        skip: usize,
+    ) -> Result<Option<Vec<RawEntry>>> {
+        match self {
+            Strategy::PlaylistSong => scrape_playlist_song(root, skip),
+            Strategy::Table => Ok(scrape_table(root, skip)),
+            Strategy::Generic => Ok(scrape_generic(root, skip)),
+        }
+    }
+}
+
+fn scrape_playlist_song(
+    root: ElementRef<'_>,
+    skip: usize,
+) -> Result<Option<Vec<RawEntry>>> {
+    let root = match root.select(&PLAYLIST).next() {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    let mut entries = Vec::new();
+    let mut hour = None;
+    let mut section = 0;
+    for div in root.select(&SONG_OR_HOUR) {
+        if div.value().name() == "h3" {
+            hour = Some(trim_owned(div.text().collect()));
+            section += 1;
+            continue;
+        }
+        let mut raw = RawEntry {
+            hour: hour.clone(),
+            section,
+            time: inner_html(div.select_one(&SONG_TIME)?),
+            ..RawEntry::default()
+        };
+        if entries.len() >= skip {
+            raw.title = div
+                .select(&SONG_TITLE)
+                .next()
+                .map(|h4| trim_owned(inner_html(h4)));
+            for li in div.select(&SONG_META) {
+                raw.add_labeled(li);
+            }
+        }
+        entries.push(raw);
+    }
+    Ok(Some(entries))
+}
+
+fn scrape_table(root: ElementRef<'_>, skip: usize) -> Option<Vec<RawEntry>> {
+    let mut entries = Vec::new();
+    for tr in root.select(&TABLE_ROW) {
+        // Most rows of an unrelated table fail the time check, so check it
+        // before serializing the rest of the cells.
+        let mut cells = tr.select(&TABLE_CELL);
+        let time = match cells.next().map(inner_html) {
+            Some(time) if looks_like_time(&time) => time,
+            _ => continue,
+        };
+        if entries.len() < skip {
+            if cells.nth(1).is_some() {
+                entries.push(RawEntry {
+                    time,
+                    ..RawEntry::default()
+                });
+            }
+            continue;
+        }
+        let mut cells = cells.map(inner_html).peekable();
+        let composer = cells.next();
+        if cells.peek().is_none() {
+            continue;
+        }
+        entries.push(RawEntry {
+            hour: None,
+            section: 0,
+            time,
+            composer,
+            title: cells.next(),
+            performers: cells.next(),
+            record_label: cells.next(),
+        });
+    }
+    if entries.is_empty() {
+        None
+    } else {
+        Some(entries)
+    }
+}
+
+fn scrape_generic(root: ElementRef<'_>, skip: usize) -> Option<Vec<RawEntry>> {
+    let mut entries = Vec::new();
+    for time in root.select(&GENERIC_TIME) {
+        let text = inner_html(time);
+        if !looks_like_time(&text) {
+            continue;
+        }
+        let parent = match time.parent().and_then(ElementRef::wrap) {
+            Some(parent) => parent,
+            None => continue,
+        };
+        let mut raw = RawEntry {
+            time: text,
+            ..RawEntry::default()
+        };
+        if entries.len() >= skip {
+            raw.title = parent
+                .select(&GENERIC_TITLE)
+                .next()
+                .map(|e| trim_owned(inner_html(e)));
+            for e in parent.select(&GENERIC_LABELED) {
+                raw.add_labeled(e);
+            }
+        }
+        entries.push(raw);
+    }
+    if entries.is_empty() {
+        None
+    } else {
+        Some(entries)
+    }
+}
+
+/// Returns true if `text` normalizes to something like "3:04pm". This is
+/// called on many elements that aren't times, so it avoids allocating.
+fn looks_like_time(text: &str) -> bool {
+    let mut end = text.trim().chars().rev().filter(|&c| c != ' ');
+    let m = end.next().map(|c| c.eq_ignore_ascii_case(&'m'));
+    let ap = end.next().map(|c| matches!(c, 'a' | 'A' | 'p' | 'P'));
+    text.contains(':') && m == Some(true) && ap == Some(true)
+}
+
+/// Normalizes times like " 3:04 PM" to "3:04pm".
+fn normalize_time(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter(|&c| c != ' ')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns the inner HTML of `element`, like `ElementRef::inner_html`. Most
+/// fields are a single text node, which this serializes directly into one
+/// string instead of going through the general serializer.
+fn inner_html(element: ElementRef<'_>) -> String {
+    let mut html = String::new();
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => escape_text(&mut html, text),
+            _ => return element.inner_html(),
+        }
+    }
+    html
+}
+
+/// Appends `text` to `html`, escaped the same way as the serializer escapes
+/// text content.
+fn escape_text(html: &mut String, text: &str) {
+    html.reserve(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(&['&', '<', '>', '\u{a0}'][..]) {
+        html.push_str(&rest[..i]);
+        let c = rest[i..].chars().next().unwrap_or_default();
+        html.push_str(match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            _ => "&nbsp;",
+        });
+        rest = &rest[i + c.len_utf8()..];
+    }
+    html.push_str(rest);
+}
+
+/// Trims whitespace from both ends of `s` in place.
+fn trim_owned(mut s: String) -> String {
+    s.truncate(s.trim_end().len());
+    let start = s.len() - s.trim_start().len();
+    s.drain(..start);
+    s
+}
+
+/// Converts raw entries to entries, skipping over those already in `known`.
+/// Returns `None` if the raw entries don't start with the known ones.
+fn to_entries(
+    raw: Vec<RawEntry>,
+    date: NaiveDate,
+    known: &[Entry],
+) -> Option<(Vec<Entry>, Vec<Warning>)> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut warnings = Vec::new();
+    // Hour headings seen so far, with the section each first appeared in.
+    let mut headings: Vec<(String, usize)> = Vec::new();
+    let mut remaining = known.iter();
+    let mut previous = None;
+    for raw in raw {
+        let time = normalize_time(&raw.time);
+        let (h, m) = match parse_clock_time(&time) {
+            Ok(clock) => clock,
+            Err(_) if remaining.len() > 0 => continue,
+            Err(_) => {
+                warn!(%time, "skipping invalid time");
+                warnings.push(Warning::BadTime(time));
+                continue;
+            }
+        };
+        let repeated_hour = match &raw.hour {
+            Some(hour) => {
+                let hour = normalize_time(hour);
+                match headings.iter().find(|(h, _)| *h == hour) {
+                    Some(&(_, section)) => section < raw.section,
+                    None => {
+                        headings.push((hour, raw.section));
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+        if let Some(entry) = remaining.next() {
+            if entry.raw.time != raw.time {
+                return None;
+            }
+            previous = Some(entry.start_time);
+            continue;
+        }
+        let naive = date.and_hms(h, m, 0);
+        let start_time = match Eastern.from_local_datetime(&naive) {
+            LocalResult::Single(start_time) => start_time,
+            LocalResult::Ambiguous(earlier, later) => {
+                // The hour repeats when clocks fall back. Prefer the second
+                // occurrence if the entry is under a repeated hour heading or
+                // the first occurrence would put it before the entry above it.
+                if repeated_hour || previous.is_some_and(|p| earlier < p) {
+                    later
+                } else {
+                    if previous.is_none() && raw.hour.is_none() {
+                        warn!(%time, "assuming first occurrence of time");
+                        warnings.push(Warning::AmbiguousTime(time));
+                    }
+                    earlier
+                }
+            }
+            LocalResult::None => {
+                // The hour is skipped when clocks spring forward, so the time
+                // must have been written before the clocks were changed.
+                let hour = Duration::hours(1);
+                match Eastern.from_local_datetime(&(naive - hour)).single() {
+                    Some(before) => {
+                        warn!(%time, "shifting time that does not exist");
+                        warnings.push(Warning::SkippedTime(time));
+                        before + hour
+                    }
+                    None => {
+                        warn!(%time, "skipping time that does not exist");
+                        warnings.push(Warning::BadTime(time));
+                        continue;
+                    }
+                }
+            }
+        };
+        if let Some(previous) = entries.last_mut() {
+            previous.end_time = start_time.into();
+        }
+        previous = Some(start_time.into());
+        entries.push(Entry {
+            index: known.len() + entries.len(),
+            program: get_program(start_time),
+            start_time: start_time.into(),
+            end_time: eastern_eod(start_time).into(),
+            composer: parse_field(raw.composer.as_deref()),
+            title: parse_field(raw.title.as_deref()),
+            performers: parse_field(raw.performers.as_deref()),
+            record_label: parse_field(raw.record_label.as_deref()),
+            hour: raw.hour,
+            raw: RawFields {
+                time: raw.time,
+                title: raw.title,
+                composer: raw.composer,
+                performers: raw.performers,
+                record_label: raw.record_label,
+            },
+        });
+    }
+    if remaining.len() > 0 {
+        return None;
+    }
+    Some((entries, warnings))
+}
+
+trait SelectExt<'a> {
+    fn select_one(&'a self, sel: &Selector) -> Result<ElementRef<'a>>;
+}
+
+impl<'a> SelectExt<'a> for ElementRef<'a> {
+    fn select_one(&'a self, sel: &Selector) -> Result<ElementRef<'a>> {
+        self.select(sel).next().ok_or(Error::BadScrape)
+    }
+}
+
+/// Decodes all HTML entities (named and numeric) in a field and normalizes it
+/// to NFC, so that "Caf&#233;", "Caf&eacute;", and "Cafe\u{301}" are all equal.
+/// Returns `None` if the field is absent or blank.
+fn parse_field(html: Option<&str>) -> Option<String> {
+    let html = html?.trim();
+    if html.is_empty() {
+        return None;
+    }
+    let decoded = html_escape::decode_html_entities(html);
+    // Most fields are already NFC, so skip the normalizing iterator's buffer.
+    if is_nfc_quick(decoded.chars()) == IsNormalized::Yes {
+        return Some(decoded.into_owned());
+    }
+    Some(decoded.nfc().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{eastern_noon, parse_eastern_time, tests::HTML, Program},
+        assert_matches::assert_matches,
+        chrono::{TimeZone, Timelike},
+    };
+
+    #[test]
+    fn test_selectors_compile() {
+        for selector in ALL_SELECTORS {
+            Lazy::force(selector);
+        }
+    }
+
+    #[test]
+    fn test_parse_field_none() {
+        assert_eq!(None, parse_field(None));
+        assert_eq!(None, parse_field(Some(" ")));
+    }
+
+    #[test]
+    fn test_parse_field_some() {
+        assert_eq!(
+            Some("Something".to_string()),
+            parse_field(Some(" Something "))
+        );
+        assert_eq!(Some("a & b".to_string()), parse_field(Some("a &amp; b ")));
+        assert_eq!(
+            Some("'Twas so".to_string()),
+            parse_field(Some("&apos;Twas so"))
+        );
+        assert_eq!(Some("what &a;".to_string()), parse_field(Some("what &a;")));
+    }
+
+    #[test]
+    fn test_parse_field_entities() {
+        assert_eq!(Some("Café".to_string()), parse_field(Some("Caf&#233;")));
+        assert_eq!(Some("Café".to_string()), parse_field(Some("Caf&#xE9;")));
+        assert_eq!(Some("Café".to_string()), parse_field(Some("Caf&eacute;")));
+        assert_eq!(
+            Some("Dvořák".to_string()),
+            parse_field(Some("Dvo&rcaron;&aacute;k"))
+        );
+        assert_eq!(Some("a\u{a0}b".to_string()), parse_field(Some("a&nbsp;b")));
+    }
+
+    #[test]
+    fn test_parse_field_nfc() {
+        assert_eq!(Some("Café".to_string()), parse_field(Some("Cafe\u{301}")));
+    }
+
+    #[test]
+    fn test_has_playlist() {
+        assert!(!has_playlist(""));
+        assert!(!has_playlist(
+            r#"<article class="block--playlist"></article>"#
+        ));
+        assert!(has_playlist(
+            r#"<article class="block--playlist">
+                <div class="playlist-song"></div>
+            </article>"#
+        ));
+    }
+
+    #[test]
+    fn test_inner_html() {
+        let html = Html::parse_fragment(
+            "<p>a &amp; b &lt;c&gt;&nbsp;d \"é\"</p>\
+             <p> x <b>y</b> </p><p></p><p>&#233;&amp;amp;</p>",
+        );
+        let sel = Selector::parse("p").unwrap();
+        for p in html.select(&sel) {
+            assert_eq!(p.inner_html(), inner_html(p));
+        }
+    }
+
+    #[test]
+    fn test_looks_like_time() {
+        let old = |text: &str| {
+            let text = text.trim().to_lowercase().replace(' ', "");
+            text.contains(':') && (text.ends_with("am") || text.ends_with("pm"))
+        };
+        for text in &[
+            "3:04pm",
+            " 3:04 PM ",
+            "12:00 a m",
+            "3:04",
+            "pm",
+            ":am",
+            "3:04pM",
+            "3:04 p\tm",
+            "time",
+            "",
+            "3:04Ｐm",
+            "3:04am\n",
+        ] {
+            assert_eq!(old(text), looks_like_time(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let (entries, strategy) =
+            parse_playlist_with_strategy(HTML, date).unwrap();
+        let t = eastern_noon(date).unwrap();
+
+        assert_eq!(Strategy::PlaylistSong, strategy);
+        assert_eq!(2, entries.len());
+        assert_eq!(Some("Franz Liszt"), entries[0].composer.as_deref());
+        assert_eq!(
+            parse_eastern_time(t, "12:01am").unwrap(),
+            entries[0].start_time
+        );
+        assert_eq!(entries[1].start_time, entries[0].end_time);
+        assert_eq!(1, entries[1].index);
+        assert_eq!(Some("12am"), entries[0].hour.as_deref());
+        assert_eq!(
+            Some("Tasso: Lament &amp; Trimuph (Symphonic Poem No. 2)"),
+            entries[0].raw.title.as_deref()
+        );
+        assert_eq!("12:01am", entries[0].raw.time);
+        assert_eq!(Program::RiseAndShine, entries[1].program);
+        assert_eq!(eastern_eod(t), entries[1].end_time);
+    }
+
+    #[test]
+    fn test_parse_playlist_empty() {
+        let html = r#"<article class="block--playlist"></article>"#;
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        assert_eq!(Vec::<Entry>::new(), parse_playlist(html, date).unwrap());
+        assert_matches!(parse_playlist("", date), Err(_));
+        assert_matches!(
+            parse_playlist("<table><tr></tr></table>", date),
+            Err(_)
+        );
+    }
+
+    #[test]
+    fn test_parse_playlist_table() {
+        let html = r#"
+<table>
+    <tr><th>Time</th><th>Composer</th><th>Title</th></tr>
+    <tr>
+        <td>1:00 PM</td>
+        <td>Antonio Vivaldi</td>
+        <td>Concerto in C, RV 443</td>
+        <td>Academy of St. Martin/Marriner</td>
+        <td>Philips</td>
+    </tr>
+</table>
+"#;
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let (entries, strategy) =
+            parse_playlist_with_strategy(html, date).unwrap();
+
+        assert_eq!(Strategy::Table, strategy);
+        assert_eq!(1, entries.len());
+        assert_eq!(13, entries[0].start_time.hour());
+        assert_eq!(Some("Antonio Vivaldi"), entries[0].composer.as_deref());
+        assert_eq!(Some("Concerto in C, RV 443"), entries[0].title.as_deref());
+        assert_eq!(Some("Philips"), entries[0].record_label.as_deref());
+    }
+
+    #[test]
+    fn test_parse_playlist_warnings() {
+        let html = |time| {
+            format!(
+                "<table><tr><td>{}</td><td>Bach</td><td>Air</td></tr>\
+                 <tr><td>4:00am</td><td>Handel</td><td>Largo</td></tr></table>",
+                time
+            )
+        };
+        let parse = |time, y, m, d| {
+            let date = NaiveDate::from_ymd(y, m, d);
+            let (entries, _, warnings) =
+                parse_playlist_with_warnings(&html(time), date).unwrap();
+            (entries.len(), warnings)
+        };
+
+        assert_eq!((2, vec![]), parse("1:30am", 2020, 9, 1));
+        assert_eq!(
+            (2, vec![Warning::SkippedTime("2:30am".to_string())]),
+            parse("2:30am", 2020, 3, 8)
+        );
+        assert_eq!(
+            (2, vec![Warning::AmbiguousTime("1:30am".to_string())]),
+            parse("1:30am", 2020, 11, 1)
+        );
+        assert_eq!(
+            (1, vec![Warning::BadTime("13:30am".to_string())]),
+            parse("13:30 AM", 2020, 9, 1)
+        );
+    }
+
+    #[test]
+    fn test_parse_playlist_spring_forward() {
+        let html = "<table><tr><td>1:30am</td><td>Bach</td><td>Air</td></tr>\
+                    <tr><td>2:30am</td><td>Handel</td><td>Largo</td></tr></table>";
+        let date = NaiveDate::from_ymd(2020, 3, 8);
+        let entries = parse_playlist(html, date).unwrap();
+
+        let expected = Eastern.ymd(2020, 3, 8).and_hms(3, 30, 0);
+        assert_eq!(expected, entries[1].start_time);
+        assert_eq!(expected, entries[0].end_time);
+    }
+
+    #[test]
+    fn test_parse_playlist_fall_back_order() {
+        let html = "<table><tr><td>1:30am</td><td>A</td><td>A</td></tr>\
+                    <tr><td>1:50am</td><td>B</td><td>B</td></tr>\
+                    <tr><td>1:10am</td><td>C</td><td>C</td></tr>\
+                    <tr><td>1:20am</td><td>D</td><td>D</td></tr></table>";
+        let date = NaiveDate::from_ymd(2020, 11, 1);
+        let (entries, _, warnings) =
+            parse_playlist_with_warnings(html, date).unwrap();
+
+        let offsets: Vec<_> = entries
+            .iter()
+            .map(|e| e.start_time.fixed_offset().offset().local_minus_utc())
+            .collect();
+        let edt = -4 * 3600;
+        let est = -5 * 3600;
+        assert_eq!(vec![edt, edt, est, est], offsets);
+        // The first entry has nothing to go by.
+        assert_eq!(
+            vec![Warning::AmbiguousTime("1:30am".to_string())],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_parse_new_entries() {
+        let row = |time, title| {
+            format!("<tr><td>{}</td><td>X</td><td>{}</td></tr>", time, title)
+        };
+        let before = format!(
+            "<table>{}{}</table>",
+            row("1:00am", "A"),
+            row("2:00am", "B")
+        );
+        let after = format!(
+            "<table>{}{}{}{}</table>",
+            row("1:00am", "A"),
+            row("2:00am", "B"),
+            row("3:00am", "C"),
+            row("3:75am", "D"),
+        );
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let known = parse_playlist(&before, date).unwrap();
+
+        let (entries, warnings) =
+            parse_new_entries(&after, date, &known).unwrap().unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(2, entries[0].index);
+        assert_eq!(Some("C".to_string()), entries[0].title);
+        assert_eq!(vec![Warning::BadTime("3:75am".to_string())], warnings);
+        assert_eq!(parse_playlist(&after, date).unwrap()[2..], entries[..]);
+
+        let changed = after.replace("2:00am", "2:05am");
+        assert_eq!(None, parse_new_entries(&changed, date, &known).unwrap());
+        assert_eq!(None, parse_new_entries(&before, date, &entries).unwrap());
+    }
+
+    #[test]
+    fn test_parse_playlist_fall_back_headings() {
+        let song = |time| {
+            format!(
+                r#"<div class="playlist-song">
+                    <div class="playlist-song__time">{}</div>
+                    <h4 class="playlist-song__title">Title</h4>
+                </div>"#,
+                time
+            )
+        };
+        // The 1:20am entry would fit after 1:10am in the first hour, so only
+        // the repeated heading shows it is in the second.
+        let html = format!(
+            r#"<article class="block block--playlist">
+                <h3 class="playlist-hour">12am</h3>{}
+                <h3 class="playlist-hour">1am</h3>{}
+                <h3 class="playlist-hour">1am</h3>{}{}
+            </article>"#,
+            song("12:30am"),
+            song("1:10am"),
+            song("1:20am"),
+            song("1:45am"),
+        );
+        let date = NaiveDate::from_ymd(2020, 11, 1);
+        let (entries, _, warnings) =
+            parse_playlist_with_warnings(&html, date).unwrap();
+
+        let utc: Vec<_> =
+            entries.iter().map(|e| e.start_time.naive_utc()).collect();
+        let utc_hms = |h, m| NaiveDate::from_ymd(2020, 11, 1).and_hms(h, m, 0);
+        assert_eq!(
+            vec![
+                utc_hms(4, 30),
+                utc_hms(5, 10),
+                utc_hms(6, 20),
+                utc_hms(6, 45)
+            ],
+            utc
+        );
+        assert_eq!(Vec::<Warning>::new(), warnings);
+    }
+
+    #[test]
+    fn test_parse_playlist_generic() {
+        let html = r#"
+<section class="new-playlist">
+    <div class="track">
+        <span class="track-time">2:30pm</span>
+        <h3 class="track-title">Symphony No. 2 in D, Op. 73</h3>
+        <p>Composed by: Johannes Brahms</p>
+    </div>
+</section>
+"#;
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let (entries, strategy) =
+            parse_playlist_with_strategy(html, date).unwrap();
+
+        assert_eq!(Strategy::Generic, strategy);
+        assert_eq!(1, entries.len());
+        assert_eq!(
+            Eastern.ymd(2020, 9, 1).and_hms(14, 30, 0),
+            entries[0].start_time
+        );
+        assert_eq!(Some("Johannes Brahms"), entries[0].composer.as_deref());
+        assert_eq!(
+            Some("Symphony No. 2 in D, Op. 73"),
+            entries[0].title.as_deref()
+        );
+        assert_eq!(None, entries[0].performers);
+    }
+
+    #[test]
+    fn test_parse_playlist_no_panic() {
+        let date = NaiveDate::from_ymd(2020, 11, 1);
+        let times = [
+            "",
+            ":",
+            "1:",
+            "1:00",
+            "13:00pm",
+            "4294967295:00pm",
+            "1:aéb",
+            "12:60am",
+            "١:٠٠am",
+            "1:00 p.m.",
+            "&#0;",
+            "1:30am",
+        ];
+        for time in &times {
+            let layouts = [
+                format!(
+                    r#"<article class="block--playlist"><div class="playlist-song"><div class="playlist-song__time">{}</div></div></article>"#,
+                    time
+                ),
+                format!(
+                    "<table><tr><td>{}</td><td></td><td></td></tr></table>",
+                    time
+                ),
+                format!(r#"<div><span class="time">{}</span></div>"#, time),
+            ];
+            for html in &layouts {
+                let _ = parse_playlist(html, date);
+            }
+        }
+
+        // A simple xorshift generator for deterministic garbage.
+        let alphabet: Vec<char> =
+            "<>/=\"'&#;:amp01239 divclasé\u{0}".chars().collect();
+        let mut state = 0x2545_f491_u32;
+        for _ in 0..500 {
+            let html: String = (0..64)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    alphabet[state as usize % alphabet.len()]
+                })
+                .collect();
+            let _ = parse_playlist(&html, date);
+        }
+    }
+}
@@ -0,0 +1,406 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! A day's playlist, parsed once and queried many times.
+
+use {
+    crate::{
+        get_program, program_bounds, Entry, Error, Request, Response, Result,
+        StationTime, Warning,
+    },
+    chrono::{DateTime, NaiveDate, TimeZone, Utc},
+    std::{slice, vec},
+};
+
+/// The parsed playlist for one day.
+///
+/// Parsing the HTML is by far the most expensive part of a lookup. Callers
+/// that ask about several times on the same day, such as widgets that refresh
+/// every few seconds, should parse the page into a `Playlist` once and call
+/// `lookup` on it for each time.
+///
+/// The entries are sorted by start time. Use `at`, `next_after`, and `between`
+/// to find entries by time with a binary search, or iterate over the playlist
+/// to visit every entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Playlist {
+    date: NaiveDate,
+    entries: Vec<Entry>,
+    warnings: Vec<Warning>,
+    source_url: Option<String>,
+    fetched_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Playlist {
+    /// Parses the HTML of the playlist page for `date`. See `parse_playlist`.
+    #[cfg(feature = "html")]
+    pub fn parse(html: &str, date: NaiveDate) -> Result<Self> {
+        let (entries, _, warnings) =
+            crate::parse::parse_playlist_with_warnings(html, date)?;
+        Ok(Playlist {
+            date,
+            entries,
+            warnings,
+            source_url: None,
+            fetched_at: None,
+            expires_at: None,
+        })
+    }
+
+    /// Updates the playlist from a newer copy of the same page.
+    ///
+    /// The page only grows during the day, so this parses just the entries
+    /// after the ones already in the playlist, and appends them. If the page
+    /// no longer starts with the known entries, it is parsed from scratch.
+    /// Returns the number of entries added.
+    #[cfg(feature = "html")]
+    pub fn refresh(&mut self, html: &str) -> Result<usize> {
+        match crate::parse::parse_new_entries(html, self.date, &self.entries)? {
+            Some((entries, warnings)) => {
+                if let (Some(last), Some(first)) =
+                    (self.entries.last_mut(), entries.first())
+                {
+                    last.end_time = first.start_time;
+                }
+                let added = entries.len();
+                self.entries.extend(entries);
+                self.warnings.extend(warnings);
+                Ok(added)
+            }
+            None => {
+                let (entries, _, warnings) =
+                    crate::parse::parse_playlist_with_warnings(
+                        html, self.date,
+                    )?;
+                let added = entries.len().saturating_sub(self.entries.len());
+                self.entries = entries;
+                self.warnings = warnings;
+                Ok(added)
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn new(
+        date: NaiveDate,
+        entries: Vec<Entry>,
+        warnings: Vec<Warning>,
+    ) -> Self {
+        Playlist {
+            date,
+            entries,
+            warnings,
+            source_url: None,
+            fetched_at: None,
+            expires_at: None,
+        }
+    }
+
+    /// Records where and when the playlist's page was downloaded.
+    #[doc(hidden)]
+    pub fn with_source(self, url: String, fetched_at: DateTime<Utc>) -> Self {
+        Playlist {
+            source_url: Some(url),
+            fetched_at: Some(fetched_at),
+            ..self
+        }
+    }
+
+    /// Records when the playlist's page stops being fresh.
+    #[doc(hidden)]
+    pub fn with_expiry(self, expires_at: Option<DateTime<Utc>>) -> Self {
+        Playlist { expires_at, ..self }
+    }
+
+    /// Returns the date of the playlist, in the station's time zone.
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// Returns the entries in the order they were played.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the playlist has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the entries in the order they were played.
+    pub fn iter(&self) -> slice::Iter<'_, Entry> {
+        self.entries.iter()
+    }
+
+    /// Returns the entry playing at `time`, or `None` if `time` is before the
+    /// first entry or after the end of the day. Like `Response::contains`, an
+    /// entry's start time is inclusive and its end time is exclusive.
+    pub fn at(&self, time: impl Into<StationTime>) -> Option<&Entry> {
+        let time = time.into();
+        entry_at(&self.entries, time).filter(|entry| time < entry.end_time)
+    }
+
+    /// Returns the first entry that starts after `time`, or `None` if there is
+    /// none yet.
+    pub fn next_after(&self, time: impl Into<StationTime>) -> Option<&Entry> {
+        let time = time.into();
+        let index = self.entries.partition_point(|e| e.start_time <= time);
+        self.entries.get(index)
+    }
+
+    /// Returns the entries that played before the one playing at `time`, in
+    /// the order they were played. This is empty if `time` is before the
+    /// second entry.
+    pub fn before(&self, time: impl Into<StationTime>) -> &[Entry] {
+        let time = time.into();
+        let index = self.entries.partition_point(|e| e.start_time <= time);
+        &self.entries[..index.saturating_sub(1)]
+    }
+
+    /// Returns the entries listed to start after `time`, in the order they will
+    /// be played. This is usually empty, but the page sometimes lists upcoming
+    /// pieces ahead of time.
+    pub fn after(&self, time: impl Into<StationTime>) -> &[Entry] {
+        let time = time.into();
+        let index = self.entries.partition_point(|e| e.start_time <= time);
+        &self.entries[index..]
+    }
+
+    /// Returns the entries playing at any time from `start` (inclusive) to
+    /// `end` (exclusive), in the order they were played.
+    pub fn between(
+        &self,
+        start: impl Into<StationTime>,
+        end: impl Into<StationTime>,
+    ) -> &[Entry] {
+        let (start, end) = (start.into(), end.into());
+        let lo = self.entries.partition_point(|e| e.end_time <= start);
+        let hi = self.entries.partition_point(|e| e.start_time < end);
+        self.entries.get(lo..hi).unwrap_or_default()
+    }
+
+    /// Returns warnings about entries that were skipped while parsing.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the URL the playlist was downloaded from, or `None` if it was
+    /// parsed from HTML that the caller provided.
+    pub fn source_url(&self) -> Option<&str> {
+        self.source_url.as_deref()
+    }
+
+    /// Returns when the playlist was downloaded, or `None` if it was parsed
+    /// from HTML that the caller provided. For a playlist read from the cache,
+    /// this is when it was originally downloaded.
+    pub fn fetched_at(&self) -> Option<DateTime<Utc>> {
+        self.fetched_at
+    }
+
+    /// Returns when the downloaded page stops being fresh according to the
+    /// server's `Cache-Control` or `Expires` headers, or `None` if it sent
+    /// neither or the playlist was parsed from HTML that the caller provided.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    /// Looks up what is playing at `request.time`. Returns `Error::NoData` if
+    /// the time is on a different day, and `Error::NoEntry` if it is before the
+    /// first entry.
+    pub fn lookup<Tz: TimeZone>(
+        &self,
+        request: &Request<Tz>,
+    ) -> Result<Response<Tz>> {
+        let time = request.station_time();
+        if time.date() != self.date {
+            return Err(Error::NoData);
+        }
+        let entry = entry_at(&self.entries, time).ok_or(Error::NoEntry)?;
+        let tz = request.time.timezone();
+        let (program_start, program_end) = program_bounds(request.time.clone());
+        Ok(Response {
+            program: get_program(request.time.clone()),
+            start_time: entry.start_time.with_timezone(&tz),
+            end_time: entry.end_time.with_timezone(&tz),
+            program_start,
+            program_end,
+            composer: entry.composer.clone(),
+            title: entry.title.clone(),
+            performers: entry.performers.clone(),
+            record_label: entry.record_label.clone(),
+            source_url: self.source_url.clone(),
+            fetched_at: self.fetched_at,
+            warnings: self.warnings.clone(),
+        })
+    }
+}
+
+impl IntoIterator for Playlist {
+    type Item = Entry;
+    type IntoIter = vec::IntoIter<Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Playlist {
+    type Item = &'a Entry;
+    type IntoIter = slice::Iter<'a, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// Returns the entry playing at `time`, given entries in the order they were
+/// played. This is a binary search, so it stays cheap when answering many
+/// queries against the same day's entries.
+fn entry_at(entries: &[Entry], time: StationTime) -> Option<&Entry> {
+    let index = entries.partition_point(|entry| entry.start_time <= time);
+    index.checked_sub(1).map(|index| &entries[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::parse_playlist, crate::tests::HTML, crate::Eastern,
+        assert_matches::assert_matches,
+    };
+
+    #[test]
+    fn test_entry_at() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let entries = parse_playlist(HTML, date).unwrap();
+        let at = |h, m| {
+            entry_at(&entries, Eastern.ymd(2020, 9, 1).and_hms(h, m, 0).into())
+        };
+
+        assert_eq!(None, at(0, 0));
+        assert_eq!(Some(&entries[0]), at(0, 1));
+        assert_eq!(Some(&entries[0]), at(0, 5));
+        assert_eq!(Some(&entries[1]), at(23, 59));
+    }
+
+    #[test]
+    fn test_at_and_next_after() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let entries = playlist.entries();
+        let at = |d, h, m| Eastern.ymd(2020, 9, d).and_hms(h, m, 0);
+
+        assert_eq!(None, playlist.at(at(1, 0, 0)));
+        assert_eq!(Some(&entries[0]), playlist.at(at(1, 0, 1)));
+        assert_eq!(Some(&entries[1]), playlist.at(at(1, 6, 0)));
+        assert_eq!(None, playlist.at(at(2, 0, 0)));
+        assert_eq!(
+            Some(&entries[1]),
+            playlist.at(at(1, 7, 0).with_timezone(&Utc))
+        );
+
+        assert_eq!(Some(&entries[0]), playlist.next_after(at(1, 0, 0)));
+        assert_eq!(Some(&entries[1]), playlist.next_after(at(1, 0, 1)));
+        assert_eq!(None, playlist.next_after(at(1, 6, 0)));
+    }
+
+    #[test]
+    fn test_between() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let entries = playlist.entries();
+        let at = |h, m| Eastern.ymd(2020, 9, 1).and_hms(h, m, 0);
+
+        assert_eq!(entries, playlist.between(at(0, 0), at(23, 0)));
+        assert_eq!(&entries[..1], playlist.between(at(0, 0), at(6, 0)));
+        assert_eq!(&entries[1..], playlist.between(at(6, 0), at(7, 0)));
+        assert!(playlist.between(at(0, 0), at(0, 1)).is_empty());
+        assert!(playlist.between(at(7, 0), at(6, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_before() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let entries = playlist.entries();
+        let at = |h, m| Eastern.ymd(2020, 9, 1).and_hms(h, m, 0);
+
+        assert!(playlist.before(at(0, 0)).is_empty());
+        assert!(playlist.before(at(3, 0)).is_empty());
+        assert_eq!(&entries[..1], playlist.before(at(6, 0)));
+        assert_eq!(&entries[..1], playlist.before(at(23, 0)));
+    }
+
+    #[test]
+    fn test_after() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let entries = playlist.entries();
+        let at = |h, m| Eastern.ymd(2020, 9, 1).and_hms(h, m, 0);
+
+        assert_eq!(entries, playlist.after(at(0, 0)));
+        assert_eq!(&entries[1..], playlist.after(at(3, 0)));
+        assert!(playlist.after(at(6, 0)).is_empty());
+        assert!(playlist.after(at(23, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let indices: Vec<_> =
+            (&playlist).into_iter().map(|e| e.index).collect();
+        assert_eq!(vec![0, 1], indices);
+        assert_eq!(
+            playlist.entries().to_vec(),
+            playlist.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let request = |d, h| Request {
+            time: Eastern.ymd(2020, 9, d).and_hms(h, 0, 0),
+        };
+
+        assert_eq!(2, playlist.entries().len());
+        assert_eq!(
+            Some("Franz Liszt"),
+            playlist.lookup(&request(1, 1)).unwrap().composer.as_deref()
+        );
+        assert_eq!(
+            playlist.entries()[1].title,
+            playlist.lookup(&request(1, 12)).unwrap().title
+        );
+        assert_matches!(playlist.lookup(&request(1, 0)), Err(Error::NoEntry));
+        assert_matches!(playlist.lookup(&request(2, 1)), Err(Error::NoData));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let row = |time, title| {
+            format!("<tr><td>{}</td><td>X</td><td>{}</td></tr>", time, title)
+        };
+        let mut html = format!("<table>{}", row("1:00am", "A"));
+        let mut playlist = Playlist::parse(&html, date).unwrap();
+
+        html.push_str(&row("2:00am", "B"));
+        html.push_str(&row("3:00am", "C"));
+        assert_eq!(2, playlist.refresh(&html).unwrap());
+        assert_eq!(Playlist::parse(&html, date).unwrap(), playlist);
+        assert_eq!(0, playlist.refresh(&html).unwrap());
+
+        // A page that no longer matches is parsed from scratch.
+        let html =
+            format!("<table>{}{}", row("1:30am", "A"), row("2:00am", "B"));
+        assert_eq!(0, playlist.refresh(&html).unwrap());
+        assert_eq!(Playlist::parse(&html, date).unwrap(), playlist);
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Programs that air on WCPE.
+
+use {
+    once_cell::sync::Lazy,
+    serde::Deserialize,
+    std::{collections::HashMap, fmt},
+};
+
+/// A program on WCPE.
+///
+/// Most variants are the station's regular and specialty programs. Anything
+/// else, such as holiday programming named in a schedule file, is a
+/// `Special` program carrying its own name.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Program {
+    Allegro,
+    AsYouLikeIt,
+    ClassicalCafe,
+    ConcertHall,
+    GreatSacredMusic,
+    MetropolitanOpera,
+    MondayNightAtTheSymphony,
+    MusicInTheNight,
+    MyLifeInMusic,
+    PeacefulReflections,
+    Preview,
+    RenaissanceFare,
+    RiseAndShine,
+    SaturdayEveningRequestProgram,
+    SingForJoy,
+    SleepersAwake,
+    ThursdayNightOperaHouse,
+    Wavelengths,
+    WeekendClassics,
+    /// A program not otherwise listed, e.g., holiday programming.
+    Special(String),
+}
+
+const NAMED: &[Program] = &[
+    Program::Allegro,
+    Program::AsYouLikeIt,
+    Program::ClassicalCafe,
+    Program::ConcertHall,
+    Program::GreatSacredMusic,
+    Program::MetropolitanOpera,
+    Program::MondayNightAtTheSymphony,
+    Program::MusicInTheNight,
+    Program::MyLifeInMusic,
+    Program::PeacefulReflections,
+    Program::Preview,
+    Program::RenaissanceFare,
+    Program::RiseAndShine,
+    Program::SaturdayEveningRequestProgram,
+    Program::SingForJoy,
+    Program::SleepersAwake,
+    Program::ThursdayNightOperaHouse,
+    Program::Wavelengths,
+    Program::WeekendClassics,
+];
+
+/// Details about a program, returned by `Program::info`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramInfo {
+    /// A sentence describing the program.
+    pub description: String,
+    /// The program's own web page, if it has one.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// The program's regular hosts. This is empty for programs that the
+    /// station's announcers take turns hosting.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+static INFO: Lazy<HashMap<String, ProgramInfo>> = Lazy::new(|| {
+    // Generated by build.rs from programs.toml.
+    let info: Vec<(&str, ProgramInfo)> =
+        include!(concat!(env!("OUT_DIR"), "/programs.rs"));
+    info.into_iter()
+        .map(|(name, info)| (name.to_owned(), info))
+        .collect()
+});
+
+impl Program {
+    /// Returns the program with the given name. Names that don't match one of
+    /// the station's programs produce a `Special` program.
+    pub fn from_name(name: &str) -> Self {
+        NAMED
+            .iter()
+            .find(|program| program.name() == name)
+            .cloned()
+            .unwrap_or_else(|| Program::Special(name.to_owned()))
+    }
+
+    /// Returns the name of the program, e.g., "Sleepers, Awake!".
+    pub fn name(&self) -> &str {
+        match self {
+            Program::Allegro => "Allegro",
+            Program::AsYouLikeIt => "As You Like It",
+            Program::ClassicalCafe => "Classical Café",
+            Program::ConcertHall => "Concert Hall",
+            Program::GreatSacredMusic => "Great Sacred Music",
+            Program::MetropolitanOpera => "Metropolitan Opera",
+            Program::MondayNightAtTheSymphony => "Monday Night at the Symphony",
+            Program::MusicInTheNight => "Music in the Night",
+            Program::MyLifeInMusic => "My Life in Music",
+            Program::PeacefulReflections => "Peaceful Reflections",
+            Program::Preview => "Preview!",
+            Program::RenaissanceFare => "Renaissance Fare",
+            Program::RiseAndShine => "Rise and Shine",
+            Program::SaturdayEveningRequestProgram => {
+                "Saturday Evening Request Program"
+            }
+            Program::SingForJoy => "Sing for Joy",
+            Program::SleepersAwake => "Sleepers, Awake!",
+            Program::ThursdayNightOperaHouse => "Thursday Night Opera House",
+            Program::Wavelengths => "Wavelengths",
+            Program::WeekendClassics => "Weekend Classics",
+            Program::Special(name) => name,
+        }
+    }
+
+    /// Returns true if the program is part of the regular weekly lineup, as
+    /// opposed to a specialty program that preempts it or a special broadcast.
+    pub fn is_regular(&self) -> bool {
+        matches!(
+            self,
+            Program::Allegro
+                | Program::AsYouLikeIt
+                | Program::ClassicalCafe
+                | Program::ConcertHall
+                | Program::MusicInTheNight
+                | Program::RiseAndShine
+                | Program::SaturdayEveningRequestProgram
+                | Program::SleepersAwake
+                | Program::WeekendClassics
+        )
+    }
+
+    /// Returns details about the program for richer displays, or `None` for
+    /// `Special` programs.
+    pub fn info(&self) -> Option<&'static ProgramInfo> {
+        match self {
+            Program::Special(_) => None,
+            _ => INFO.get(self.name()),
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        for program in NAMED {
+            assert_eq!(*program, Program::from_name(program.name()));
+        }
+        assert_eq!(
+            Program::Special("Christmas Day".to_owned()),
+            Program::from_name("Christmas Day")
+        );
+    }
+
+    #[test]
+    fn test_is_regular() {
+        use {crate::regular_program, chrono::Weekday};
+        for weekday in [Weekday::Mon, Weekday::Sat] {
+            for hour in 0..24 {
+                assert!(regular_program(weekday, hour).is_regular());
+            }
+        }
+        assert!(Program::SleepersAwake.is_regular());
+        assert!(Program::SaturdayEveningRequestProgram.is_regular());
+        assert!(!Program::ThursdayNightOperaHouse.is_regular());
+        assert!(!Program::MetropolitanOpera.is_regular());
+        assert!(!Program::Special("Christmas Eve".to_string()).is_regular());
+    }
+
+    #[test]
+    fn test_info() {
+        for program in NAMED {
+            let info = program.info().unwrap();
+            if let Some(url) = &info.url {
+                assert!(url.starts_with("https://"), "{}", program);
+            }
+        }
+        assert_eq!(NAMED.len(), INFO.len());
+        assert_eq!(
+            Some("https://www.metopera.org/season/radio/"),
+            Program::MetropolitanOpera.info().unwrap().url.as_deref()
+        );
+        assert_eq!(None, Program::Allegro.info().unwrap().url);
+        assert_eq!(
+            vec!["Debra Lew Harder"],
+            Program::MetropolitanOpera.info().unwrap().hosts
+        );
+        assert_eq!(None, Program::Special("Allegro".to_owned()).info());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_info_matches_toml() {
+        let info: HashMap<String, ProgramInfo> =
+            toml::from_str(include_str!("programs.toml")).unwrap();
+        assert_eq!(*INFO, info);
+    }
+
+    #[test]
+    fn test_names_nfc() {
+        use unicode_normalization::is_nfc;
+
+        for program in NAMED {
+            assert!(is_nfc(program.name()), "{}", program);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Describing when a piece plays relative to now, the way people say it.
+
+use chrono::{DateTime, Duration, TimeZone};
+
+/// Describes the piece from `start` to `end` relative to `now`, e.g., "started
+/// 12 minutes ago · ends in 8 minutes". Past pieces say "ended 1 hour ago" and
+/// future ones "starts in 5 minutes". Elapsed minutes are rounded down and
+/// remaining minutes up, like the `{elapsed}` and `{remaining}` placeholders
+/// of a `Template`.
+pub fn relative_time<Tz: TimeZone>(
+    start: &DateTime<Tz>,
+    end: &DateTime<Tz>,
+    now: &DateTime<Tz>,
+) -> String {
+    let describe = |time: &DateTime<Tz>, past: &str, future: &str| {
+        let seconds = (time.clone() - now.clone()).num_seconds();
+        if seconds > 0 {
+            let minutes = (seconds + 59) / 60;
+            format!("{} in {}", future, span(Duration::minutes(minutes)))
+        } else if seconds > -60 {
+            format!("{} just now", past)
+        } else {
+            format!("{} {} ago", past, span(Duration::seconds(-seconds)))
+        }
+    };
+    format!(
+        "{} · {}",
+        describe(start, "started", "starts"),
+        describe(end, "ended", "ends")
+    )
+}
+
+/// Formats `duration` in whole minutes, e.g., "1 hour 5 minutes".
+fn span(duration: Duration) -> String {
+    let plural = |n: i64, unit: &str| {
+        format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+    };
+    let (hours, minutes) = (duration.num_hours(), duration.num_minutes() % 60);
+    match (hours, minutes) {
+        (0, _) => plural(minutes, "minute"),
+        (_, 0) => plural(hours, "hour"),
+        _ => format!("{} {}", plural(hours, "hour"), plural(minutes, "minute")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Utc};
+
+    fn relative(now: (u32, u32, u32)) -> String {
+        let start = Utc.ymd(2020, 9, 1).and_hms(20, 0, 0);
+        let end = Utc.ymd(2020, 9, 1).and_hms(20, 20, 0);
+        let now = Utc.ymd(2020, 9, 1).and_hms(now.0, now.1, now.2);
+        relative_time(&start, &end, &now)
+    }
+
+    #[test]
+    fn test_playing() {
+        assert_eq!(
+            "started 12 minutes ago · ends in 8 minutes",
+            relative((20, 12, 0))
+        );
+        assert_eq!(
+            "started just now · ends in 20 minutes",
+            relative((20, 0, 30))
+        );
+        assert_eq!(
+            "started 19 minutes ago · ends in 1 minute",
+            relative((20, 19, 30))
+        );
+    }
+
+    #[test]
+    fn test_past_and_future() {
+        assert_eq!(
+            "starts in 1 hour · ends in 1 hour 20 minutes",
+            relative((19, 0, 0))
+        );
+        assert_eq!(
+            "started 2 hours 5 minutes ago · ended 1 hour 45 minutes ago",
+            relative((22, 5, 0))
+        );
+    }
+}
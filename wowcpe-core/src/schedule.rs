@@ -0,0 +1,118 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Queries about when programs air.
+
+use {
+    crate::{get_program, program_bounds, Eastern, Program},
+    chrono::{DateTime, Duration, TimeZone, Timelike},
+};
+
+/// Programs start and end on the hour or half hour.
+const STEP_MINUTES: i64 = 30;
+
+/// How far ahead to search. This covers programs that only air once a year,
+/// like a holiday, or for part of the year, like the Metropolitan Opera.
+const MAX_DAYS: i64 = 366;
+
+/// Returns the start and end of the next time `program` airs, starting at or
+/// after `after`. If the program is on the air at `after`, this skips to the
+/// airing after that one. Returns `None` if it does not air within a year.
+///
+/// This uses the same schedule as `Response::program`, so it accounts for the
+/// specialty programs that only air on a particular week of the month, the
+/// Metropolitan Opera season, and holidays.
+pub fn next_occurrence<Tz: TimeZone>(
+    program: &Program,
+    after: &DateTime<Tz>,
+) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+    let step = Duration::minutes(STEP_MINUTES);
+    let mut time = next_step(after.clone());
+    let mut previous = get_program(time.clone() - step);
+    for _ in 0..MAX_DAYS * 24 * 60 / STEP_MINUTES {
+        let current = get_program(time.clone());
+        if current == *program && previous != *program {
+            return Some(program_bounds(time));
+        }
+        previous = current;
+        time += step;
+    }
+    None
+}
+
+/// Rounds `time` up to the next hour or half hour in the station's time zone.
+fn next_step<Tz: TimeZone>(time: DateTime<Tz>) -> DateTime<Tz> {
+    let tz = time.timezone();
+    let eastern = time.with_timezone(&Eastern);
+    let truncated = eastern
+        .with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(eastern);
+    let minute = i64::from(truncated.minute());
+    let rounded = truncated - Duration::minutes(minute % STEP_MINUTES);
+    let rounded = if rounded == eastern {
+        rounded
+    } else {
+        rounded + Duration::minutes(STEP_MINUTES)
+    };
+    rounded.with_timezone(&tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::FixedOffset};
+
+    #[test]
+    fn test_next_occurrence() {
+        let at = |d, h, m| Eastern.ymd(2020, 9, d).and_hms(h, m, 0);
+        let next = |program, after| next_occurrence(&program, &after).unwrap();
+
+        assert_eq!(
+            (at(10, 19, 0), at(10, 22, 0)),
+            next(Program::ThursdayNightOperaHouse, at(7, 12, 0))
+        );
+        // Already on the air, so wait for next week.
+        assert_eq!(
+            (at(17, 19, 0), at(17, 22, 0)),
+            next(Program::ThursdayNightOperaHouse, at(10, 20, 0))
+        );
+        // Exactly at the start counts.
+        assert_eq!(
+            (at(10, 19, 0), at(10, 22, 0)),
+            next(Program::ThursdayNightOperaHouse, at(10, 19, 0))
+        );
+        // Second Monday of the month, then the third Sunday.
+        assert_eq!(
+            (at(14, 19, 0), at(14, 20, 0)),
+            next(Program::RenaissanceFare, at(1, 0, 0))
+        );
+        assert_eq!(
+            (at(20, 17, 0), at(20, 18, 0)),
+            next(Program::RenaissanceFare, at(14, 19, 1))
+        );
+    }
+
+    #[test]
+    fn test_next_occurrence_holiday() {
+        let after = Eastern.ymd(2020, 9, 1).and_hms(0, 0, 0);
+        let christmas = Program::Special("Christmas Day".to_owned());
+        let (start, end) = next_occurrence(&christmas, &after).unwrap();
+        assert_eq!(Eastern.ymd(2020, 12, 25).and_hms(0, 0, 0), start);
+        assert_eq!(Eastern.ymd(2020, 12, 26).and_hms(0, 0, 0), end);
+
+        let never = Program::Special("Never".to_owned());
+        assert_eq!(None, next_occurrence(&never, &after));
+    }
+
+    #[test]
+    fn test_next_step() {
+        let at = |h, m, s| Eastern.ymd(2020, 9, 1).and_hms(h, m, s);
+        assert_eq!(at(3, 0, 0), next_step(at(3, 0, 0)));
+        assert_eq!(at(3, 30, 0), next_step(at(3, 0, 1)));
+        assert_eq!(at(3, 30, 0), next_step(at(3, 29, 0)));
+        assert_eq!(at(4, 0, 0), next_step(at(3, 45, 0)));
+        // Half hours are in Eastern time, even in a time zone offset by 45
+        // minutes.
+        let nepal = FixedOffset::east(5 * 3600 + 45 * 60);
+        assert_eq!(at(4, 0, 0), next_step(at(3, 45, 0).with_timezone(&nepal)));
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! An abstraction over the ways of finding out what is playing.
+
+use crate::{Playlist, Request, Response, Result, StationTime};
+
+/// A source of information about what is playing on WCPE.
+///
+/// The playlist scraper is one source: `Playlist` answers for the day it was
+/// parsed from, and `wowcpe::Client` downloads whichever day it needs. Code
+/// written against this trait works with any of them, and with sources added
+/// later such as stream metadata. Use [`fallback`] to try sources in order.
+///
+/// [`fallback`]: #method.fallback
+pub trait NowPlayingSource {
+    /// Returns what is playing at `time`.
+    fn current(&self, time: StationTime) -> Result<Response>;
+
+    /// Returns a source that tries `self` first and, if it fails, `other`.
+    fn fallback<S: NowPlayingSource>(self, other: S) -> Fallback<Self, S>
+    where
+        Self: Sized,
+    {
+        Fallback {
+            primary: self,
+            secondary: other,
+        }
+    }
+}
+
+impl<S: NowPlayingSource + ?Sized> NowPlayingSource for &S {
+    fn current(&self, time: StationTime) -> Result<Response> {
+        (**self).current(time)
+    }
+}
+
+impl<S: NowPlayingSource + ?Sized> NowPlayingSource for Box<S> {
+    fn current(&self, time: StationTime) -> Result<Response> {
+        (**self).current(time)
+    }
+}
+
+impl NowPlayingSource for Playlist {
+    fn current(&self, time: StationTime) -> Result<Response> {
+        self.lookup(&Request {
+            time: time.to_local(),
+        })
+    }
+}
+
+/// A source that tries one source and falls back to another. Created by
+/// `NowPlayingSource::fallback`.
+#[derive(Clone, Debug)]
+pub struct Fallback<A, B> {
+    primary: A,
+    secondary: B,
+}
+
+impl<A: NowPlayingSource, B: NowPlayingSource> NowPlayingSource
+    for Fallback<A, B>
+{
+    fn current(&self, time: StationTime) -> Result<Response> {
+        self.primary
+            .current(time)
+            .or_else(|_| self.secondary.current(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{tests::HTML, Eastern, Error},
+        assert_matches::assert_matches,
+        chrono::{NaiveDate, TimeZone},
+    };
+
+    #[test]
+    fn test_playlist_source() {
+        let date = NaiveDate::from_ymd(2020, 9, 1);
+        let playlist = Playlist::parse(HTML, date).unwrap();
+        let time = Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0).into();
+        let response = playlist.current(time).unwrap();
+        assert_eq!(Some("Franz Liszt"), response.composer.as_deref());
+
+        let tomorrow = time + chrono::Duration::days(1);
+        assert_matches!(playlist.current(tomorrow), Err(Error::NoData));
+    }
+
+    #[test]
+    fn test_fallback() {
+        let today = Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1));
+        let today = today.unwrap();
+        let yesterday =
+            Playlist::new(NaiveDate::from_ymd(2020, 8, 31), vec![], vec![]);
+        let time = Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0).into();
+
+        let source = (&yesterday).fallback(&today);
+        assert_eq!(today.current(time).unwrap(), source.current(time).unwrap());
+
+        // Sources can be boxed to choose them at runtime.
+        let source: Box<dyn NowPlayingSource> =
+            Box::new(Box::new(yesterday).fallback(Box::new(today)));
+        assert!(source.current(time).is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Schedule of specialty and holiday programs that preempt the regular weekly
+//! lineup.
+
+use {
+    crate::{Eastern, Program},
+    chrono::{DateTime, Datelike, NaiveDate, TimeZone, Timelike, Weekday},
+    once_cell::sync::Lazy,
+    serde::Deserialize,
+    std::{io, sync::Mutex},
+};
+
+/// A schedule of specialty programs.
+///
+/// Specialty programs air in fixed weekly slots, some only on a particular
+/// week of the month. The crate embeds a schedule that was accurate when it
+/// was released (see `SpecialtySchedule::builtin`), but the station moves
+/// programs around from time to time. To correct for that without waiting for
+/// a new release, load an updated schedule and pass it to
+/// `set_specialty_schedule`.
+///
+/// Schedules are written in TOML like this:
+///
+/// ```toml
+/// [[rule]]
+/// program = "My Life in Music"
+/// weekday = "Mon"
+/// start = "19:00"
+/// end = "20:00"
+/// days = [1, 7]
+/// ```
+///
+/// Times are Eastern, and `end` is exclusive (use `"24:00"` for midnight). The
+/// optional `days` restricts the rule to an inclusive range of days of the
+/// month. When several rules match, the first one wins.
+///
+/// Around Christmas and other holidays the station replaces its usual lineup.
+/// Holidays override the weekly rules and are written like this:
+///
+/// ```toml
+/// [[holiday]]
+/// program = "Christmas Eve Lessons and Carols"
+/// date = "12-24"
+/// start = "15:00"
+/// end = "17:00"
+/// ```
+///
+/// A `date` of the form `MM-DD` applies every year, while `YYYY-MM-DD` applies
+/// only once, for holidays like Thanksgiving that move around. Omitting
+/// `start` and `end` covers the whole day. Programs that aren't among the
+/// station's regular ones become `Program::Special`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpecialtySchedule {
+    holidays: Vec<Holiday>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Holiday {
+    program: Program,
+    year: Option<i32>,
+    month: u32,
+    day: u32,
+    start: u32,
+    end: u32,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Rule {
+    program: Program,
+    weekday: Weekday,
+    start: u32,
+    end: u32,
+    days: Option<(u32, u32)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleFile {
+    #[serde(default)]
+    holiday: Vec<HolidayFile>,
+    #[serde(default)]
+    rule: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HolidayFile {
+    program: String,
+    date: String,
+    start: Option<String>,
+    end: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    program: String,
+    weekday: String,
+    start: String,
+    end: String,
+    days: Option<(u32, u32)>,
+}
+
+static BUILTIN: Lazy<SpecialtySchedule> = Lazy::new(|| {
+    // Generated by build.rs from specialty.toml.
+    let file = include!(concat!(env!("OUT_DIR"), "/specialty.rs"));
+    SpecialtySchedule::from_file(file)
+        .expect("built-in specialty schedule is valid")
+});
+
+static SCHEDULE: Mutex<Option<SpecialtySchedule>> = Mutex::new(None);
+
+impl SpecialtySchedule {
+    /// Returns the schedule embedded in the crate.
+    pub fn builtin() -> Self {
+        BUILTIN.clone()
+    }
+
+    /// Parses a schedule from TOML in the format shown above.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(input: &str) -> io::Result<Self> {
+        SpecialtySchedule::from_file(toml::from_str(input).map_err(invalid)?)
+    }
+
+    fn from_file(file: ScheduleFile) -> io::Result<Self> {
+        let holidays = file
+            .holiday
+            .into_iter()
+            .map(Holiday::parse)
+            .collect::<io::Result<_>>()?;
+        let rules = file
+            .rule
+            .into_iter()
+            .map(Rule::parse)
+            .collect::<io::Result<_>>()?;
+        Ok(SpecialtySchedule { holidays, rules })
+    }
+
+    /// Returns the holiday or specialty program airing at `time`, if any.
+    pub fn program_at<Tz: TimeZone>(
+        &self,
+        time: &DateTime<Tz>,
+    ) -> Option<Program> {
+        let time = time.with_timezone(&Eastern);
+        let minute = time.hour() * 60 + time.minute();
+        let holiday = self.holidays.iter().find(|holiday| {
+            holiday.year.is_none_or(|year| year == time.year())
+                && holiday.month == time.month()
+                && holiday.day == time.day()
+                && holiday.start <= minute
+                && minute < holiday.end
+        });
+        if let Some(holiday) = holiday {
+            return Some(holiday.program.clone());
+        }
+        self.rule_at(time.weekday(), time.day(), minute)
+    }
+
+    /// Returns the specialty program airing on `weekday` at `minute` past
+    /// midnight when it is day `day` of the month, ignoring holidays.
+    fn rule_at(
+        &self,
+        weekday: Weekday,
+        day: u32,
+        minute: u32,
+    ) -> Option<Program> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.weekday == weekday
+                    && rule.start <= minute
+                    && minute < rule.end
+                    && rule.days.is_none_or(|(first, last)| {
+                        (first..=last).contains(&day)
+                    })
+            })
+            .map(|rule| rule.program.clone())
+    }
+}
+
+impl Holiday {
+    fn parse(holiday: HolidayFile) -> io::Result<Self> {
+        let err = || invalid(format!("invalid date {:?}", holiday.date));
+        let (year, month_day) = match holiday.date.len() {
+            5 => (None, holiday.date.as_str()),
+            10 => {
+                let year = holiday.date.get(..4).ok_or_else(err)?;
+                let year = year.parse().map_err(|_| err())?;
+                (Some(year), holiday.date.get(5..).ok_or_else(err)?)
+            }
+            _ => return Err(err()),
+        };
+        // Check the date in a leap year so that "02-29" is allowed.
+        let date = NaiveDate::parse_from_str(
+            &format!("{}-{}", year.unwrap_or(2000), month_day),
+            "%Y-%m-%d",
+        )
+        .map_err(|_| err())?;
+        let start = parse_minute(holiday.start.as_deref().unwrap_or("00:00"))?;
+        let end = parse_minute(holiday.end.as_deref().unwrap_or("24:00"))?;
+        check_interval(&holiday.program, start, end)?;
+        Ok(Holiday {
+            program: Program::from_name(&holiday.program),
+            year,
+            month: date.month(),
+            day: date.day(),
+            start,
+            end,
+        })
+    }
+}
+
+impl Rule {
+    fn parse(rule: RuleFile) -> io::Result<Self> {
+        let weekday = rule.weekday.parse().map_err(|_| {
+            invalid(format!("invalid weekday {:?}", rule.weekday))
+        })?;
+        let start = parse_minute(&rule.start)?;
+        let end = parse_minute(&rule.end)?;
+        check_interval(&rule.program, start, end)?;
+        if let Some((first, last)) = rule.days {
+            if first < 1 || last > 31 || first > last {
+                return Err(invalid(format!(
+                    "invalid days {}..={} for {:?}",
+                    first, last, rule.program
+                )));
+            }
+        }
+        Ok(Rule {
+            program: Program::from_name(&rule.program),
+            weekday,
+            start,
+            end,
+            days: rule.days,
+        })
+    }
+}
+
+/// Parses an "HH:MM" time into minutes since midnight, allowing "24:00".
+fn parse_minute(s: &str) -> io::Result<u32> {
+    let err = || invalid(format!("invalid time {:?}", s));
+    let (h, m) = s.split_once(':').ok_or_else(err)?;
+    if h.len() != 2 || m.len() != 2 {
+        return Err(err());
+    }
+    let h: u32 = h.parse().map_err(|_| err())?;
+    let m: u32 = m.parse().map_err(|_| err())?;
+    match (h, m) {
+        (0..=23, 0..=59) | (24, 0) => Ok(h * 60 + m),
+        _ => Err(err()),
+    }
+}
+
+fn check_interval(program: &str, start: u32, end: u32) -> io::Result<()> {
+    if start < end {
+        Ok(())
+    } else {
+        Err(invalid(format!("{:?} must start before it ends", program)))
+    }
+}
+
+fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Sets the specialty schedule used to determine programs for the rest of the
+/// process. Passing `None` goes back to the built-in schedule.
+pub fn set_specialty_schedule(schedule: Option<SpecialtySchedule>) {
+    *SCHEDULE.lock().unwrap() = schedule;
+}
+
+/// Returns the holiday or specialty program airing at `time` according to the
+/// current schedule, if any.
+pub(crate) fn program_at<Tz: TimeZone>(time: &DateTime<Tz>) -> Option<Program> {
+    match SCHEDULE.lock().unwrap().as_ref() {
+        Some(schedule) => schedule.program_at(time),
+        None => BUILTIN.program_at(time),
+    }
+}
+
+/// Returns the specialty program airing on `weekday` at `minute` past midnight
+/// when it is day `day` of the month according to the current schedule,
+/// ignoring holidays.
+pub(crate) fn rule_at(
+    weekday: Weekday,
+    day: u32,
+    minute: u32,
+) -> Option<Program> {
+    match SCHEDULE.lock().unwrap().as_ref() {
+        Some(schedule) => schedule.rule_at(weekday, day, minute),
+        None => BUILTIN.rule_at(weekday, day, minute),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        let schedule = SpecialtySchedule::builtin();
+        let at = |d, h, m| {
+            schedule.program_at(&Eastern.ymd(2020, 9, d).and_hms(h, m, 0))
+        };
+        assert_eq!(Some(Program::MyLifeInMusic), at(7, 19, 0));
+        assert_eq!(Some(Program::RenaissanceFare), at(14, 19, 59));
+        assert_eq!(None, at(21, 19, 0));
+        assert_eq!(Some(Program::MondayNightAtTheSymphony), at(21, 20, 0));
+        assert_eq!(None, at(6, 7, 29));
+        assert_eq!(Some(Program::SingForJoy), at(6, 7, 30));
+        assert_eq!(Some(Program::MyLifeInMusic), at(13, 17, 0));
+        assert_eq!(Some(Program::PeacefulReflections), at(6, 23, 59));
+        assert_eq!(None, at(8, 20, 0));
+        let christmas = Eastern.ymd(2020, 12, 25).and_hms(9, 0, 0);
+        assert_eq!(
+            Some(Program::Special("Christmas Day".to_owned())),
+            schedule.program_at(&christmas)
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_builtin_matches_toml() {
+        let schedule =
+            SpecialtySchedule::from_toml(include_str!("specialty.toml"));
+        assert_eq!(SpecialtySchedule::builtin(), schedule.unwrap());
+    }
+
+    #[test]
+    fn test_holidays() {
+        let schedule = SpecialtySchedule::from_toml(
+            r#"
+            [[holiday]]
+            program = "Thanksgiving"
+            date = "2020-11-26"
+
+            [[holiday]]
+            program = "Concert Hall"
+            date = "12-24"
+            start = "15:00"
+            end = "17:00"
+
+            [[rule]]
+            program = "Thursday Night Opera House"
+            weekday = "Thu"
+            start = "19:00"
+            end = "22:00"
+            "#,
+        )
+        .unwrap();
+        let at = |y, m, d, h| {
+            schedule.program_at(&Eastern.ymd(y, m, d).and_hms(h, 0, 0))
+        };
+        let thanksgiving = Program::Special("Thanksgiving".to_owned());
+        assert_eq!(Some(thanksgiving.clone()), at(2020, 11, 26, 0));
+        assert_eq!(Some(thanksgiving), at(2020, 11, 26, 19));
+        assert_eq!(
+            Some(Program::ThursdayNightOperaHouse),
+            at(2021, 11, 25, 19)
+        );
+        assert_eq!(None, at(2021, 11, 25, 12));
+        assert_eq!(Some(Program::ConcertHall), at(2021, 12, 24, 15));
+        assert_eq!(Some(Program::ConcertHall), at(2030, 12, 24, 16));
+        assert_eq!(None, at(2030, 12, 24, 17));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let schedule = SpecialtySchedule::from_toml(
+            r#"
+            [[rule]]
+            program = "Test Program"
+            weekday = "Tuesday"
+            start = "09:15"
+            end = "10:00"
+            "#,
+        )
+        .unwrap();
+        let at = |h, m| {
+            schedule.program_at(&Eastern.ymd(2020, 9, 1).and_hms(h, m, 0))
+        };
+        assert_eq!(None, at(9, 14));
+        assert_eq!(
+            Some(Program::Special("Test Program".to_owned())),
+            at(9, 15)
+        );
+        assert_eq!(None, at(10, 0));
+    }
+
+    #[test]
+    fn test_from_toml_invalid() {
+        let rule = |weekday, start, end, days| {
+            format!(
+                "[[rule]]\nprogram = \"X\"\nweekday = \"{}\"\nstart = \"{}\"\nend = \"{}\"\n{}",
+                weekday, start, end, days
+            )
+        };
+        let holiday = |date| {
+            format!("[[holiday]]\nprogram = \"X\"\ndate = \"{}\"\n", date)
+        };
+        for input in &[
+            rule("Mon", "19:00", "20:00", "days = [1, 7]"),
+            holiday("02-29"),
+            holiday("2020-02-29"),
+            "".to_owned(),
+        ] {
+            assert!(SpecialtySchedule::from_toml(input).is_ok(), "{}", input);
+        }
+        for input in &[
+            rule("Someday", "19:00", "20:00", ""),
+            rule("Mon", "7:00", "20:00", ""),
+            rule("Mon", "19:00", "24:01", ""),
+            rule("Mon", "20:00", "19:00", ""),
+            rule("Mon", "19:00", "20:00", "days = [8, 7]"),
+            rule("Mon", "19:00", "20:00", "days = [0, 7]"),
+            rule("Mon", "19:00", "20:00", "color = \"red\""),
+            holiday("12-32"),
+            holiday("2021-02-29"),
+            holiday("12/25"),
+            holiday("é2020-12-25"),
+        ] {
+            assert!(SpecialtySchedule::from_toml(input).is_err(), "{}", input);
+        }
+    }
+}
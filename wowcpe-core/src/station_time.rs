@@ -0,0 +1,175 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Times in the station's time zone.
+
+use {
+    crate::Eastern,
+    chrono::{
+        DateTime, Duration, FixedOffset, Local, NaiveDate, TimeZone, Utc,
+    },
+    std::{
+        cmp::Ordering,
+        fmt,
+        ops::{Add, Deref, Sub},
+    },
+};
+
+/// A moment in time, viewed in the station's time zone (US Eastern).
+///
+/// The station's day, which decides which playlist page a time belongs to, is
+/// the date in Eastern time. Taking the date of a time in some other zone is
+/// an easy way to end up a day off, so the API uses `StationTime` wherever
+/// the Eastern interpretation matters, such as `playlist_url` and the times
+/// of an `Entry`. Convert from any `DateTime` with `From`, and back with
+/// `with_timezone` or `From`. It dereferences to the underlying `DateTime` in
+/// Eastern time for everything else, like formatting.
+///
+/// Comparisons with a `DateTime` in any time zone compare the moments in time.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct StationTime(DateTime<Eastern>);
+
+impl StationTime {
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Utc::now().into()
+    }
+
+    /// Returns the station's date at this time.
+    pub fn date(&self) -> NaiveDate {
+        self.0.date_naive()
+    }
+
+    /// Converts to a `DateTime` in the time zone `tz`.
+    pub fn with_timezone<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+        self.0.with_timezone(tz)
+    }
+
+    /// Converts to a `DateTime` in the local time zone.
+    pub fn to_local(&self) -> DateTime<Local> {
+        self.with_timezone(&Local)
+    }
+
+    /// Converts to a `DateTime` in UTC.
+    pub fn to_utc(&self) -> DateTime<Utc> {
+        self.with_timezone(&Utc)
+    }
+}
+
+impl Deref for StationTime {
+    type Target = DateTime<Eastern>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<Tz: TimeZone> From<DateTime<Tz>> for StationTime {
+    fn from(time: DateTime<Tz>) -> Self {
+        StationTime(time.with_timezone(&Eastern))
+    }
+}
+
+impl From<StationTime> for DateTime<Eastern> {
+    fn from(time: StationTime) -> Self {
+        time.0
+    }
+}
+
+impl From<StationTime> for DateTime<Utc> {
+    fn from(time: StationTime) -> Self {
+        time.to_utc()
+    }
+}
+
+impl From<StationTime> for DateTime<Local> {
+    fn from(time: StationTime) -> Self {
+        time.to_local()
+    }
+}
+
+impl From<StationTime> for DateTime<FixedOffset> {
+    fn from(time: StationTime) -> Self {
+        time.0.fixed_offset()
+    }
+}
+
+impl<Tz: TimeZone> PartialEq<DateTime<Tz>> for StationTime {
+    fn eq(&self, other: &DateTime<Tz>) -> bool {
+        self.0 == *other
+    }
+}
+
+impl<Tz: TimeZone> PartialEq<StationTime> for DateTime<Tz> {
+    fn eq(&self, other: &StationTime) -> bool {
+        *self == other.0
+    }
+}
+
+impl<Tz: TimeZone> PartialOrd<DateTime<Tz>> for StationTime {
+    fn partial_cmp(&self, other: &DateTime<Tz>) -> Option<Ordering> {
+        self.0.naive_utc().partial_cmp(&other.naive_utc())
+    }
+}
+
+impl<Tz: TimeZone> PartialOrd<StationTime> for DateTime<Tz> {
+    fn partial_cmp(&self, other: &StationTime) -> Option<Ordering> {
+        self.naive_utc().partial_cmp(&other.0.naive_utc())
+    }
+}
+
+impl Add<Duration> for StationTime {
+    type Output = StationTime;
+
+    fn add(self, duration: Duration) -> Self {
+        StationTime(self.0 + duration)
+    }
+}
+
+impl Sub<Duration> for StationTime {
+    type Output = StationTime;
+
+    fn sub(self, duration: Duration) -> Self {
+        StationTime(self.0 - duration)
+    }
+}
+
+impl Sub for StationTime {
+    type Output = Duration;
+
+    fn sub(self, other: Self) -> Duration {
+        self.0 - other.0
+    }
+}
+
+impl fmt::Display for StationTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date() {
+        // 11pm Eastern is already the next day in UTC.
+        let utc = Utc.ymd(2020, 9, 2).and_hms(3, 0, 0);
+        let time = StationTime::from(utc);
+        assert_eq!(NaiveDate::from_ymd(2020, 9, 1), time.date());
+        assert_eq!(NaiveDate::from_ymd(2020, 9, 2), utc.date_naive());
+        assert_eq!(utc, time.to_utc());
+        assert_eq!(Eastern.ymd(2020, 9, 1).and_hms(23, 0, 0), time);
+    }
+
+    #[test]
+    fn test_compare() {
+        let time = StationTime::from(Eastern.ymd(2020, 9, 1).and_hms(12, 0, 0));
+        let later = Utc.ymd(2020, 9, 1).and_hms(17, 0, 0);
+        assert!(time < later);
+        assert!(later > time);
+        assert_eq!(StationTime::from(later), time + Duration::hours(1));
+        assert_eq!(Duration::hours(1), StationTime::from(later) - time);
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Summarizing many playlist entries, e.g., to find the most played composers.
+
+use {
+    crate::{Entry, Work},
+    chrono::{DateTime, Duration, FixedOffset, TimeZone},
+    serde::Serialize,
+    std::{collections::HashMap, fmt},
+};
+
+/// A summary of playlist entries: how often each composer, record label, work,
+/// and program came up. Each list is sorted with the most played first.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Stats {
+    /// Number of entries summarized.
+    pub plays: usize,
+    /// Plays of each composer.
+    pub composers: Vec<Count>,
+    /// Plays of each record label.
+    pub labels: Vec<Count>,
+    /// Works played more than once, named like "Composer: Title" after the
+    /// first title seen. Titles that differ only in formatting, e.g., "Op. 3
+    /// No. 6" and "Opus 3, No. 6", count as the same work (see
+    /// `Work::same_work`).
+    pub works: Vec<Count>,
+    /// Plays in each program.
+    pub programs: Vec<Count>,
+}
+
+/// A name and the number of plays it had, in `Stats`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Count {
+    /// The composer, label, work, or program.
+    pub name: String,
+    /// The number of plays.
+    pub count: usize,
+}
+
+/// A work played more than once within a window of time, from `repeats`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Repeat {
+    /// The work, named like "Composer: Title" after the first title seen, as
+    /// in `Stats::works`.
+    pub name: String,
+    /// When it played, earliest first.
+    pub plays: Vec<Play>,
+}
+
+/// One play of a work, in `Repeat`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Play {
+    /// Time the piece started playing.
+    pub start_time: DateTime<FixedOffset>,
+    /// The program it played in, e.g., Classical Café.
+    pub program: String,
+}
+
+impl Play {
+    fn new(entry: &Entry) -> Self {
+        Play {
+            start_time: entry.start_time.into(),
+            program: entry.program.name().to_string(),
+        }
+    }
+}
+
+/// Formats the play like "2024-11-03 on Classical Café", using the date at
+/// the station.
+impl fmt::Display for Play {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}",
+            self.start_time.format("%Y-%m-%d"),
+            self.program
+        )
+    }
+}
+
+impl Stats {
+    /// Summarizes `entries`. Fields that are missing are left out.
+    pub fn new<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Self {
+        let entries: Vec<&Entry> = entries.into_iter().collect();
+        let composers = entries.iter().filter_map(|e| e.composer.clone());
+        let labels = entries.iter().filter_map(|e| e.record_label.clone());
+        let programs = entries.iter().map(|e| e.program.name().to_string());
+        Stats {
+            plays: entries.len(),
+            composers: tally(composers),
+            labels: tally(labels),
+            works: repeated_works(&entries),
+            programs: tally(programs),
+        }
+    }
+
+    /// Keeps only the first `n` items of each list.
+    pub fn truncate(&mut self, n: usize) {
+        self.composers.truncate(n);
+        self.labels.truncate(n);
+        self.works.truncate(n);
+        self.programs.truncate(n);
+    }
+}
+
+fn tally(names: impl Iterator<Item = String>) -> Vec<Count> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+    sorted(counts.into_iter().collect())
+}
+
+fn repeated_works(entries: &[&Entry]) -> Vec<Count> {
+    sorted(
+        group_works(entries)
+            .into_iter()
+            .filter(|(_, plays)| plays.len() > 1)
+            .map(|(name, plays)| (name, plays.len()))
+            .collect(),
+    )
+}
+
+/// Finds works that played again within `window` of their last play, e.g.,
+/// with a window of 7 days, what the station repeated within a week. Plays are
+/// chained, so a work played every 5 days comes up once with all its plays.
+/// Works are matched as in `Stats::works`. The result is sorted with the most
+/// played first, and then by name. Returns nothing if `window` is not
+/// positive, since then nothing could be a repeat.
+pub fn repeats<'a>(
+    entries: impl IntoIterator<Item = &'a Entry>,
+    window: Duration,
+) -> Vec<Repeat> {
+    if window <= Duration::zero() {
+        return Vec::new();
+    }
+    let entries: Vec<&Entry> = entries.into_iter().collect();
+    let mut repeats = Vec::new();
+    for (name, mut plays) in group_works(&entries) {
+        plays.sort_by_key(|entry| entry.start_time);
+        let mut run: Vec<&Entry> = Vec::new();
+        for entry in plays {
+            if let Some(last) = run.last() {
+                if entry.start_time - last.start_time > window {
+                    push_repeat(&mut repeats, &name, &run);
+                    run.clear();
+                }
+            }
+            run.push(entry);
+        }
+        push_repeat(&mut repeats, &name, &run);
+    }
+    repeats.sort_by(|a, b| {
+        (b.plays.len().cmp(&a.plays.len()))
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.plays[0].start_time.cmp(&b.plays[0].start_time))
+    });
+    repeats
+}
+
+/// Finds the most recent play of the work `title` by `composer` in `entries`
+/// that started before `before`, e.g., to say when the piece playing now last
+/// played. Works are matched as in `Stats::works`.
+pub fn last_played<'a, Tz: TimeZone>(
+    composer: &str,
+    title: &str,
+    before: &DateTime<Tz>,
+    entries: impl IntoIterator<Item = &'a Entry>,
+) -> Option<Play> {
+    let work = Work::parse(title);
+    entries
+        .into_iter()
+        .filter(|entry| entry.start_time < *before)
+        .filter(|entry| entry.composer.as_deref() == Some(composer))
+        .filter(|entry| match &entry.title {
+            Some(title) => Work::parse(title).same_work(&work),
+            None => false,
+        })
+        .max_by_key(|entry| entry.start_time)
+        .map(Play::new)
+}
+
+fn push_repeat(repeats: &mut Vec<Repeat>, name: &str, run: &[&Entry]) {
+    if run.len() > 1 {
+        repeats.push(Repeat {
+            name: name.to_string(),
+            plays: run.iter().map(|entry| Play::new(entry)).collect(),
+        });
+    }
+}
+
+/// Groups `entries` by work, naming each like "Composer: Title" after the
+/// first title seen. Entries without a composer and title are left out.
+fn group_works<'a>(entries: &[&'a Entry]) -> Vec<(String, Vec<&'a Entry>)> {
+    // Works can't be hashed, since `same_work` is not an equivalence relation.
+    let mut works: Vec<(&str, Work, String, Vec<&Entry>)> = Vec::new();
+    for entry in entries {
+        let (composer, title) = match (&entry.composer, &entry.title) {
+            (Some(composer), Some(title)) => (composer.as_str(), title),
+            _ => continue,
+        };
+        let work = Work::parse(title);
+        match works
+            .iter_mut()
+            .find(|(c, w, _, _)| *c == composer && w.same_work(&work))
+        {
+            Some((_, _, _, plays)) => plays.push(entry),
+            None => works.push((composer, work, title.clone(), vec![entry])),
+        }
+    }
+    works
+        .into_iter()
+        .map(|(composer, _, title, plays)| {
+            (format!("{}: {}", composer, title), plays)
+        })
+        .collect()
+}
+
+/// Sorts by count, most first, and then by name.
+fn sorted(mut counts: Vec<(String, usize)>) -> Vec<Count> {
+    counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+    counts
+        .into_iter()
+        .map(|(name, count)| Count { name, count })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{tests::HTML, Playlist, StationTime},
+        chrono::NaiveDate,
+    };
+
+    fn count(name: &str, count: usize) -> Count {
+        Count {
+            name: name.to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let mut entries = playlist.entries().to_vec();
+        let mut repeat = entries[1].clone();
+        repeat.title = Some("Concerto Grosso in D, Opus 3, No. 6".to_string());
+        repeat.record_label = None;
+        entries.push(repeat);
+
+        let stats = Stats::new(&entries);
+        assert_eq!(3, stats.plays);
+        assert_eq!(
+            vec![count("George Frideric Handel", 2), count("Franz Liszt", 1)],
+            stats.composers
+        );
+        // The repeat has no record label.
+        assert_eq!(2, stats.labels.iter().map(|c| c.count).sum::<usize>());
+        assert_eq!(
+            vec![count(
+                "George Frideric Handel: Concerto Grosso in D, Op. 3 No. 6",
+                2
+            )],
+            stats.works
+        );
+        assert_eq!(
+            vec![count("Rise and Shine", 2), count("Sleepers, Awake!", 1)],
+            stats.programs
+        );
+    }
+
+    #[test]
+    fn test_repeats() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let handel = &playlist.entries()[1];
+        let later = |days: i64| {
+            let mut entry = handel.clone();
+            entry.start_time = entry.start_time + Duration::days(days);
+            entry
+        };
+        // Played on days 0, 3, and 5, and then again on day 20.
+        let mut entries = playlist.entries().to_vec();
+        entries.extend(vec![later(20), later(5), later(3)]);
+
+        let found = repeats(&entries, Duration::days(7));
+        assert_eq!(1, found.len());
+        assert_eq!(
+            "George Frideric Handel: Concerto Grosso in D, Op. 3 No. 6",
+            found[0].name
+        );
+        let times: Vec<StationTime> = found[0]
+            .plays
+            .iter()
+            .map(|play| play.start_time.into())
+            .collect();
+        assert_eq!(
+            vec![handel.start_time, later(3).start_time, later(5).start_time],
+            times
+        );
+        assert_eq!("Rise and Shine", found[0].plays[0].program);
+
+        assert!(repeats(&entries, Duration::days(1)).is_empty());
+        assert_eq!(4, repeats(&entries, Duration::days(15))[0].plays.len());
+        assert!(repeats(&entries, Duration::zero()).is_empty());
+        assert!(repeats(&entries, -Duration::days(7)).is_empty());
+    }
+
+    #[test]
+    fn test_last_played() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let handel = &playlist.entries()[1];
+        let (composer, title) = (
+            handel.composer.as_deref().unwrap(),
+            "Concerto Grosso in D, Opus 3, No. 6",
+        );
+        let mut entries = playlist.entries().to_vec();
+        let mut earlier = handel.clone();
+        earlier.start_time = earlier.start_time - Duration::days(3);
+        earlier.program = entries[0].program.clone();
+        entries.push(earlier);
+
+        let now = *handel.start_time + Duration::days(7);
+        let play = last_played(composer, title, &now, &entries).unwrap();
+        assert_eq!(handel.start_time, play.start_time);
+        assert_eq!("2020-09-01 on Rise and Shine", play.to_string());
+
+        let play = last_played(composer, title, &*handel.start_time, &entries);
+        assert_eq!("2020-08-29 on Sleepers, Awake!", play.unwrap().to_string());
+        assert_eq!(None, last_played("Bach", title, &now, &entries));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let playlist =
+            Playlist::parse(HTML, NaiveDate::from_ymd(2020, 9, 1)).unwrap();
+        let mut stats = Stats::new(playlist.entries());
+        stats.truncate(1);
+        assert_eq!(1, stats.composers.len());
+        assert_eq!(2, stats.plays);
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! The station's public audio streams.
+
+use serde::Serialize;
+
+/// One of WCPE's public audio streams.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct Stream {
+    /// A short name for choosing the stream, e.g., "mp3".
+    pub id: &'static str,
+    /// The audio format, e.g., "MP3".
+    pub format: &'static str,
+    /// The bitrate in kilobits per second.
+    pub bitrate: u32,
+    /// The URL to play.
+    pub url: &'static str,
+}
+
+/// WCPE's public streams, with the default first.
+pub const STREAMS: &[Stream] = &[
+    Stream {
+        id: "mp3",
+        format: "MP3",
+        bitrate: 128,
+        url: "http://audio-mp3.ibiblio.org:8000/wcpe.mp3",
+    },
+    Stream {
+        id: "ogg",
+        format: "Ogg Vorbis",
+        bitrate: 128,
+        url: "http://audio-ogg.ibiblio.org:8000/wcpe.ogg",
+    },
+];
+
+impl Stream {
+    /// Returns the stream with `id`, ignoring case.
+    pub fn find(id: &str) -> Option<&'static Stream> {
+        STREAMS
+            .iter()
+            .find(|stream| stream.id.eq_ignore_ascii_case(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(Some(&STREAMS[1]), Stream::find("OGG"));
+        assert_eq!(None, Stream::find("flac"));
+    }
+
+    #[test]
+    fn test_ids_unique() {
+        for (i, stream) in STREAMS.iter().enumerate() {
+            assert_eq!(Some(stream), Stream::find(stream.id), "{}", i);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Formatting responses with templates supplied by the user.
+
+use {
+    crate::{relative_time, Response},
+    chrono::{DateTime, TimeZone},
+    std::{error, fmt, str::FromStr},
+};
+
+/// A template for formatting a `Response` on one line, e.g., for a status bar.
+///
+/// Parse one from a string like `"{composer} – {title} ({remaining}m left)"`.
+/// These placeholders are replaced by the fields of the response:
+///
+/// | Placeholder       | Replaced by                                        |
+/// |-------------------|----------------------------------------------------|
+/// | `{program}`       | The program name, e.g., Sleepers, Awake!           |
+/// | `{composer}`      | The composer                                       |
+/// | `{title}`         | The title                                          |
+/// | `{performers}`    | The performers                                     |
+/// | `{record_label}`  | The record label                                   |
+/// | `{start}`         | The time the piece started, e.g., 7:30 PM          |
+/// | `{end}`           | The time the piece ends                            |
+/// | `{program_start}` | The time the program started                       |
+/// | `{program_end}`   | The time the program ends                          |
+/// | `{elapsed}`       | Whole minutes since the piece started              |
+/// | `{remaining}`     | Minutes until the piece ends, rounded up           |
+/// | `{relative}`      | E.g., started 12 minutes ago · ends in 8 minutes   |
+/// | `{source_url}`    | The URL of the playlist page                       |
+/// | `{fetched_at}`    | When the page was downloaded, in RFC 3339 format   |
+///
+/// Fields missing from the playlist are replaced by nothing. Use `{{` and `}}`
+/// for literal braces.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Field {
+    Program,
+    Composer,
+    Title,
+    Performers,
+    RecordLabel,
+    Start,
+    End,
+    ProgramStart,
+    ProgramEnd,
+    Elapsed,
+    Remaining,
+    Relative,
+    SourceUrl,
+    FetchedAt,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "program" => Field::Program,
+            "composer" => Field::Composer,
+            "title" => Field::Title,
+            "performers" => Field::Performers,
+            "record_label" => Field::RecordLabel,
+            "start" => Field::Start,
+            "end" => Field::End,
+            "program_start" => Field::ProgramStart,
+            "program_end" => Field::ProgramEnd,
+            "elapsed" => Field::Elapsed,
+            "remaining" => Field::Remaining,
+            "relative" => Field::Relative,
+            "source_url" => Field::SourceUrl,
+            "fetched_at" => Field::FetchedAt,
+            _ => return None,
+        })
+    }
+}
+
+impl Template {
+    /// Formats `response`, counting the elapsed and remaining minutes from
+    /// `now`.
+    pub fn render<Tz: TimeZone>(
+        &self,
+        response: &Response<Tz>,
+        now: &DateTime<Tz>,
+    ) -> String
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(field) => {
+                    out.push_str(&render_field(*field, response, now))
+                }
+            }
+        }
+        out
+    }
+}
+
+fn render_field<Tz: TimeZone>(
+    field: Field,
+    r: &Response<Tz>,
+    now: &DateTime<Tz>,
+) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let time = |time: &DateTime<Tz>| {
+        time.format("%l:%M %p").to_string().trim().to_string()
+    };
+    match field {
+        Field::Program => r.program.name().to_string(),
+        Field::Composer => text(&r.composer),
+        Field::Title => text(&r.title),
+        Field::Performers => text(&r.performers),
+        Field::RecordLabel => text(&r.record_label),
+        Field::Start => time(&r.start_time),
+        Field::End => time(&r.end_time),
+        Field::ProgramStart => time(&r.program_start),
+        Field::ProgramEnd => time(&r.program_end),
+        Field::Elapsed => {
+            let seconds = (now.clone() - r.start_time.clone()).num_seconds();
+            (seconds.max(0) / 60).to_string()
+        }
+        Field::Remaining => {
+            let seconds = (r.end_time.clone() - now.clone()).num_seconds();
+            ((seconds.max(0) + 59) / 60).to_string()
+        }
+        Field::Relative => relative_time(&r.start_time, &r.end_time, now),
+        Field::SourceUrl => text(&r.source_url),
+        Field::FetchedAt => {
+            r.fetched_at.map(|t| t.to_rfc3339()).unwrap_or_default()
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = ParseTemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| {
+                        ParseTemplateError("Unclosed '{'".to_string())
+                    })?;
+                    let name = &rest[..end];
+                    let field = Field::from_name(name).ok_or_else(|| {
+                        ParseTemplateError(format!(
+                            "Unknown placeholder {{{}}}",
+                            name
+                        ))
+                    })?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(ParseTemplateError(
+                        "Unmatched '}' (use '}}' for a literal brace)"
+                            .to_string(),
+                    ))
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+}
+
+/// An error returned when parsing a `Template` from a string fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseTemplateError(String);
+
+impl fmt::Display for ParseTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for ParseTemplateError {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{lookup_in_html, tests::HTML, Eastern, Request},
+    };
+
+    fn response() -> Response<Eastern> {
+        let request = Request {
+            time: Eastern.ymd(2020, 9, 1).and_hms(3, 0, 0),
+        };
+        lookup_in_html(&request, HTML).unwrap()
+    }
+
+    fn render(template: &str) -> String {
+        let now = Eastern.ymd(2020, 9, 1).and_hms(3, 0, 30);
+        let template: Template = template.parse().unwrap();
+        template.render(&response(), &now)
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            "Franz Liszt (Sleepers, Awake!) 180m left",
+            render("{composer} ({program}) {remaining}m left")
+        );
+        assert_eq!("12:01 AM-6:00 AM, 179", render("{start}-{end}, {elapsed}"));
+        assert_eq!(
+            "started 2 hours 59 minutes ago · ends in 3 hours",
+            render("{relative}")
+        );
+        assert_eq!("{composer}", render("{{composer}}"));
+        assert_eq!("", render("{source_url}{fetched_at}"));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!("{composer".parse::<Template>().is_err());
+        assert!("composer}".parse::<Template>().is_err());
+        assert_eq!(
+            "Unknown placeholder {composr}",
+            "{composr}".parse::<Template>().unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Diagnostics. These are `tracing` events with the `tracing` feature, and
+//! compile to nothing without it.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, debug_span, warn};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use noop::{debug, debug_span, warn, Span};
+
+#[cfg(not(feature = "tracing"))]
+mod noop {
+    /// Stands in for a `tracing` span.
+    pub(crate) struct Span;
+
+    impl Span {
+        pub(crate) fn entered(self) -> Self {
+            self
+        }
+    }
+
+    macro_rules! noop_debug {
+        ($($arg:tt)*) => {};
+    }
+
+    macro_rules! noop_warn {
+        ($($arg:tt)*) => {};
+    }
+
+    macro_rules! noop_debug_span {
+        ($($arg:tt)*) => {
+            $crate::trace::Span
+        };
+    }
+
+    pub(crate) use {
+        noop_debug as debug, noop_debug_span as debug_span, noop_warn as warn,
+    };
+}
@@ -0,0 +1,51 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Problems that don't prevent a lookup but may make it less accurate.
+
+use {
+    serde::{Deserialize, Serialize},
+    std::fmt,
+};
+
+/// A problem encountered while interpreting a playlist.
+///
+/// Warnings don't cause lookups to fail, but they mean some of the playlist
+/// was dropped or had to be interpreted on assumption, so a response may be
+/// less accurate than usual. They are collected in `Playlist::warnings` and
+/// `Response::warnings` so applications can surface or log them as they see
+/// fit, and are also emitted as `tracing` events.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum Warning {
+    /// An entry's time, e.g. "2:30am", falls in the hour skipped when clocks
+    /// spring forward, so it was assumed to be standard time (3:30am daylight
+    /// time).
+    SkippedTime(String),
+    /// An entry's time, e.g. "1:30am", occurs twice when clocks fall back, and
+    /// with no hour heading or earlier entry to go by, the first occurrence
+    /// was assumed.
+    AmbiguousTime(String),
+    /// An entry was dropped because its time could not be parsed.
+    BadTime(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::SkippedTime(time) => write!(
+                f,
+                "Assumed {} is in standard time, since it does not exist in \
+                 daylight time",
+                time
+            ),
+            Warning::AmbiguousTime(time) => write!(
+                f,
+                "Assumed {} is the first of the two times with that name on \
+                 the night clocks fall back",
+                time
+            ),
+            Warning::BadTime(time) => {
+                write!(f, "Skipped an entry with invalid time {:?}", time)
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2017 Mitchell Kember. Subject to the MIT License.
+
+//! Parsing structure out of piece titles.
+
+use std::fmt;
+
+/// A musical work, parsed from a title like "Concerto Grosso in D, Op. 3 No. 6".
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Work {
+    /// Name of the work without key, catalogue numbers, or nickname, e.g.,
+    /// "Concerto Grosso".
+    pub name: String,
+    /// Key of the work, if given.
+    pub key: Option<Key>,
+    /// Catalogue numbers, in the order they appear in the title.
+    pub catalogue: Vec<CatalogueNumber>,
+    /// Nickname of the work, e.g., "Surprise" for Haydn's Symphony No. 94.
+    pub nickname: Option<String>,
+}
+
+/// A key such as D major or B-flat minor.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Key {
+    /// Tonic of the key, e.g., "D" or "B-flat".
+    pub tonic: String,
+    /// Mode of the key. Titles that omit the mode are assumed to be major.
+    pub mode: Mode,
+}
+
+/// The mode of a key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// A catalogue of musical works.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Catalogue {
+    /// Opus number, written "Op." or "Opus".
+    Opus,
+    /// Bach-Werke-Verzeichnis, written "BWV".
+    Bwv,
+    /// Köchel catalogue of Mozart's works, written "K." or "KV".
+    Kochel,
+    /// Hoboken catalogue of Haydn's works, written "Hob.".
+    Hoboken,
+}
+
+/// A number in one of the catalogues, e.g., "Op. 3 No. 6".
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct CatalogueNumber {
+    /// The catalogue the number belongs to.
+    pub catalogue: Catalogue,
+    /// The number itself, e.g., "3" or "I:94".
+    pub number: String,
+    /// The item within the number, e.g., "6" in "Op. 3 No. 6".
+    pub item: Option<String>,
+}
+
+impl Work {
+    /// Parses a title into a `Work`. This never fails: parts of the title that
+    /// are not recognized are left in `name`.
+    pub fn parse(title: &str) -> Work {
+        let title = title.trim();
+        let mut name_end = title.len();
+
+        let nickname = find_nickname(title).map(|(start, nickname)| {
+            name_end = name_end.min(start);
+            nickname
+        });
+        let key = find_key(title).map(|(start, key)| {
+            name_end = name_end.min(start);
+            key
+        });
+        let catalogue = find_catalogue(title);
+        if let Some((start, _)) = catalogue.first() {
+            name_end = name_end.min(*start);
+        }
+
+        let name = title[..name_end].trim_end_matches(|c: char| {
+            c.is_whitespace() || c == ',' || c == ';' || c == '-'
+        });
+        let name = if name.is_empty() { title } else { name };
+
+        Work {
+            name: name.to_string(),
+            key,
+            catalogue: catalogue.into_iter().map(|(_, n)| n).collect(),
+            nickname,
+        }
+    }
+
+    /// Returns true if `self` and `other` most likely refer to the same work.
+    /// Works sharing a catalogue are compared by catalogue number; otherwise,
+    /// they are compared by name and key, ignoring case.
+    pub fn same_work(&self, other: &Work) -> bool {
+        for a in &self.catalogue {
+            for b in &other.catalogue {
+                if a.catalogue == b.catalogue {
+                    return a == b;
+                }
+            }
+        }
+        self.name.to_lowercase() == other.name.to_lowercase()
+            && self.key == other.key
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Major => write!(f, "{} major", self.tonic),
+            Mode::Minor => write!(f, "{} minor", self.tonic),
+        }
+    }
+}
+
+impl fmt::Display for CatalogueNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.catalogue {
+            Catalogue::Opus => "Op.",
+            Catalogue::Bwv => "BWV",
+            Catalogue::Kochel => "K.",
+            Catalogue::Hoboken => "Hob.",
+        };
+        write!(f, "{} {}", prefix, self.number)?;
+        if let Some(item) = &self.item {
+            write!(f, " No. {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+fn find_nickname(title: &str) -> Option<(usize, String)> {
+    for &(open, close) in &[('"', '"'), ('“', '”')] {
+        if let Some(start) = title.find(open) {
+            let rest = &title[start + open.len_utf8()..];
+            if let Some(len) = rest.find(close) {
+                let nickname = rest[..len].trim();
+                if !nickname.is_empty() {
+                    return Some((start, nickname.to_string()));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn find_key(title: &str) -> Option<(usize, Key)> {
+    let mut offset = 0;
+    while let Some(index) = title[offset..].find(" in ") {
+        let start = offset + index;
+        offset = start + 1;
+        let rest = &title[start + 4..];
+        let mut chars = rest.chars();
+        let letter = match chars.next() {
+            Some(c @ 'A'..='G') => c,
+            _ => continue,
+        };
+        let rest = &rest[1..];
+        let (accidental, rest) = parse_accidental(rest);
+        let (mode, rest) = parse_mode(rest);
+        if !rest.is_empty() && !rest.starts_with(|c: char| !c.is_alphanumeric())
+        {
+            continue;
+        }
+        let tonic = format!("{}{}", letter, accidental);
+        return Some((start, Key { tonic, mode }));
+    }
+    None
+}
+
+fn parse_accidental(input: &str) -> (&'static str, &str) {
+    for &(prefix, accidental) in &[
+        ("-flat", "-flat"),
+        (" flat", "-flat"),
+        ("♭", "-flat"),
+        ("-sharp", "-sharp"),
+        (" sharp", "-sharp"),
+        ("♯", "-sharp"),
+        ("#", "-sharp"),
+    ] {
+        if let Some(rest) = strip_prefix_ignore_case(input, prefix) {
+            return (accidental, rest);
+        }
+    }
+    ("", input)
+}
+
+fn parse_mode(input: &str) -> (Mode, &str) {
+    if let Some(rest) = strip_prefix_ignore_case(input, " minor") {
+        (Mode::Minor, rest)
+    } else if let Some(rest) = strip_prefix_ignore_case(input, " major") {
+        (Mode::Major, rest)
+    } else {
+        (Mode::Major, input)
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(
+    input: &'a str,
+    prefix: &str,
+) -> Option<&'a str> {
+    let head = input.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&input[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn find_catalogue(title: &str) -> Vec<(usize, CatalogueNumber)> {
+    let words = split_words(title);
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let (start, word) = words[i];
+        let catalogue = match word.trim_end_matches('.').to_lowercase().as_str()
+        {
+            "op" | "opus" => Catalogue::Opus,
+            "bwv" => Catalogue::Bwv,
+            "k" | "kv" => Catalogue::Kochel,
+            "hob" => Catalogue::Hoboken,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let number = match words.get(i + 1) {
+            Some((_, n)) if n.starts_with(|c: char| c.is_ascii_digit()) => n,
+            Some((_, n))
+                if catalogue == Catalogue::Hoboken && n.contains(':') =>
+            {
+                n
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        i += 2;
+        let item = match (words.get(i), words.get(i + 1)) {
+            (Some((_, no)), Some((_, n)))
+                if no.trim_end_matches('.').eq_ignore_ascii_case("no")
+                    && n.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                i += 2;
+                Some(n.to_string())
+            }
+            _ => None,
+        };
+        result.push((
+            start,
+            CatalogueNumber {
+                catalogue,
+                number: number.to_string(),
+                item,
+            },
+        ));
+    }
+    result
+}
+
+/// Splits `input` on whitespace and punctuation that separates catalogue
+/// numbers, returning each word with its byte offset.
+fn split_words(input: &str) -> Vec<(usize, &str)> {
+    let is_separator = |c: char| {
+        c.is_whitespace() || c == ',' || c == ';' || c == '(' || c == ')'
+    };
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in input.char_indices() {
+        match (start, is_separator(c)) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push((s, &input[s..i]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &input[s..]));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(
+        catalogue: Catalogue,
+        number: &str,
+        item: Option<&str>,
+    ) -> CatalogueNumber {
+        CatalogueNumber {
+            catalogue,
+            number: number.to_string(),
+            item: item.map(str::to_string),
+        }
+    }
+
+    fn key(tonic: &str, mode: Mode) -> Option<Key> {
+        Some(Key {
+            tonic: tonic.to_string(),
+            mode,
+        })
+    }
+
+    #[test]
+    fn test_parse_plain() {
+        let work =
+            Work::parse("Tasso: Lament & Trimuph (Symphonic Poem No. 2)");
+        assert_eq!("Tasso: Lament & Trimuph (Symphonic Poem No. 2)", work.name);
+        assert_eq!(None, work.key);
+        assert!(work.catalogue.is_empty());
+        assert_eq!(None, work.nickname);
+    }
+
+    #[test]
+    fn test_parse_opus() {
+        let work = Work::parse("Concerto Grosso in D, Op. 3 No. 6");
+        assert_eq!("Concerto Grosso", work.name);
+        assert_eq!(key("D", Mode::Major), work.key);
+        assert_eq!(vec![num(Catalogue::Opus, "3", Some("6"))], work.catalogue);
+    }
+
+    #[test]
+    fn test_parse_minor_key() {
+        let work = Work::parse("Symphony No. 5 in C minor, Op. 67");
+        assert_eq!("Symphony No. 5", work.name);
+        assert_eq!(key("C", Mode::Minor), work.key);
+        assert_eq!(vec![num(Catalogue::Opus, "67", None)], work.catalogue);
+    }
+
+    #[test]
+    fn test_parse_accidentals() {
+        let work = Work::parse("Piano Sonata in B-flat Major, K. 333");
+        assert_eq!(key("B-flat", Mode::Major), work.key);
+        assert_eq!(vec![num(Catalogue::Kochel, "333", None)], work.catalogue);
+
+        let work = Work::parse("Prelude in F sharp minor");
+        assert_eq!(key("F-sharp", Mode::Minor), work.key);
+    }
+
+    #[test]
+    fn test_parse_bwv() {
+        let work = Work::parse("Brandenburg Concerto No. 3 in G, BWV 1048");
+        assert_eq!("Brandenburg Concerto No. 3", work.name);
+        assert_eq!(vec![num(Catalogue::Bwv, "1048", None)], work.catalogue);
+    }
+
+    #[test]
+    fn test_parse_nickname() {
+        let work = Work::parse("Symphony No. 94 in G, Hob. I:94 \"Surprise\"");
+        assert_eq!("Symphony No. 94", work.name);
+        assert_eq!(vec![num(Catalogue::Hoboken, "I:94", None)], work.catalogue);
+        assert_eq!(Some("Surprise".to_string()), work.nickname);
+    }
+
+    #[test]
+    fn test_parse_not_a_key() {
+        let work = Work::parse("Dances in Every Key");
+        assert_eq!("Dances in Every Key", work.name);
+        assert_eq!(None, work.key);
+    }
+
+    #[test]
+    fn test_same_work() {
+        let a = Work::parse("Concerto Grosso in D, Op. 3 No. 6");
+        let b = Work::parse("Concerto grosso, Op. 3 No. 6");
+        let c = Work::parse("Concerto Grosso in D, Op. 3 No. 5");
+        assert!(a.same_work(&b));
+        assert!(!a.same_work(&c));
+    }
+}
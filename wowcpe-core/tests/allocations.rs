@@ -0,0 +1,80 @@
+use {
+    chrono::NaiveDate,
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Returns a playlist page with an entry every 5 minutes, like a busy day.
+fn page() -> String {
+    let mut html = String::from(r#"<article class="block block--playlist">"#);
+    for i in 0..288 {
+        let (h, m) = (i / 12, i % 12 * 5);
+        if m == 0 {
+            html += &format!(
+                r#"<h3 class="playlist-hour">{}{}</h3>"#,
+                (h + 11) % 12 + 1,
+                if h < 12 { "am" } else { "pm" }
+            );
+        }
+        html += &format!(
+            r#"<div class="playlist-song">
+                <div class="playlist-song__time">{}:{:02}{}</div>
+                <h4 class="playlist-song__title">Symphony No. {} in D &amp; E</h4>
+                <ul class="playlist-song__meta">
+                    <li>Composed by: Joseph Haydn</li>
+                    <li>Performed by: Orchestra/Conductor</li>
+                    <li>Label: Naxos</li>
+                    <li class="playlist-song__meta-half">Catalog Number: 01234</li>
+                </ul>
+            </div>"#,
+            (h + 11) % 12 + 1,
+            m,
+            if h < 12 { "am" } else { "pm" },
+            i
+        );
+    }
+    html + "</article>"
+}
+
+/// Counts the allocations made by `f`. The counter is global, so this file
+/// must contain only one test.
+fn allocations<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let result = f();
+    (ALLOCATIONS.load(Ordering::SeqCst) - before, result)
+}
+
+#[test]
+fn test_parse_allocations() {
+    let html = page();
+    let date = NaiveDate::from_ymd(2020, 9, 1);
+    let (tree, _) = allocations(|| scraper::Html::parse_fragment(&html));
+    let (total, entries) =
+        allocations(|| wowcpe_core::parse_playlist(&html, date).unwrap());
+
+    assert_eq!(288, entries.len());
+    // Building the document tree dominates. Extracting each entry should only
+    // allocate its fields, not intermediate strings.
+    let per_entry = (total - tree) / entries.len();
+    assert!(per_entry <= 16, "{} allocations per entry", per_entry);
+}